@@ -1,9 +1,22 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+mod protocol;
+mod story;
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .manage(story::StoryState::default())
+        .register_uri_scheme_protocol(protocol::SCHEME, |ctx, request| {
+            protocol::handle(ctx.app_handle(), request)
+        })
+        .invoke_handler(tauri::generate_handler![
+            story::open_story,
+            story::load_node,
+            story::choose,
+            story::save_game,
+            story::load_game,
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
-
@@ -0,0 +1,218 @@
+//! `story://` URI scheme.
+//!
+//! Serves `.story` and `.storypatch` files from the app's resource
+//! directory and from `<app data>/stories`, with real HTTP Range
+//! semantics. WebViews differ in whether bundled assets answer `Range`
+//! headers; going through this scheme lets the wasm decoder take the same
+//! ranged path on every platform.
+//!
+//! From the frontend, build URLs with `convertFileSrc(name, "story")`,
+//! which picks `story://localhost/<name>` or `http://story.localhost/<name>`
+//! depending on the platform.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+
+use tauri::http::{header, Method, Request, Response, StatusCode};
+use tauri::{AppHandle, Manager, Runtime};
+
+/// Scheme name registered with the builder.
+pub const SCHEME: &str = "story";
+
+/// File extensions the scheme will serve.
+const SERVED_EXTENSIONS: &[&str] = &["story", "storypatch"];
+
+/// Outcome of matching a `Range` header against a file size.
+#[derive(Debug, PartialEq, Eq)]
+enum RangeMatch {
+    /// No usable range: serve the whole file with 200.
+    Full,
+    /// Serve bytes `start..=end` with 206.
+    Partial(u64, u64),
+    /// The range lies outside the file: 416.
+    Unsatisfiable,
+}
+
+/// Parses a single `bytes=` range against a file of `size` bytes.
+///
+/// Supports `a-b`, open-ended `a-` and suffix `-n` forms; `b` is clamped
+/// to the end of the file. Multi-range requests and other units are
+/// answered with the full file, which RFC 9110 permits.
+fn match_range(value: &str, size: u64) -> RangeMatch {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return RangeMatch::Full;
+    };
+    if spec.contains(',') {
+        return RangeMatch::Full;
+    }
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        return RangeMatch::Full;
+    };
+    let (first, last) = (first.trim(), last.trim());
+    let range = if first.is_empty() {
+        // Suffix range: the last `n` bytes.
+        match last.parse::<u64>() {
+            Ok(0) | Err(_) => return RangeMatch::Unsatisfiable,
+            Ok(n) => (size.saturating_sub(n), size.saturating_sub(1)),
+        }
+    } else {
+        let Ok(start) = first.parse::<u64>() else {
+            return RangeMatch::Full;
+        };
+        let end = if last.is_empty() {
+            size.saturating_sub(1)
+        } else {
+            match last.parse::<u64>() {
+                Ok(end) if end >= start => end.min(size.saturating_sub(1)),
+                _ => return RangeMatch::Full,
+            }
+        };
+        (start, end)
+    };
+    if size == 0 || range.0 >= size {
+        return RangeMatch::Unsatisfiable;
+    }
+    RangeMatch::Partial(range.0, range.1)
+}
+
+/// Handles one `story://` request.
+pub fn handle<R: Runtime>(app: &AppHandle<R>, request: Request<Vec<u8>>) -> Response<Vec<u8>> {
+    if request.method() == Method::OPTIONS {
+        return with_cors(Response::builder().status(StatusCode::NO_CONTENT))
+            .header(header::ACCESS_CONTROL_ALLOW_METHODS, "GET, HEAD, OPTIONS")
+            .header(header::ACCESS_CONTROL_ALLOW_HEADERS, "Range, If-Range")
+            .body(Vec::new())
+            .unwrap();
+    }
+    let Some(path) = resolve(app, request.uri().path()) else {
+        return error(StatusCode::NOT_FOUND);
+    };
+    let Ok(mut file) = File::open(&path) else {
+        return error(StatusCode::NOT_FOUND);
+    };
+    let Ok(size) = file.metadata().map(|m| m.len()) else {
+        return error(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+
+    let range = request
+        .headers()
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .map_or(RangeMatch::Full, |v| match_range(v, size));
+    let (status, start, end) = match range {
+        RangeMatch::Full => (StatusCode::OK, 0, size.saturating_sub(1)),
+        RangeMatch::Partial(start, end) => (StatusCode::PARTIAL_CONTENT, start, end),
+        RangeMatch::Unsatisfiable => {
+            return with_cors(Response::builder().status(StatusCode::RANGE_NOT_SATISFIABLE))
+                .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+                .body(Vec::new())
+                .unwrap();
+        }
+    };
+    let len = if size == 0 { 0 } else { end - start + 1 };
+
+    let mut body = Vec::new();
+    if request.method() != Method::HEAD {
+        body = vec![0; len as usize];
+        let read = file
+            .seek(SeekFrom::Start(start))
+            .and_then(|_| file.read_exact(&mut body));
+        if read.is_err() {
+            return error(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    let mut builder = with_cors(Response::builder().status(status))
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header(header::CONTENT_LENGTH, len.to_string())
+        .header(header::ACCEPT_RANGES, "bytes");
+    if status == StatusCode::PARTIAL_CONTENT {
+        builder = builder.header(
+            header::CONTENT_RANGE,
+            format!("bytes {}-{}/{}", start, end, size),
+        );
+    }
+    builder.body(body).unwrap()
+}
+
+/// Maps a request path to a file in the resource directory or
+/// `<app data>/stories`, rejecting traversal and unserved extensions.
+fn resolve<R: Runtime>(app: &AppHandle<R>, uri_path: &str) -> Option<PathBuf> {
+    let rel = Path::new(uri_path.trim_start_matches('/'));
+    if !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+        return None;
+    }
+    let ext = rel.extension()?.to_str()?;
+    if !SERVED_EXTENSIONS.contains(&ext) {
+        return None;
+    }
+    let roots = [
+        app.path().resource_dir().ok(),
+        app.path().app_data_dir().ok().map(|d| d.join("stories")),
+    ];
+    roots
+        .into_iter()
+        .flatten()
+        .map(|root| root.join(rel))
+        .find(|p| p.is_file())
+}
+
+/// Adds the CORS headers the WebView needs to read ranged responses from
+/// another origin.
+fn with_cors(builder: tauri::http::response::Builder) -> tauri::http::response::Builder {
+    builder
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            "Content-Range, Content-Length, Accept-Ranges",
+        )
+}
+
+/// An empty response with `status`.
+fn error(status: StatusCode) -> Response<Vec<u8>> {
+    with_cors(Response::builder().status(status))
+        .body(Vec::new())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_closed_ranges() {
+        assert_eq!(match_range("bytes=0-0", 100), RangeMatch::Partial(0, 0));
+        assert_eq!(match_range("bytes=10-19", 100), RangeMatch::Partial(10, 19));
+        assert_eq!(match_range("bytes=90-500", 100), RangeMatch::Partial(90, 99));
+        assert_eq!(match_range("bytes=20-10", 100), RangeMatch::Full);
+    }
+
+    #[test]
+    fn matches_suffix_ranges() {
+        assert_eq!(match_range("bytes=-10", 100), RangeMatch::Partial(90, 99));
+        assert_eq!(match_range("bytes=-500", 100), RangeMatch::Partial(0, 99));
+        assert_eq!(match_range("bytes=-0", 100), RangeMatch::Unsatisfiable);
+    }
+
+    #[test]
+    fn matches_open_ended_ranges() {
+        assert_eq!(match_range("bytes=40-", 100), RangeMatch::Partial(40, 99));
+        assert_eq!(match_range("bytes=99-", 100), RangeMatch::Partial(99, 99));
+    }
+
+    #[test]
+    fn serves_multi_range_and_other_units_in_full() {
+        assert_eq!(match_range("bytes=0-9,20-29", 100), RangeMatch::Full);
+        assert_eq!(match_range("items=0-9", 100), RangeMatch::Full);
+        assert_eq!(match_range("bytes=abc", 100), RangeMatch::Full);
+    }
+
+    #[test]
+    fn rejects_ranges_past_the_end() {
+        assert_eq!(match_range("bytes=100-", 100), RangeMatch::Unsatisfiable);
+        assert_eq!(match_range("bytes=150-200", 100), RangeMatch::Unsatisfiable);
+        assert_eq!(match_range("bytes=0-", 0), RangeMatch::Unsatisfiable);
+        assert_eq!(match_range("bytes=-5", 0), RangeMatch::Unsatisfiable);
+    }
+}
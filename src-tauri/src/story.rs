@@ -0,0 +1,223 @@
+//! Native story commands.
+//!
+//! The desktop build reads `.story` files straight from disk (memory-mapped
+//! where possible) through `wasm_module::reader`, instead of fetching a
+//! bundled file through the WebView. Results have the same shape as the
+//! wasm `CyoaGame` methods, so the frontend can use either.
+
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use wasm_module::patch::fnv1a64;
+use wasm_module::reader::{NodeOutput, StoryReader};
+
+/// Story bytes, memory-mapped when the platform allows it.
+enum StoryBytes {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl AsRef<[u8]> for StoryBytes {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            StoryBytes::Mapped(m) => m,
+            StoryBytes::Owned(v) => v,
+        }
+    }
+}
+
+impl StoryBytes {
+    /// Maps the file at `path`, falling back to reading it into memory.
+    fn load(path: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("Cannot open {}: {}", path, e))?;
+        // SAFETY: the map is read-only; the story file is not expected to be
+        // modified while it is open.
+        match unsafe { Mmap::map(&file) } {
+            Ok(map) => Ok(StoryBytes::Mapped(map)),
+            Err(_) => fs::read(path)
+                .map(StoryBytes::Owned)
+                .map_err(|e| format!("Cannot read {}: {}", path, e)),
+        }
+    }
+}
+
+/// The open story and the player's position in it.
+struct Session {
+    path: String,
+    reader: StoryReader<StoryBytes>,
+    current: usize,
+    /// Achievements unlocked in this story over every playthrough; see
+    /// `unlocks_path`.
+    unlocked: Vec<[u8; 3]>,
+}
+
+impl Session {
+    /// Opens the story at `path` with its saved unlocks, positioned at
+    /// the root node.
+    fn open(app: &AppHandle, path: String) -> Result<Session, String> {
+        let reader = StoryReader::open(StoryBytes::load(&path)?).map_err(|e| e.to_string())?;
+        let current = reader.root_idx().map_err(|e| e.to_string())?;
+        // A missing or unreadable unlocks file just means nothing is
+        // unlocked yet.
+        let unlocked = fs::read(unlocks_path(app, &path)?)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .unwrap_or_default();
+        Ok(Session {
+            path,
+            reader,
+            current,
+            unlocked,
+        })
+    }
+
+    /// Runs `load` against the reader and the unlocked set, writing the set
+    /// back to disk if it grew.
+    fn track<T>(
+        &mut self,
+        app: &AppHandle,
+        load: impl FnOnce(&StoryReader<StoryBytes>, &mut Vec<[u8; 3]>) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let before = self.unlocked.len();
+        // Unlocks stick even if the rest of the load fails.
+        let out = load(&self.reader, &mut self.unlocked);
+        if self.unlocked.len() != before {
+            let path = unlocks_path(app, &self.path)?;
+            let json = serde_json::to_vec(&self.unlocked).map_err(|e| e.to_string())?;
+            fs::write(&path, json)
+                .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+        }
+        out
+    }
+}
+
+/// Managed state holding the current session, if a story is open.
+#[derive(Default)]
+pub struct StoryState(Mutex<Option<Session>>);
+
+/// On-disk save slot contents. Unlocked achievements are not part of a
+/// save; they are kept per story across playthroughs.
+#[derive(Serialize, Deserialize)]
+struct SaveFile {
+    story_path: String,
+    node_idx: usize,
+}
+
+/// Opens the story at `path` and loads its root node.
+#[tauri::command]
+pub fn open_story(
+    path: String,
+    app: AppHandle,
+    state: State<'_, StoryState>,
+) -> Result<NodeOutput, String> {
+    let mut session = Session::open(&app, path)?;
+    let root = session.current;
+    let node = session.track(&app, |reader, unlocked| {
+        reader.load_node(root, unlocked).map_err(|e| e.to_string())
+    })?;
+    *state.0.lock().unwrap() = Some(session);
+    Ok(node)
+}
+
+/// Loads node `idx` of the open story and makes it the current node.
+#[tauri::command]
+pub fn load_node(
+    idx: usize,
+    app: AppHandle,
+    state: State<'_, StoryState>,
+) -> Result<NodeOutput, String> {
+    let mut guard = state.0.lock().unwrap();
+    let session = guard.as_mut().ok_or("No story open")?;
+    let node = session.track(&app, |reader, unlocked| {
+        reader.load_node(idx, unlocked).map_err(|e| e.to_string())
+    })?;
+    session.current = idx;
+    Ok(node)
+}
+
+/// Follows edge `choice` of the current node and loads its destination.
+#[tauri::command]
+pub fn choose(
+    choice: usize,
+    app: AppHandle,
+    state: State<'_, StoryState>,
+) -> Result<NodeOutput, String> {
+    let mut guard = state.0.lock().unwrap();
+    let session = guard.as_mut().ok_or("No story open")?;
+    let current = session.current;
+    let (dest, node) = session.track(&app, |reader, unlocked| {
+        reader.choose(current, choice, unlocked).map_err(|e| e.to_string())
+    })?;
+    session.current = dest;
+    Ok(node)
+}
+
+/// Writes the current story and node to save slot `slot` in the app data
+/// directory.
+#[tauri::command]
+pub fn save_game(slot: String, app: AppHandle, state: State<'_, StoryState>) -> Result<(), String> {
+    let guard = state.0.lock().unwrap();
+    let session = guard.as_ref().ok_or("No story open")?;
+    let save = SaveFile {
+        story_path: session.path.clone(),
+        node_idx: session.current,
+    };
+    let path = slot_path(&app, &slot)?;
+    let json = serde_json::to_vec(&save).map_err(|e| e.to_string())?;
+    fs::write(&path, json).map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+}
+
+/// Restores save slot `slot`, reopening its story if needed, and loads
+/// the saved node.
+#[tauri::command]
+pub fn load_game(
+    slot: String,
+    app: AppHandle,
+    state: State<'_, StoryState>,
+) -> Result<NodeOutput, String> {
+    let path = slot_path(&app, &slot)?;
+    let json = fs::read(&path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let save: SaveFile = serde_json::from_slice(&json).map_err(|e| e.to_string())?;
+
+    let mut guard = state.0.lock().unwrap();
+    let reopen = guard.as_ref().is_none_or(|s| s.path != save.story_path);
+    if reopen {
+        *guard = Some(Session::open(&app, save.story_path.clone())?);
+    }
+    let session = guard.as_mut().ok_or("No story open")?;
+    session.current = save.node_idx;
+    session.track(&app, |reader, unlocked| {
+        reader.load_node(save.node_idx, unlocked).map_err(|e| e.to_string())
+    })
+}
+
+/// Path of save slot `slot`, creating the saves directory if needed.
+fn slot_path(app: &AppHandle, slot: &str) -> Result<PathBuf, String> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if slot.is_empty() || !slot.chars().all(valid) {
+        return Err(format!("Invalid save slot name {:?}", slot));
+    }
+    Ok(data_dir(app, "saves")?.join(format!("{}.json", slot)))
+}
+
+/// Path of the unlocked achievements of the story at `story_path`, kept
+/// apart from the save slots so unlocks survive every playthrough.
+fn unlocks_path(app: &AppHandle, story_path: &str) -> Result<PathBuf, String> {
+    let key = fnv1a64(story_path.as_bytes());
+    Ok(data_dir(app, "unlocks")?.join(format!("{:016x}.json", key)))
+}
+
+/// Subdirectory `name` of the app data directory, created if needed.
+fn data_dir(app: &AppHandle, name: &str) -> Result<PathBuf, String> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?
+        .join(name);
+    fs::create_dir_all(&dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    Ok(dir)
+}
@@ -0,0 +1,75 @@
+/*
+ * Typed wrappers around the Tauri backend's native story commands.
+ *
+ * In the desktop build the story is read straight from disk by the Rust backend instead of
+ * being fetched through the WebView. Nodes come back in the same shape as from the WASM client.
+ */
+
+import { invoke } from '@tauri-apps/api/core';
+import type { Achievement, Edge } from './wasm';
+
+/**
+ * Low-level node shape returned by the native commands.
+ * @internal
+ */
+type NodeRaw = {
+  content: string;
+  edges: { label: string; dest_idx: number }[];
+  unlocked: Achievement[];
+};
+
+/**
+ * A loaded node: its text, its choices and any achievements unlocked on the way.
+ */
+export type NativeNode = {
+  content: string;
+  edges: Edge[];
+  unlocked: Achievement[];
+};
+
+/** Convert a raw node into the public-facing shape. */
+function toNode(raw: NodeRaw): NativeNode {
+  return {
+    content: raw.content,
+    edges: raw.edges.map(({ label, dest_idx }) => ({ label, dest: dest_idx })),
+    unlocked: raw.unlocked,
+  };
+}
+
+/**
+ * Open the `.story` file at `path` on disk and load its root node.
+ *
+ * @throws if the file cannot be read or is not a valid story.
+ */
+export async function openStory(path: string): Promise<NativeNode> {
+  return toNode(await invoke<NodeRaw>('open_story', { path }));
+}
+
+/**
+ * Load node `idx` of the open story and make it the current node.
+ */
+export async function loadNode(idx: number): Promise<NativeNode> {
+  return toNode(await invoke<NodeRaw>('load_node', { idx }));
+}
+
+/**
+ * Follow choice `choice` of the current node.
+ */
+export async function choose(choice: number): Promise<NativeNode> {
+  return toNode(await invoke<NodeRaw>('choose', { choice }));
+}
+
+/**
+ * Save the current position to slot `slot` (letters, digits, `-` and `_`
+ * only). Unlocked achievements are kept per story, outside the save slots.
+ */
+export async function saveGame(slot: string): Promise<void> {
+  await invoke('save_game', { slot });
+}
+
+/**
+ * Restore slot `slot`, reopening its story if needed, and load the saved node.
+ */
+export async function loadGame(slot: string): Promise<NativeNode> {
+  return toNode(await invoke<NodeRaw>('load_game', { slot }));
+}
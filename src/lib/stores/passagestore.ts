@@ -5,7 +5,17 @@
  * story node and navigation.
  */
 
-import { ready, fetchRootNodeFull, fetchNodeFull } from '$lib/wasm';
+import {
+  ready,
+  fetchRootNodeFull,
+  fetchNodeFull,
+  chooseEdge,
+  reloadStory as reopenStory,
+  nodeChunkId,
+  nodeIndex,
+  isCyoaError,
+  onFirstScreen,
+} from '$lib/wasm';
 import { writable, derived, get } from 'svelte/store';
 
 /**
@@ -47,6 +57,12 @@ export const nodeCache = writable<Map<number, StoryNode>>(new Map());
  */
 export const currentIndex = writable<number>(0);
 
+/**
+ * Set when the story file was replaced on the server mid-session; the
+ * player should be offered `reloadStory`.
+ */
+export const storyChanged = writable<boolean>(false);
+
 /**
  * Derived store that returns the StoryNode corresponding to currentIndex.
  * Falls back to a placeholder node if the requested index is not yet cached.
@@ -62,7 +78,8 @@ export const currentNode = derived(
  *
  * Steps:
  * 1. Await the WASM module initialization (ready promise).
- * 2. Fetch the root node data via the Rust-generated API.
+ * 2. Fetch the root node data via the Rust-generated API, showing it early
+ *    if the story's bootstrap section delivers it before the index.
  * 3. Store the root node under index 0 in the cache.
  * 4. Set currentIndex to 0, triggering subscribers to display the root.
  *
@@ -72,6 +89,11 @@ export async function initialize(): Promise<void> {
   // Ensure the WASM runtime is loaded
   await ready;
 
+  // Show the root node from the bootstrap section as soon as it arrives
+  onFirstScreen((node) => {
+    nodeCache.update((m) => (m.has(0) ? m : m.set(0, node)));
+  });
+
   // Retrieve root node from the WASM engine
   const root = await fetchRootNodeFull();
 
@@ -81,35 +103,18 @@ export async function initialize(): Promise<void> {
 }
 
 /**
- * Load a specific story node by its index, cache it, and attempt to prefetch its children.
- *
- * - If the node is already cached, this is a no-op.
- * - Otherwise, it fetches the node, updates the cache, and
- *   kicks off background fetches for all child nodes (edges.dest).
- * - Errors during fetch are caught and logged; the cache is updated with an error node.
- *
- * @param idx - Numeric index of the node to load.
- * @returns A Promise that resolves once the node fetch (and prefetch dispatch) is initiated.
+ * Cache `node` under `idx`, or an error placeholder if loading it failed.
+ * A `STORY_CHANGED` error raises the reload banner instead.
  */
-export async function loadNode(idx: number): Promise<void> {
-  const cache = get(nodeCache);
-  // Skip loading if already present
-  if (cache.has(idx)) return;
-
+async function settle(idx: number, load: Promise<StoryNode>): Promise<void> {
   try {
-    // Fetch the node data from WASM
-    const node = await fetchNodeFull(idx);
+    const node = await load;
     nodeCache.update(m => m.set(idx, node));
-
-    // Prefetch each child node in parallel, logging any failures
-    const childPromises = node.edges.map(({ dest }) =>
-      fetchNodeFull(dest)
-        .then(child => nodeCache.update(m => m.set(dest, child)))
-        .catch(err => {
-          console.error(`prefetch failed for node #${dest}:`, err);
-        })
-    );
   } catch (e) {
+    if (isCyoaError(e, 'STORY_CHANGED')) {
+      storyChanged.set(true);
+      return;
+    }
     // On error, log and insert an error placeholder in the cache
     console.error(`Error loading node #${idx}:`, e);
     nodeCache.update(m =>
@@ -119,13 +124,61 @@ export async function loadNode(idx: number): Promise<void> {
 }
 
 /**
- * Navigate to a different story node by updating currentIndex and triggering a load.
+ * Load a specific story node by its index and cache it, without taking
+ * any edge to it (e.g. to show it again after a reload).
+ *
+ * - If the node is already cached, this is a no-op.
+ * - Errors during fetch are caught and logged; the cache is updated with an error node.
+ *
+ * Likely next nodes are prefetched by the engine, which fetches their
+ * chunks without running any of their effects.
+ *
+ * @param idx - Numeric index of the node to load.
+ * @returns A Promise that resolves once the node is cached.
+ */
+export async function loadNode(idx: number): Promise<void> {
+  const cache = get(nodeCache);
+  // Skip loading if already present
+  if (cache.has(idx)) return;
+  await settle(idx, fetchNodeFull(idx));
+}
+
+/**
+ * Take choice `choice` of the current node: the engine applies the edge's
+ * effects (e.g. achievement unlocks) and loads its destination, which
+ * becomes the current node.
  *
- * @param idx - Numeric index of the target node.
+ * This runs even when the destination is cached, since taking an edge
+ * always has to apply its effects.
+ *
+ * @param choice - Zero-based position of the choice in the current node's edges.
  */
-export function goTo(idx: number): void {
-  // Update active node index
+export async function choose(choice: number): Promise<void> {
+  const from = get(currentIndex);
+  const edge = get(currentNode).edges[choice];
+  if (!edge) return;
+  // Show the destination (or the loading placeholder) right away
+  currentIndex.set(edge.dest);
+  await settle(edge.dest, chooseEdge(from, choice));
+}
+
+/**
+ * Reopen the story after it changed on the server, keeping the player's
+ * place: the cache is cleared and the current node is looked up in the
+ * new file by its chunk ID, since indices may have shifted. If the new
+ * version no longer has that node, the player is taken to the root.
+ */
+export async function reloadStory(): Promise<void> {
+  const id = await nodeChunkId(get(currentIndex));
+  await reopenStory();
+  nodeCache.set(new Map());
+  storyChanged.set(false);
+  const idx = id === undefined ? undefined : await nodeIndex(id);
+  if (idx === undefined) {
+    currentIndex.set(0);
+    await settle(0, fetchRootNodeFull());
+    return;
+  }
   currentIndex.set(idx);
-  // Begin loading (and caching) the new node asynchronously
-  loadNode(idx);
+  await loadNode(idx);
 }
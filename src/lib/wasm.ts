@@ -7,8 +7,14 @@
  */
 
 import init, { CyoaGame } from '../pkg/wasm_module.js';
-import type { CyoaGame as ClientType } from '../pkg/wasm_module.js';
+import type {
+  CyoaGame as ClientType,
+  CyoaError,
+  CyoaErrorCode,
+  LogLevel,
+} from '../pkg/wasm_module.js';
 import { base } from '$app/paths';
+import { convertFileSrc, isTauri } from '@tauri-apps/api/core';
 
 /**
  * Relative URL to the compiled WebAssembly binary for the CYOA engine.
@@ -19,8 +25,12 @@ const wasmUrl = new URL('../pkg/wasm_module_bg.wasm', import.meta.url);
 /**
  * Absolute path (URL) to the story file to load into the WASM engine.
  * Combines SvelteKit's the `base` path alias with the story file name.
+ * In the desktop build the file is served by the backend's `story://`
+ * scheme, which answers Range requests on every platform.
  */
-export const STORY_PATH = `${base}/magium.story`;
+export const STORY_PATH = isTauri()
+  ? convertFileSrc('magium.story', 'story')
+  : `${base}/magium.story`;
 
 /**
  * Promise that resolves when the WASM module has been initialized.
@@ -39,6 +49,22 @@ type EdgeRaw = {
   dest_idx: number;
 };
 
+/**
+ * An achievement from the story's catalog, as returned from WASM.
+ */
+export type Achievement = {
+  /** Uppercase hex achievement ID, e.g. "000102" */
+  id: string;
+  /** Short title shown in the panel and in unlock toasts */
+  title: string;
+  /** Description of how the achievement is earned */
+  description: string;
+  /** Whether the achievement stays concealed until unlocked */
+  hidden: boolean;
+  /** Whether the player has unlocked it (persists across playthroughs) */
+  unlocked: boolean;
+};
+
 /**
  * Low-level representation of a game node (scene) returned from WASM.
  * @internal
@@ -48,6 +74,8 @@ type NodeRaw = {
   content: string;
   /** Array of outgoing edges (choices) from this node */
   edges: EdgeRaw[];
+  /** Achievements newly unlocked by reaching this node */
+  unlocked: Achievement[];
 };
 
 /**
@@ -69,12 +97,102 @@ async function getClient(): Promise<ClientType> {
 
   // Lazy-instantiation: create the client if it doesn't exist
   if (!(STORY_PATH in clients)) {
-    clients[STORY_PATH] = new CyoaGame(STORY_PATH);
+    clients[STORY_PATH] = new CyoaGame(STORY_PATH, undefined, clientOptions());
   }
 
   return clients[STORY_PATH];
 }
 
+export type { CyoaError, CyoaErrorCode };
+
+/**
+ * Check whether `err` was thrown by the engine, optionally with a given
+ * `code`, e.g. `isCyoaError(e, 'STORY_CHANGED')`.
+ */
+export function isCyoaError(err: unknown, code?: CyoaErrorCode): err is CyoaError {
+  return (
+    err instanceof Error &&
+    err.name === 'CyoaError' &&
+    (code === undefined || (err as CyoaError).code === code)
+  );
+}
+
+/**
+ * Reopen the configured story after a `STORY_CHANGED` error, discarding
+ * the stale client. Unlocked achievements are kept by the engine; node
+ * indices are not, so the caller takes the player's position along with
+ * `nodeChunkId` and `nodeIndex`.
+ */
+export async function reloadStory(): Promise<void> {
+  await ready;
+  clients[STORY_PATH] = await new CyoaGame(STORY_PATH, undefined, clientOptions());
+}
+
+/**
+ * Chunk ID of the node at `idx`, which unlike the index still names the
+ * same node after the story is redeployed; `undefined` if `idx` is not a
+ * node.
+ */
+export async function nodeChunkId(idx: number): Promise<string | undefined> {
+  const client = await getClient();
+  return client.node_chunk_id(idx);
+}
+
+/**
+ * Index of the node with chunk ID `id` in the current story, or
+ * `undefined` if it has none.
+ */
+export async function nodeIndex(id: string): Promise<number | undefined> {
+  const client = await getClient();
+  return client.node_index(id);
+}
+
+/**
+ * Listener registered with `onFirstScreen`, if any.
+ */
+let firstScreenListener: ((node: Scene) => void) | undefined;
+
+/**
+ * Options for new clients, forwarding the bootstrap's first screen to
+ * the registered listener. In the desktop build the story comes from the
+ * backend's `story://` scheme, which is another origin than the page, so
+ * requests must be sent in `cors` mode.
+ */
+function clientOptions() {
+  return {
+    ...(isTauri() ? { mode: 'cors' as const } : {}),
+    onFirstScreen: (raw: NodeRaw) => firstScreenListener?.(toScene(raw)),
+  };
+}
+
+/**
+ * Receive the root node as soon as the story's bootstrap section arrives,
+ * before the engine has read the index; `fetchRootNodeFull` still
+ * resolves later with the same node. Stories without a bootstrap never
+ * call `callback`. Register before the first fetch.
+ */
+export function onFirstScreen(callback?: (node: Scene) => void): void {
+  firstScreenListener = callback;
+}
+
+/**
+ * Open a `.story` file the player picked or dropped onto the window,
+ * replacing the configured story for all subsequent calls. Chunks are
+ * read lazily from the file, so no web server is needed.
+ *
+ * @example
+ * ```ts
+ * await openLocalStory(event.dataTransfer.files[0]);
+ * const root = await fetchRootNodeFull();
+ * ```
+ *
+ * @throws if the file is not a valid story.
+ */
+export async function openLocalStory(file: File): Promise<void> {
+  await ready;
+  clients[STORY_PATH] = await CyoaGame.fromBlob(file);
+}
+
 /**
  * Public-facing format for edges returned to the application.
  */
@@ -107,12 +225,24 @@ export type Edge = {
 export async function fetchRootNodeFull(): Promise<{
   content: string;
   edges: Edge[];
+  unlocked: Achievement[];
 }> {
   const client = await getClient();
   // Load the root node (index 0) via the WASM API
   const jsNode = (await client.load_root_node_full()) as NodeRaw;
+  return toScene(jsNode);
+}
+
+/**
+ * A scene as returned by `fetchRootNodeFull` and `fetchNodeFull`.
+ */
+type Scene = { content: string; edges: Edge[]; unlocked: Achievement[] };
 
-  // Transform raw edges into public-facing Edge objects
+/**
+ * Transform a raw node's edges into public-facing Edge objects.
+ * @internal
+ */
+function toScene(jsNode: NodeRaw): Scene {
   const edges: Edge[] = jsNode.edges.map(({ label, dest_idx }) => ({
     label,
     dest: dest_idx,
@@ -121,6 +251,7 @@ export async function fetchRootNodeFull(): Promise<{
   return {
     content: jsNode.content,
     edges,
+    unlocked: jsNode.unlocked,
   };
 }
 
@@ -149,6 +280,7 @@ export async function fetchNodeFull(
 ): Promise<{
   content: string;
   edges: Edge[];
+  unlocked: Achievement[];
 }> {
   const client = await getClient();
   const jsNode = (await client.load_node_full(nodeIdx)) as NodeRaw;
@@ -162,5 +294,170 @@ export async function fetchNodeFull(
   return {
     content: jsNode.content,
     edges,
+    unlocked: jsNode.unlocked,
   };
 }
+
+/**
+ * Follow choice `choice` of node `fromIdx`: the engine applies the edge's
+ * effects and the destination's entry functions, then loads it.
+ *
+ * This is how the player moves through the story; `fetchNodeFull` only
+ * displays a node (still running its entry functions) and does not count
+ * as taking an edge.
+ *
+ * @param fromIdx - Zero-based index of the node the choice belongs to
+ * @param choice - Zero-based position of the choice in that node's edges
+ *
+ * @throws if the choice is out of range or the WASM call fails.
+ */
+export async function chooseEdge(fromIdx: number, choice: number): Promise<Scene> {
+  const client = await getClient();
+  return toScene((await client.choose(fromIdx, choice)) as NodeRaw);
+}
+
+/**
+ * Fetch the story's achievements catalog, with each entry's unlock state.
+ *
+ * @returns Promise resolving to every achievement, in catalog order.
+ */
+export async function fetchAchievements(): Promise<Achievement[]> {
+  const client = await getClient();
+  return (await client.achievements()) as Achievement[];
+}
+
+/**
+ * Typed story metadata read from the story file's metadata chunks.
+ * Keys missing from the file are `null` or empty arrays.
+ */
+export type StoryMetadata = {
+  title: string | null;
+  authors: string[];
+  /** Story content version, e.g. "1.2.0" */
+  version: string | null;
+  description: string | null;
+  content_warnings: string[];
+  /** Path or URL of the cover image */
+  cover_asset: string | null;
+  /** Oldest engine version able to play the story */
+  min_engine_version: string | null;
+  /** Index of the root node, if the root pointer resolves */
+  root_idx: number | null;
+  /** Whether this engine satisfies `min_engine_version` */
+  engine_compatible: boolean;
+};
+
+/**
+ * Fetch the story's metadata (title, authors, version, ...).
+ *
+ * @returns Promise resolving to the decoded metadata object.
+ */
+export async function fetchMetadata(): Promise<StoryMetadata> {
+  const client = await getClient();
+  return (await client.metadata()) as StoryMetadata;
+}
+
+/**
+ * Progress of an offline download, reported after each fetched batch.
+ */
+export type DownloadProgress = {
+  bytes_done: number;
+  bytes_total: number;
+  chunks_done: number;
+  chunks_total: number;
+};
+
+/**
+ * Download the story (or one chapter of it) for offline play.
+ *
+ * Chunks already downloaded are skipped, so calling this again after a
+ * cancellation or failure resumes where it stopped.
+ *
+ * @param onProgress - Called with the overall progress after each batch.
+ * @param chapter - Only download nodes tagged with this chapter.
+ * @returns Promise resolving to the final progress.
+ * @throws a `CyoaError` with code `CANCELLED` after `cancelDownload`, or on
+ *   an integrity check failure or network error.
+ */
+export async function downloadForOffline(
+  onProgress?: (progress: DownloadProgress) => void,
+  chapter?: string,
+): Promise<DownloadProgress> {
+  const client = await getClient();
+  const progress =
+    chapter === undefined
+      ? await client.download_all(onProgress)
+      : await client.download_chapter(chapter, onProgress);
+  return progress as DownloadProgress;
+}
+
+/**
+ * Cancel a running `downloadForOffline` after its current batch.
+ */
+export async function cancelDownload(): Promise<void> {
+  const client = await getClient();
+  client.cancel_download();
+}
+
+/**
+ * Abort the node loads still in flight, e.g. when the player taps another
+ * choice first. Their promises reject with code `ABORTED`; offline
+ * downloads keep going.
+ */
+export async function abortLoads(): Promise<void> {
+  const client = await getClient();
+  client.abort();
+}
+
+/**
+ * Cache hits and misses of one chunk type within a `LoadStats`.
+ */
+export type ChunkCounts = {
+  chunk_type: string;
+  hits: number;
+  misses: number;
+};
+
+/**
+ * Cost of one node load, or totals since the story was opened.
+ */
+export type LoadStats = {
+  /** Node loads counted (1 for a single load's trace) */
+  loads: number;
+  elapsed_ms: number;
+  requests: number;
+  /** Bytes received for chunk requests */
+  bytes: number;
+  network_ms: number;
+  zstd_ms: number;
+  parse_ms: number;
+  chunks: ChunkCounts[];
+};
+
+/**
+ * Receive the trace of every node load, e.g. to report slow page turns,
+ * or pass `undefined` to stop.
+ */
+export async function onLoadTrace(
+  callback?: (trace: LoadStats) => void,
+): Promise<void> {
+  const client = await getClient();
+  client.set_trace_callback(callback);
+}
+
+/**
+ * Fetch totals since the story was opened, prefetches included.
+ */
+export async function fetchStats(): Promise<LoadStats> {
+  const client = await getClient();
+  return client.stats() as LoadStats;
+}
+
+/**
+ * Change how much the engine logs to the console, e.g. `'debug'` while
+ * investigating a problem on a player's device.
+ */
+export async function setLogLevel(level: LogLevel): Promise<void> {
+  await ready;
+  CyoaGame.setLogLevel(level);
+}
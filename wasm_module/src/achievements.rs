@@ -0,0 +1,178 @@
+//! # Achievements
+//!
+//! Parsing of the achievements catalog chunk and bookkeeping for the set of
+//! achievements the player has unlocked.
+//!
+//! Unlocks are stored in `localStorage` under a key derived from the story
+//! URL, independently of any per-run save, so they survive starting a new
+//! playthrough.
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use serde::Serialize;
+use std::io::{Cursor, Read};
+use web_sys::{Storage, window};
+
+use crate::decoder::GameError;
+use crate::utils::{hex_id, parse_hex_id};
+
+/// Reserved function ID for the "unlock achievement" effect.
+///
+/// When it appears in a node's entry functions or an edge's effects, its
+/// argument bytes hold the 3-byte ID of the achievement to unlock.
+pub const FN_UNLOCK_ACHIEVEMENT: u32 = 0xFFFF_0001;
+
+/// Prefix of the `localStorage` key holding unlocked achievement IDs.
+const STORAGE_PREFIX: &str = "cyoa:achievements:";
+
+/// One entry of the achievements catalog.
+#[derive(Clone, Debug)]
+pub struct Achievement {
+    /// The 3-byte achievement ID referenced by unlock effects.
+    pub id: [u8; 3],
+    /// Short title shown in the achievements panel and toasts.
+    pub title: String,
+    /// Longer description of how the achievement is earned.
+    pub description: String,
+    /// Whether the achievement should stay concealed until unlocked.
+    pub hidden: bool,
+}
+
+/// JS-facing view of an achievement, including its unlock state.
+#[derive(Serialize)]
+pub struct AchievementOutput {
+    /// Uppercase hex achievement ID, e.g. `"000102"`.
+    pub id: String,
+    /// Achievement title.
+    pub title: String,
+    /// Achievement description.
+    pub description: String,
+    /// Whether the achievement is hidden until unlocked.
+    pub hidden: bool,
+    /// Whether the player has unlocked it.
+    pub unlocked: bool,
+}
+
+impl Achievement {
+    /// Builds the JS-facing view of this achievement.
+    pub fn to_output(&self, unlocked: bool) -> AchievementOutput {
+        AchievementOutput {
+            id: hex_id(&self.id),
+            title: self.title.clone(),
+            description: self.description.clone(),
+            hidden: self.hidden,
+            unlocked,
+        }
+    }
+}
+
+/// Parses the decompressed payload of a `ChunkType::Achievements` chunk.
+///
+/// Layout:
+/// - u16: number of entries
+/// - for each entry:
+///   - 3-byte achievement ID
+///   - u8: flags (bit 0 = hidden)
+///   - u16 title length + UTF-8 title
+///   - u16 description length + UTF-8 description
+///
+/// # Returns
+///
+/// - `Ok(Vec<Achievement>)` with all catalog entries in file order.
+/// - `Err(GameError::Parse(_))` on malformed data or invalid UTF-8.
+pub fn parse_catalog(data: &[u8]) -> Result<Vec<Achievement>, GameError> {
+    let mut c = Cursor::new(data);
+    let cnt = c
+        .read_u16::<LittleEndian>()
+        .map_err(|_| GameError::Parse("Read achievement count"))?;
+    let mut out = Vec::with_capacity(cnt as usize);
+    for _ in 0..cnt {
+        let mut id = [0u8; 3];
+        c.read_exact(&mut id)
+            .map_err(|_| GameError::Parse("Read achievement id"))?;
+        let flags = c
+            .read_u8()
+            .map_err(|_| GameError::Parse("Read achievement flags"))?;
+        let title = read_str16(&mut c)?;
+        let description = read_str16(&mut c)?;
+        out.push(Achievement {
+            id,
+            title,
+            description,
+            hidden: flags & 1 != 0,
+        });
+    }
+    Ok(out)
+}
+
+/// Reads a u16 length-prefixed UTF-8 string.
+fn read_str16(c: &mut Cursor<&[u8]>) -> Result<String, GameError> {
+    let len = c
+        .read_u16::<LittleEndian>()
+        .map_err(|_| GameError::Parse("Read string length"))?;
+    let mut buf = vec![0; len as usize];
+    c.read_exact(&mut buf)
+        .map_err(|_| GameError::Parse("Read string"))?;
+    String::from_utf8(buf).map_err(|_| GameError::Parse("Invalid UTF-8"))
+}
+
+/// The set of unlocked achievements for one story, mirrored to
+/// `localStorage` on every change.
+pub struct UnlockStore {
+    key: String,
+    unlocked: Vec<[u8; 3]>,
+}
+
+impl UnlockStore {
+    /// Loads the unlock record for the story at `story_url`.
+    ///
+    /// Missing storage (e.g. disabled cookies) or malformed entries simply
+    /// yield an empty record rather than an error.
+    pub fn load(story_url: &str) -> Self {
+        let key = format!("{}{}", STORAGE_PREFIX, story_url);
+        let unlocked = local_storage()
+            .and_then(|s| s.get_item(&key).ok().flatten())
+            .map(|v| v.split(',').filter_map(parse_hex_id).collect())
+            .unwrap_or_default();
+        Self { key, unlocked }
+    }
+
+    /// Returns `true` if `id` has been unlocked.
+    pub fn contains(&self, id: &[u8; 3]) -> bool {
+        self.unlocked.contains(id)
+    }
+
+    /// All unlocked IDs, in the order they were unlocked.
+    pub fn ids(&self) -> &[[u8; 3]] {
+        &self.unlocked
+    }
+
+    /// Records `id` as unlocked and persists the record.
+    ///
+    /// Returns `true` if the achievement was newly unlocked.
+    pub fn unlock(&mut self, id: [u8; 3]) -> bool {
+        if self.contains(&id) {
+            return false;
+        }
+        self.unlocked.push(id);
+        self.persist();
+        true
+    }
+
+    /// Writes the current record back to `localStorage`, ignoring failures.
+    fn persist(&self) {
+        if let Some(storage) = local_storage() {
+            let value = self
+                .unlocked
+                .iter()
+                .map(hex_id)
+                .collect::<Vec<_>>()
+                .join(",");
+            let _ = storage.set_item(&self.key, &value);
+        }
+    }
+}
+
+/// Returns the window's `localStorage`, if available.
+fn local_storage() -> Option<Storage> {
+    window()?.local_storage().ok().flatten()
+}
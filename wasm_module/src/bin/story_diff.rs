@@ -0,0 +1,49 @@
+//! # story_diff
+//!
+//! Diffs two `.story` files into a patch that upgrades the first into the
+//! second.
+//!
+//! ```text
+//! story_diff <old.story> <new.story> <out.storypatch>
+//! ```
+
+use std::{env, fs, process};
+
+use wasm_module::patch::{diff, story_fingerprint};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 4 {
+        eprintln!("usage: {} <old.story> <new.story> <out.storypatch>", args[0]);
+        process::exit(2);
+    }
+    let read = |path: &str| {
+        fs::read(path).unwrap_or_else(|e| {
+            eprintln!("failed to read {}: {}", path, e);
+            process::exit(1);
+        })
+    };
+    let old = read(&args[1]);
+    let new = read(&args[2]);
+    let patch = diff(&old, &new).unwrap_or_else(|e| {
+        eprintln!("failed to diff: {}", e);
+        process::exit(1);
+    });
+    if patch.base_fingerprint == patch.target_fingerprint && !patch.ops.is_empty() {
+        eprintln!(
+            "warning: both stories have fingerprint {:016X}; write a fingerprint metadata chunk into {}",
+            story_fingerprint(&new).unwrap_or_default(),
+            args[2]
+        );
+    }
+    if let Err(e) = fs::write(&args[3], patch.to_bytes()) {
+        eprintln!("failed to write {}: {}", args[3], e);
+        process::exit(1);
+    }
+    println!(
+        "{} ops, {:016X} -> {:016X}",
+        patch.ops.len(),
+        patch.base_fingerprint,
+        patch.target_fingerprint
+    );
+}
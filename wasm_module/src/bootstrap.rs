@@ -0,0 +1,123 @@
+//! # Bootstrap Section
+//!
+//! A story file may carry a `ChunkType::Bootstrap` chunk right after the
+//! header, holding copies of everything the first screen needs: the root
+//! pointer, the root node, its content, its edges and their labels. The
+//! loader fetches it in the same request as the header, shows the first
+//! screen from it and only then reads the index.
+//!
+//! Its payload is:
+//!
+//! ```text
+//! u16 chunk_count, then chunk_count complete TLV chunks back to back
+//! u16 position_count, then position_count × (node ID [u8; 3], u32 index position)
+//! ```
+//!
+//! The chunk list is laid out as in a page chunk (see the `page` module).
+//! The positions give the index position of the root node and of every
+//! node its edges lead to, since choices are reported by position. All
+//! integers are little-endian.
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+
+use crate::cache::ChunkKey;
+use crate::decoded::{ContentText, EdgeRecord, NodeRecord, Record};
+use crate::decoder::{ChunkType, CyoaGame, GameError};
+use crate::metadata::ID_ROOT_POINTER;
+use crate::page::{self, Bundle};
+use crate::reader::{EdgeOutput, NodeOutput};
+use crate::wasmtable::run_guard;
+
+/// Bytes read after the header in the first request, in the hope of
+/// catching the whole bootstrap chunk. Files without one pay for this
+/// much extra in that request.
+pub(crate) const BOOTSTRAP_WINDOW: u64 = 16 * 1024;
+
+/// A parsed bootstrap chunk.
+pub(crate) struct Bootstrap {
+    /// Raw TLV chunks, as they also appear elsewhere in the file.
+    pub(crate) chunks: Bundle,
+    /// Index positions of nodes, by node ID.
+    positions: HashMap<[u8; 3], usize>,
+}
+
+impl Bootstrap {
+    /// Returns the total length of the bootstrap chunk at the start of
+    /// `bytes`, or `None` if `bytes` does not start with one.
+    pub(crate) fn chunk_len(bytes: &[u8]) -> Option<usize> {
+        if bytes.first() != Some(&(ChunkType::Bootstrap as u8)) {
+            return None;
+        }
+        let (_t, _id, _flags, comp_len, _un, hdr_len) = CyoaGame::parse_tlv_header(bytes).ok()?;
+        Some(hdr_len + comp_len as usize)
+    }
+
+    /// Parses a complete raw bootstrap chunk.
+    ///
+    /// # Errors
+    ///
+    /// - `GameError::Parse` if the chunk or any chunk inside it is
+    ///   malformed or truncated.
+    pub(crate) fn parse(raw: &[u8]) -> Result<Bootstrap, GameError> {
+        let payload = CyoaGame::chunk_payload(raw)?;
+        let mut c = Cursor::new(payload.as_slice());
+        let truncated = |_| GameError::Parse("Bootstrap truncated");
+
+        let chunks = page::read_chunks(&mut c)?;
+        let count = c.read_u16::<LittleEndian>().map_err(truncated)?;
+        let mut positions = HashMap::new();
+        for _ in 0..count {
+            let mut id = [0; 3];
+            c.read_exact(&mut id).map_err(truncated)?;
+            let pos = c.read_u32::<LittleEndian>().map_err(truncated)?;
+            positions.insert(id, pos as usize);
+        }
+        Ok(Bootstrap { chunks, positions })
+    }
+
+    /// Builds the root node exactly as `load_root_node_full` would return
+    /// it, or `None` if that needs anything the bootstrap lacks: a missing
+    /// chunk or position, entry functions (which need the achievements
+    /// catalog), or an edge into another story file.
+    pub(crate) fn first_screen(&self) -> Option<NodeOutput> {
+        let payload = |key: ChunkKey| CyoaGame::chunk_payload(self.chunks.get(&key)?).ok();
+        let text = |id: [u8; 3]| {
+            ContentText::decode(&payload((ChunkType::Content, id))?)
+                .ok()
+                .map(|t| t.0)
+        };
+
+        let pointer = payload((ChunkType::Metadata, ID_ROOT_POINTER))?;
+        let root: [u8; 3] = pointer.get(..3)?.try_into().ok()?;
+        let node = NodeRecord::decode(&payload((ChunkType::Node, root))?).ok()?;
+        if !node.entry_funcs.is_empty() {
+            return None;
+        }
+
+        let mut content = String::new();
+        for seg in &node.content_seq {
+            if let Some((func_id, guard_bytes)) = &seg.guard
+                && !run_guard(*func_id, guard_bytes)
+            {
+                continue;
+            }
+            content.push_str(&text(seg.content_id)?);
+        }
+
+        let mut edges = Vec::with_capacity(node.edge_ids.len());
+        for id in &node.edge_ids {
+            let edge = EdgeRecord::decode(&payload((ChunkType::Edge, *id))?).ok()?;
+            edges.push(EdgeOutput {
+                label: text(edge.label)?,
+                dest_idx: *self.positions.get(&edge.dest)? as u32,
+            });
+        }
+        Some(NodeOutput {
+            content,
+            edges,
+            unlocked: Vec::new(),
+        })
+    }
+}
@@ -0,0 +1,302 @@
+//! # Chunk Cache
+//!
+//! LRU cache keyed by `(ChunkType, id)`, bounded by the total weight
+//! (size in bytes) of the cached values rather than their count. It holds
+//! raw chunk bytes as well as decoded records (see the `decoded` module);
+//! callers state each value's weight on insert.
+//!
+//! Entries live in a slab threaded as a doubly linked list (most recently
+//! used at the head) and are found through a `HashMap`, so `get`, `insert`
+//! and each eviction are O(1). Pinned chunks are kept outside the list and
+//! are never evicted; they still count towards the byte total, so
+//! pinning more than the budget leaves no room for anything else.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::decoder::ChunkType;
+
+/// Cache key: chunk type and 3-byte chunk ID.
+pub(crate) type ChunkKey = (ChunkType, [u8; 3]);
+
+/// Default byte budget of a file's raw chunk cache.
+pub(crate) const DEFAULT_CACHE_BUDGET: usize = 8 * 1024 * 1024;
+/// Default byte budget of a file's decoded record cache.
+pub(crate) const DEFAULT_DECODED_BUDGET: usize = 4 * 1024 * 1024;
+
+/// Marks the end of the list.
+const NIL: usize = usize::MAX;
+
+/// One cached value and its list neighbours. `value` is `None` while the
+/// slot is free.
+struct Slot<V> {
+    key: ChunkKey,
+    value: Option<V>,
+    weight: usize,
+    prev: usize,
+    next: usize,
+}
+
+/// Byte-budgeted LRU cache of chunk values; raw chunk bytes by default.
+pub(crate) struct ChunkCache<V = Arc<Vec<u8>>> {
+    slots: Vec<Slot<V>>,
+    /// Indices of unused slots in `slots`.
+    free: Vec<usize>,
+    map: HashMap<ChunkKey, usize>,
+    head: usize,
+    tail: usize,
+    pinned: HashMap<ChunkKey, (V, usize)>,
+    /// Keys to pin, including ones not cached yet.
+    pin_keys: HashSet<ChunkKey>,
+    /// Total weight of all cached values, pinned ones included.
+    bytes: usize,
+    budget: usize,
+}
+
+impl<V: Clone> ChunkCache<V> {
+    /// Creates an empty cache holding at most `budget` bytes.
+    pub(crate) fn new(budget: usize) -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            map: HashMap::new(),
+            head: NIL,
+            tail: NIL,
+            pinned: HashMap::new(),
+            pin_keys: HashSet::new(),
+            bytes: 0,
+            budget,
+        }
+    }
+
+    /// Creates an empty cache with the same budget and pins as this one.
+    pub(crate) fn empty_like(&self) -> Self {
+        let mut cache = Self::new(self.budget);
+        cache.pin_keys = self.pin_keys.clone();
+        cache
+    }
+
+    /// Changes the byte budget, evicting least recently used chunks if
+    /// the cache is now over it.
+    pub(crate) fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict();
+    }
+
+    /// Returns the chunk for `key`, marking it most recently used.
+    pub(crate) fn get(&mut self, key: &ChunkKey) -> Option<V> {
+        if let Some((v, _)) = self.pinned.get(key) {
+            return Some(v.clone());
+        }
+        let i = *self.map.get(key)?;
+        self.unlink(i);
+        self.push_front(i);
+        self.slots[i].value.clone()
+    }
+
+    /// Returns `true` if `key` is cached, without touching its recency.
+    pub(crate) fn contains(&self, key: &ChunkKey) -> bool {
+        self.pinned.contains_key(key) || self.map.contains_key(key)
+    }
+
+    /// Caches `value` under `key`, counting `weight` bytes against the
+    /// budget, then evicts down to the budget.
+    ///
+    /// Unpinned values heavier than the whole budget are not cached.
+    pub(crate) fn insert(&mut self, key: ChunkKey, value: V, weight: usize) {
+        if self.pin_keys.contains(&key) {
+            self.bytes += weight;
+            if let Some((_, old)) = self.pinned.insert(key, (value, weight)) {
+                self.bytes -= old;
+            }
+            return;
+        }
+        if let Some(&i) = self.map.get(&key) {
+            self.bytes = self.bytes - self.slots[i].weight + weight;
+            self.slots[i].value = Some(value);
+            self.slots[i].weight = weight;
+            self.unlink(i);
+            self.push_front(i);
+        } else if weight <= self.budget {
+            self.bytes += weight;
+            let slot = Slot {
+                key,
+                value: Some(value),
+                weight,
+                prev: NIL,
+                next: NIL,
+            };
+            let i = match self.free.pop() {
+                Some(i) => {
+                    self.slots[i] = slot;
+                    i
+                }
+                None => {
+                    self.slots.push(slot);
+                    self.slots.len() - 1
+                }
+            };
+            self.map.insert(key, i);
+            self.push_front(i);
+        }
+        self.evict();
+    }
+
+    /// Pins `key` so it is never evicted, now or once it is inserted.
+    pub(crate) fn pin(&mut self, key: ChunkKey) {
+        self.pin_keys.insert(key);
+        if let Some(i) = self.map.remove(&key) {
+            self.unlink(i);
+            self.free.push(i);
+            if let Some(value) = self.slots[i].value.take() {
+                self.pinned.insert(key, (value, self.slots[i].weight));
+            }
+        }
+    }
+
+    /// Evicts least recently used unpinned values while over budget.
+    fn evict(&mut self) {
+        while self.bytes > self.budget && self.tail != NIL {
+            let i = self.tail;
+            self.unlink(i);
+            let slot = &mut self.slots[i];
+            self.bytes -= slot.weight;
+            slot.value = None;
+            self.map.remove(&slot.key);
+            self.free.push(i);
+        }
+    }
+
+    fn unlink(&mut self, i: usize) {
+        let (prev, next) = (self.slots[i].prev, self.slots[i].next);
+        match prev {
+            NIL => self.head = next,
+            p => self.slots[p].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            n => self.slots[n].prev = prev,
+        }
+    }
+
+    fn push_front(&mut self, i: usize) {
+        self.slots[i].prev = NIL;
+        self.slots[i].next = self.head;
+        match self.head {
+            NIL => self.tail = i,
+            h => self.slots[h].prev = i,
+        }
+        self.head = i;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(id: u8) -> ChunkKey {
+        (ChunkType::Content, [0, 0, id])
+    }
+
+    /// Keys of `ids` that are cached.
+    fn cached(cache: &ChunkCache<u8>, ids: &[u8]) -> Vec<u8> {
+        ids.iter().copied().filter(|&id| cache.contains(&key(id))).collect()
+    }
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let mut cache = ChunkCache::new(30);
+        for id in 1..=3 {
+            cache.insert(key(id), id, 10);
+        }
+        assert_eq!(cache.get(&key(1)), Some(1));
+        cache.insert(key(4), 4, 10);
+        assert_eq!(cached(&cache, &[1, 2, 3, 4]), [1, 3, 4]);
+        cache.insert(key(5), 5, 20);
+        assert_eq!(cached(&cache, &[1, 2, 3, 4, 5]), [4, 5]);
+    }
+
+    #[test]
+    fn contains_does_not_touch_recency() {
+        let mut cache = ChunkCache::new(20);
+        cache.insert(key(1), 1, 10);
+        cache.insert(key(2), 2, 10);
+        assert!(cache.contains(&key(1)));
+        cache.insert(key(3), 3, 10);
+        assert_eq!(cached(&cache, &[1, 2, 3]), [2, 3]);
+    }
+
+    #[test]
+    fn skips_values_over_the_budget() {
+        let mut cache = ChunkCache::new(30);
+        cache.insert(key(1), 1, 10);
+        cache.insert(key(2), 2, 31);
+        assert_eq!(cached(&cache, &[1, 2]), [1]);
+        cache.insert(key(3), 3, 30);
+        assert_eq!(cached(&cache, &[1, 2, 3]), [3]);
+    }
+
+    #[test]
+    fn reinsert_replaces_value_and_weight() {
+        let mut cache = ChunkCache::new(30);
+        cache.insert(key(1), 1, 10);
+        cache.insert(key(2), 2, 10);
+        cache.insert(key(1), 11, 20);
+        assert_eq!(cache.bytes, 30);
+        assert_eq!(cache.get(&key(1)), Some(11));
+        cache.insert(key(2), 12, 5);
+        assert_eq!(cache.bytes, 25);
+        // Re-inserting made 2 the most recently used, so 1 goes first.
+        cache.insert(key(3), 3, 10);
+        assert_eq!(cached(&cache, &[1, 2, 3]), [2, 3]);
+        assert_eq!(cache.bytes, 15);
+    }
+
+    #[test]
+    fn pinned_values_are_never_evicted() {
+        let mut cache = ChunkCache::new(20);
+        cache.pin(key(1));
+        cache.insert(key(1), 1, 10);
+        cache.insert(key(2), 2, 10);
+        cache.pin(key(2));
+        cache.insert(key(3), 3, 10);
+        assert_eq!(cached(&cache, &[1, 2, 3]), [1, 2]);
+        // Pins count towards the budget even when they exceed it.
+        cache.pin(key(4));
+        cache.insert(key(4), 4, 50);
+        assert_eq!(cache.bytes, 70);
+        assert_eq!(cache.get(&key(4)), Some(4));
+        cache.insert(key(4), 5, 5);
+        assert_eq!(cache.bytes, 25);
+    }
+
+    #[test]
+    fn empty_like_keeps_pins_and_budget() {
+        let mut cache = ChunkCache::new(10);
+        cache.pin(key(1));
+        cache.insert(key(1), 1, 10);
+        let mut copy = cache.empty_like();
+        assert!(!copy.contains(&key(1)));
+        copy.insert(key(2), 2, 10);
+        copy.insert(key(1), 1, 10);
+        copy.insert(key(3), 3, 10);
+        assert_eq!(cached(&copy, &[1, 2, 3]), [1]);
+    }
+
+    #[test]
+    fn set_budget_evicts_down_to_it() {
+        let mut cache = ChunkCache::new(40);
+        cache.pin(key(9));
+        cache.insert(key(9), 9, 5);
+        for id in 1..=3 {
+            cache.insert(key(id), id, 10);
+        }
+        cache.set_budget(25);
+        assert_eq!(cached(&cache, &[1, 2, 3, 9]), [2, 3, 9]);
+        cache.set_budget(0);
+        assert_eq!(cached(&cache, &[1, 2, 3, 9]), [9]);
+        cache.set_budget(100);
+        cache.insert(key(1), 1, 10);
+        assert_eq!(cached(&cache, &[1, 9]), [1, 9]);
+    }
+}
@@ -0,0 +1,114 @@
+//! # Decoded Records
+//!
+//! Parsed forms of node, edge and content chunks. Each mounted file keeps
+//! them in a second-level cache next to its raw chunk cache, so showing a
+//! node again costs no TLV parsing, decompression or UTF-8 decoding.
+
+use std::rc::Rc;
+
+use crate::decoder::{ContentEntry, CyoaGame, FuncCall, GameError};
+
+/// A parsed `ChunkType::Node` payload.
+pub(crate) struct NodeRecord {
+    /// Content segments with their guards, in display order.
+    pub(crate) content_seq: Vec<ContentEntry>,
+    /// Functions run when the node is entered.
+    pub(crate) entry_funcs: Vec<FuncCall>,
+    /// IDs of the node's edge chunks, in choice order.
+    pub(crate) edge_ids: Vec<[u8; 3]>,
+    /// Key/value tags, e.g. `("chapter", "3")`.
+    pub(crate) tags: Vec<(String, String)>,
+}
+
+/// A parsed `ChunkType::Edge` payload.
+pub(crate) struct EdgeRecord {
+    /// Content chunk holding the choice label.
+    pub(crate) label: [u8; 3],
+    /// Destination node, or link, ID.
+    pub(crate) dest: [u8; 3],
+    /// Effects applied when the edge is taken.
+    pub(crate) effects: Vec<FuncCall>,
+}
+
+/// The text of a `ChunkType::Content` payload.
+pub(crate) struct ContentText(pub(crate) String);
+
+/// Any cached record.
+#[derive(Clone)]
+pub(crate) enum Decoded {
+    Node(Rc<NodeRecord>),
+    Edge(Rc<EdgeRecord>),
+    Text(Rc<ContentText>),
+}
+
+/// A record type that can be decoded from a chunk payload and stored in
+/// the decoded cache.
+pub(crate) trait Record: Sized {
+    /// Parses a decompressed chunk payload.
+    fn decode(payload: &[u8]) -> Result<Self, GameError>;
+    /// Wraps the record for the cache.
+    fn wrap(rec: Rc<Self>) -> Decoded;
+    /// Extracts the record from a cache value of the matching kind.
+    fn unwrap(value: &Decoded) -> Option<Rc<Self>>;
+}
+
+impl Record for NodeRecord {
+    fn decode(payload: &[u8]) -> Result<Self, GameError> {
+        Ok(NodeRecord {
+            content_seq: CyoaGame::parse_node_content_seq(payload)?,
+            entry_funcs: CyoaGame::parse_node_entry_funcs(payload)?,
+            edge_ids: CyoaGame::parse_node_edges_ids(payload)?,
+            tags: CyoaGame::parse_node_tags(payload)?,
+        })
+    }
+
+    fn wrap(rec: Rc<Self>) -> Decoded {
+        Decoded::Node(rec)
+    }
+
+    fn unwrap(value: &Decoded) -> Option<Rc<Self>> {
+        match value {
+            Decoded::Node(n) => Some(n.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl Record for EdgeRecord {
+    fn decode(payload: &[u8]) -> Result<Self, GameError> {
+        let (label, dest) = CyoaGame::parse_edge_label_dest_cids(payload)?;
+        Ok(EdgeRecord {
+            label,
+            dest,
+            effects: CyoaGame::parse_edge_effects(payload)?,
+        })
+    }
+
+    fn wrap(rec: Rc<Self>) -> Decoded {
+        Decoded::Edge(rec)
+    }
+
+    fn unwrap(value: &Decoded) -> Option<Rc<Self>> {
+        match value {
+            Decoded::Edge(e) => Some(e.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl Record for ContentText {
+    fn decode(payload: &[u8]) -> Result<Self, GameError> {
+        Ok(ContentText(CyoaGame::parse_content_text(payload)?))
+    }
+
+    fn wrap(rec: Rc<Self>) -> Decoded {
+        Decoded::Text(rec)
+    }
+
+    fn unwrap(value: &Decoded) -> Option<Rc<Self>> {
+        match value {
+            Decoded::Text(t) => Some(t.clone()),
+            _ => None,
+        }
+    }
+}
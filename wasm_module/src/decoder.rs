@@ -3,60 +3,147 @@
 //! This module implements `CyoaGame`, a Rust/WASM binding for loading,
 //! parsing, and navigating “Choose Your Own Adventure” game data stored
 //! in a custom TLV-packed binary format. It uses HTTP range requests
-//! for efficient on-demand fetching, an LRU cache for raw chunks,
-//! and zstd for optional compression.
+//! for efficient on-demand fetching, byte-budgeted LRU caches for raw
+//! chunks and their decoded records, and zstd for optional compression.
 //!
 //! ## Features
-//! - Probe remote file for size and range-request support
+//! - Probe remote file for size and range-request support, falling back to
+//!   a single whole-file download on servers without it
 //! - Fetch only the header and index, then lazily load nodes & edges
-//! - Merge contiguous ranges into single HTTP requests
+//! - Show the root node from an optional bootstrap section read with the
+//!   header, before the index arrives
+//! - Open stories from in-memory bytes or a local `Blob`/`File`
+//! - Load a node in one request from its optional page chunk
+//! - Merge contiguous and near-contiguous chunk ranges into single HTTP requests
+//! - Cap concurrent requests, serving the visible node before prefetching
+//!   and offline downloads
+//! - Time out, retry with backoff, and abort network requests
+//! - Detect the story file being replaced mid-session with `If-Range`
+//! - Trace each node load (requests, bytes, cache hits, network, zstd and
+//!   parse time), keep running totals, and log at a runtime-settable level
+//! - Persist downloaded chunks across sessions, keyed by story fingerprint
+//! - Download whole stories or single chapters for offline play, with
+//!   progress, cancellation, resume and an integrity check
+//! - Mount expansion story files and follow edges across them by namespace
 //! - Full WASM-bindgen exports for use from JavaScript
-//! - Structured errors mapped to `JsValue` with human-readable messages
+//! - Errors thrown as JS `Error` objects with a stable code and the chunk,
+//!   node and byte offset involved
 
 use byteorder::{LittleEndian, ReadBytesExt};
-use futures::future::try_join_all;
-use js_sys::{Array, Uint8Array};
+use futures::future::{LocalBoxFuture, Shared, join_all, try_join_all};
+use futures::FutureExt;
+use futures::channel::oneshot;
+use std::future::Future;
+use js_sys::{Array, Function, Reflect, Uint8Array};
 use serde::Serialize;
 use serde_wasm_bindgen::to_value;
-use std::cell::RefCell;
-use std::collections::VecDeque;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::collections::{HashMap, HashSet};
 use std::io::{Cursor, Read, Seek, SeekFrom};
 use std::sync::Arc;
 use wasm_bindgen::JsCast;
 use wasm_bindgen::prelude::*;
-use wasm_bindgen_futures::JsFuture;
-use web_sys::{Headers, RequestInit, RequestMode, Response, Window, window};
+use wasm_bindgen_futures::{JsFuture, spawn_local};
+use web_sys::{Blob, File};
 use zstd_safe::decompress;
-use crate::wasmtable::run_guard;
+use crate::achievements::{
+    Achievement, AchievementOutput, UnlockStore, parse_catalog,
+};
+use crate::metadata::{
+    ID_ROOT_POINTER, MetaKey, MetaValue, StoryMetadata, Version, parse_value,
+};
+use crate::bootstrap::{BOOTSTRAP_WINDOW, Bootstrap};
+use crate::page::{self, Bundle};
+use crate::cache::{ChunkCache, ChunkKey, DEFAULT_CACHE_BUDGET, DEFAULT_DECODED_BUDGET};
+use crate::engine;
+use crate::decoded::{ContentText, Decoded, EdgeRecord, NodeRecord, Record};
+use crate::errors::{ErrorContext, detach};
+use crate::logging::{self, Level, LogLevel};
+use crate::patch::{Patch, PatchError, PatchOp, fnv1a64};
+use crate::net::{CyoaGameOptions, Net, RetryPolicy};
+use crate::ranges::plan_ranges;
+use crate::sched::{DEFAULT_MAX_CONCURRENCY, Priority, Scheduler};
+use crate::store::{CacheApiStore, ChunkStore, MemoryStore, StoryKey};
+use crate::trace::{self, Stats};
+use crate::reader::{EdgeOutput, NodeOutput};
+use crate::utils::hex_id;
 
 // -- Type-safe enums and structured errors --
 
 /// All possible chunk types in the CYOA file format.
 #[repr(u8)]
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum ChunkType {
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum ChunkType {
+    /// Tombstone of an entry a patch removed; never stored on disk. Kept
+    /// in the index so later global node indices do not shift.
+    Removed = 0x00,
     /// A content node holding text and edge references.
     Node = 0x01,
     /// A binary blob encoding one edge’s metadata.
     Edge = 0x02,
     /// A text payload (e.g. node text or edge label).
     Content = 0x03,
-    /// Metadata chunks (e.g. root-pointer).
+    /// Metadata chunks keyed by `MetaKey` (e.g. root-pointer, title).
     Metadata = 0x04,
+    /// Achievements catalog (ID, title, description, hidden flag).
+    Achievements = 0x05,
+    /// Link to a node in another mounted story file, by namespace.
+    Link = 0x06,
+    /// Copies of the chunks the first screen needs, right after the
+    /// header; see the `bootstrap` module.
+    Bootstrap = 0x07,
+    /// Copies of the chunks one node needs, under the node's ID; see the
+    /// `page` module.
+    Page = 0x08,
     /// Pool of argument blobs (internal use).
     ArgBlobPool = 0xFD,
     /// WASM table data (internal use).
     WasmTable = 0xFE,
 }
 
+impl ChunkType {
+    /// Maps an on-disk type byte to its `ChunkType`, if known.
+    pub(crate) fn from_u8(t: u8) -> Option<ChunkType> {
+        Some(match t {
+            0x01 => ChunkType::Node,
+            0x02 => ChunkType::Edge,
+            0x03 => ChunkType::Content,
+            0x04 => ChunkType::Metadata,
+            0x05 => ChunkType::Achievements,
+            0x06 => ChunkType::Link,
+            0x07 => ChunkType::Bootstrap,
+            0x08 => ChunkType::Page,
+            0xFD => ChunkType::ArgBlobPool,
+            0xFE => ChunkType::WasmTable,
+            _ => return None,
+        })
+    }
+
+    /// Returns the name JavaScript errors report as `chunkType`.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            ChunkType::Removed => "removed",
+            ChunkType::Node => "node",
+            ChunkType::Edge => "edge",
+            ChunkType::Content => "content",
+            ChunkType::Metadata => "metadata",
+            ChunkType::Achievements => "achievements",
+            ChunkType::Link => "link",
+            ChunkType::Bootstrap => "bootstrap",
+            ChunkType::Page => "page",
+            ChunkType::ArgBlobPool => "arg_blob_pool",
+            ChunkType::WasmTable => "wasm_table",
+        }
+    }
+}
+
 /// Errors that can occur while probing, fetching,
 /// or parsing the CYOA file.
 #[derive(Debug)]
-enum GameError {
+pub(crate) enum GameError {
     /// Non-200 HTTP response, with status code.
     Http(u16),
-    /// Server does not support HTTP range requests.
-    RangeNotSupported,
     /// File magic header did not match `CYOA`.
     InvalidMagic,
     /// Index pointer points past end of file.
@@ -65,47 +152,126 @@ enum GameError {
     Parse(&'static str),
     /// Root pointer metadata chunk was not found.
     MissingRoot,
+    /// A story file's engine or dependency requirements are not met.
+    Dependency(String),
+    /// A patch file was malformed or does not apply to any mounted story.
+    Patch(String),
+    /// An offline download was cancelled with `cancel_download`, or a
+    /// queued request was dropped by the scheduler.
+    Cancelled,
+    /// A request failed before any response, with the browser's message.
+    Network(String),
+    /// A request ran past the network policy's timeout.
+    Timeout,
+    /// A request was aborted with `CyoaGame::abort`.
+    Aborted,
+    /// A response did not hold the requested bytes.
+    BadResponse(String),
+    /// The story file on the server was replaced since it was opened.
+    StoryChanged,
     /// Other errors, with textual detail.
     Other(String),
 }
 
-impl From<GameError> for JsValue {
-    fn from(err: GameError) -> JsValue {
-        // Maps each GameError variant to a JS exception string.
-        match err {
-            GameError::Http(code) => JsValue::from_str(&format!("HTTP error: {}", code)),
-            GameError::InvalidMagic => JsValue::from_str("Invalid file magic"),
-            GameError::IndexOutOfRange => JsValue::from_str("Index out of range"),
-            GameError::RangeNotSupported => JsValue::from_str("Range requests not supported"),
-            GameError::MissingRoot => JsValue::from_str("Root pointer metadata missing"),
-            GameError::Parse(msg) => JsValue::from_str(msg),
-            GameError::Other(s) => JsValue::from_str(&s),
+impl std::fmt::Display for GameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The human-readable message of each GameError variant.
+        match self {
+            GameError::Http(code) => write!(f, "HTTP error: {}", code),
+            GameError::InvalidMagic => write!(f, "Invalid file magic"),
+            GameError::IndexOutOfRange => write!(f, "Index out of range"),
+            GameError::MissingRoot => write!(f, "Root pointer metadata missing"),
+            GameError::Dependency(s) => write!(f, "Unmet dependency: {}", s),
+            GameError::Patch(s) => write!(f, "Patch error: {}", s),
+            GameError::Parse(msg) => write!(f, "{}", msg),
+            GameError::Cancelled => write!(f, "Request cancelled"),
+            GameError::Network(s) => write!(f, "Network error: {}", s),
+            GameError::Timeout => write!(f, "Request timed out"),
+            GameError::Aborted => write!(f, "Request aborted"),
+            GameError::BadResponse(s) => write!(f, "Bad range response: {}", s),
+            GameError::StoryChanged => write!(f, "Story file changed on the server"),
+            GameError::Other(s) => write!(f, "{}", s),
         }
     }
 }
 
-/// Logs debug messages to the browser console when
-/// compiled with `debug_assertions`.
-macro_rules! log_debug {
-    ($($arg:tt)*) => {{
-        if cfg!(debug_assertions) {
-            web_sys::console::log_1(&JsValue::from_str(&format!($($arg)*)));
+impl GameError {
+    /// Returns the stable code JavaScript sees as the error's `code`; one
+    /// of the `CyoaErrorCode` union.
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            GameError::Http(_) => "HTTP",
+            GameError::InvalidMagic => "INVALID_MAGIC",
+            GameError::IndexOutOfRange => "INDEX_OUT_OF_RANGE",
+            GameError::Parse(_) => "PARSE",
+            GameError::MissingRoot => "MISSING_ROOT",
+            GameError::Dependency(_) => "DEPENDENCY",
+            GameError::Patch(_) => "PATCH",
+            GameError::Cancelled => "CANCELLED",
+            GameError::Network(_) => "NETWORK",
+            GameError::Timeout => "TIMEOUT",
+            GameError::Aborted => "ABORTED",
+            GameError::BadResponse(_) => "BAD_RESPONSE",
+            GameError::StoryChanged => "STORY_CHANGED",
+            GameError::Other(_) => "OTHER",
         }
-    }};
+    }
+
+    /// Returns `true` for failures worth retrying: network errors,
+    /// timeouts, unusable range responses and 5xx responses.
+    pub(crate) fn is_transient(&self) -> bool {
+        match self {
+            GameError::Network(_) | GameError::Timeout | GameError::BadResponse(_) => true,
+            GameError::Http(status) => *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+impl From<PatchError> for GameError {
+    fn from(err: PatchError) -> GameError {
+        GameError::Patch(err.to_string())
+    }
 }
 
 /// Number of bytes in the fixed CYOA header.
-const HEADER_LEN: usize = 22;
-/// ID used in metadata to point to the root node.
-const ID_ROOT_POINTER: [u8; 3] = [0, 0, 1];
+pub(crate) const HEADER_LEN: usize = 22;
+
+/// Parsed TLV chunk header:
+/// `(type, id, flags, compressed_len, uncompressed_len_opt, header_len)`.
+type TlvHeader = (u8, [u8; 3], u8, u32, Option<u32>, usize);
 
 /// One entry in the on-disk index: type, ID, offset and length.
 #[derive(Clone, Debug)]
-struct IndexEntry {
-    chunk_type: ChunkType,
-    chunk_id: [u8; 3],
-    offset: u64,
-    length: u32,
+pub(crate) struct IndexEntry {
+    pub(crate) chunk_type: ChunkType,
+    pub(crate) chunk_id: [u8; 3],
+    pub(crate) offset: u64,
+    pub(crate) length: u32,
+}
+
+impl IndexEntry {
+    /// The entry's chunk cache key.
+    pub(crate) fn key(&self) -> ChunkKey {
+        (self.chunk_type, self.chunk_id)
+    }
+
+    /// Returns `true` if `raw` is plausibly this entry's chunk: it has the
+    /// indexed length and a TLV header naming this type and ID whose
+    /// payload fills the rest.
+    fn is_intact(&self, raw: &[u8]) -> bool {
+        if raw.len() != self.length as usize {
+            return false;
+        }
+        match CyoaGame::parse_tlv_header(raw) {
+            Ok((t, id, _flags, comp_len, _un, hdr_len)) => {
+                t == self.chunk_type as u8
+                    && id == self.chunk_id
+                    && hdr_len + comp_len as usize == raw.len()
+            }
+            Err(_) => false,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -117,314 +283,1998 @@ pub struct ContentEntry {
     pub content_id: [u8; 3],
 }
 
-/// Represents one outgoing edge from a node.
-#[derive(Serialize)]
-struct EdgeOutput {
-    /// Text label shown for this choice.
-    label: String,
-    /// Index of the node this edge points to.
-    dest_idx: u32,
+/// The fields of a Node payload ahead of its content_sequence, in file
+/// order (the node ID comes first); see `CyoaGame::skip_node_fields`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum NodeField {
+    Language,
+    Tags,
+    EntryFuncs,
+    Edges,
+    Translations,
 }
 
-/// The in‐memory representation of a game node:
-/// its content text plus all outgoing edges.
-#[derive(Serialize)]
-struct NodeOutput {
-    /// The narrative or choice text.
-    content: String,
-    /// All outgoing edges (choices).
-    edges: Vec<EdgeOutput>,
+/// A function reference (entry function, guard or effect) together with
+/// its argument bytes.
+pub(crate) type FuncCall = (u32, Vec<u8>);
+
+/// Approximate bytes fetched per round of an offline download.
+const DOWNLOAD_BATCH: u64 = 1024 * 1024;
+
+/// Progress of an offline download, as passed to the JS callback.
+#[derive(Clone, Debug, Default, Serialize)]
+struct DownloadProgress {
+    bytes_done: u64,
+    bytes_total: u64,
+    chunks_done: u32,
+    chunks_total: u32,
+}
+
+impl DownloadProgress {
+    /// Counts `entries` as done.
+    fn add(&mut self, entries: &[&IndexEntry]) {
+        for e in entries {
+            self.bytes_done += e.length as u64;
+            self.chunks_done += 1;
+        }
+    }
 }
 
-/// Simple LRU cache of raw chunk blobs, keyed by ID.
-struct RawCache {
-    entries: VecDeque<([u8; 3], Arc<Vec<u8>>)>,
-    capacity: usize,
+/// Name given to stories opened from bytes or a blob without one.
+const LOCAL_NAME: &str = "local.story";
+
+/// Default largest gap, in bytes, bridged when merging chunk ranges.
+const DEFAULT_RANGE_GAP: u32 = 1024;
+
+/// Settings for background prefetching of a node's neighbours.
+#[derive(Clone, Copy, Debug)]
+struct PrefetchConfig {
+    /// How many edges away from the displayed node to prefetch; `0`
+    /// disables prefetching.
+    depth: u32,
+    /// Maximum bytes fetched by one prefetch pass.
+    byte_budget: u32,
 }
 
-impl RawCache {
-    /// Create a new cache with the given capacity.
-    fn new(cap: usize) -> Self {
+impl Default for PrefetchConfig {
+    fn default() -> Self {
         Self {
-            entries: VecDeque::new(),
-            capacity: cap,
+            depth: 1,
+            byte_budget: 256 * 1024,
         }
     }
+}
 
-    /// Get a chunk by key, bumping it to the front if found.
-    fn get(&mut self, key: &[u8; 3]) -> Option<Arc<Vec<u8>>> {
-        if let Some(pos) = self.entries.iter().position(|(k, _)| k == key) {
-            let (k, v) = self.entries.remove(pos).unwrap();
-            self.entries.push_front((k, v.clone()));
-            return Some(v);
-        }
-        None
+/// A chunk fetch in progress, shared by every caller waiting for it.
+type PendingChunk = Shared<LocalBoxFuture<'static, Result<Arc<Vec<u8>>, JsValue>>>;
+
+/// In-flight chunk fetches and the priority each is queued at.
+type InflightTable = HashMap<ChunkKey, (PendingChunk, Rc<Cell<Priority>>)>;
+
+/// A caller waiting for another caller's mount of the same URL.
+type MountWaiter = oneshot::Sender<Result<Rc<StoryFile>, JsValue>>;
+
+/// Marks a URL as being mounted in `CyoaGame::mounting` until the mount
+/// finishes. Dropping it unfinished (the mount future was dropped) clears
+/// the entry, which wakes the waiters with a cancellation.
+struct PendingMount<'a> {
+    table: &'a RefCell<HashMap<String, Vec<MountWaiter>>>,
+    url: String,
+}
+
+impl<'a> PendingMount<'a> {
+    fn start(table: &'a RefCell<HashMap<String, Vec<MountWaiter>>>, url: String) -> Self {
+        table.borrow_mut().insert(url.clone(), Vec::new());
+        PendingMount { table, url }
     }
-    /// Insert a new chunk, evicting the oldest if full.
-    fn insert(&mut self, key: [u8; 3], value: Arc<Vec<u8>>) {
-        if self.entries.len() == self.capacity {
-            self.entries.pop_back();
+
+    /// Hands `result` to every caller that joined this mount.
+    fn finish(self, result: &Result<Rc<StoryFile>, JsValue>) {
+        let waiters = self.table.borrow_mut().remove(&self.url).unwrap_or_default();
+        for tx in waiters {
+            let _ = tx.send(result.clone());
         }
-        self.entries.push_front((key, value));
     }
 }
 
-/// The main game loader exposed to JavaScript via wasm_bindgen.
-/// Handles probing, range-requests, parsing TLV, zstd decompression,
-/// and exposes `load_root_node_full` / `load_node_full` APIs.
-#[wasm_bindgen]
-pub struct CyoaGame {
+impl Drop for PendingMount<'_> {
+    fn drop(&mut self) {
+        self.table.borrow_mut().remove(&self.url);
+    }
+}
+
+/// One mounted `.story` file: its location, parsed index and chunk cache.
+///
+/// A game mounts the base story plus any number of expansions. Node
+/// indices handed to JavaScript are global: each file's index entries are
+/// numbered after those of every file mounted before it.
+struct StoryFile {
     url: String,
     size: u64,
     supports_range: bool,
+    /// `ETag` or `Last-Modified` seen by the probe, sent as `If-Range` so
+    /// chunks are never read from a newer file than the index.
+    validator: Option<String>,
+    /// The whole file, when the server lacks Range support and it was
+    /// downloaded in one go. Chunks are then served by slicing.
+    whole: Option<Arc<Vec<u8>>>,
+    /// Local `Blob`/`File` the story was opened from; chunks are read from
+    /// it lazily with `slice()`.
+    blob: Option<Blob>,
     index: Vec<IndexEntry>,
-    raw_cache: RefCell<RawCache>,
+    raw_cache: RefCell<ChunkCache>,
+    /// Decoded node, edge and content records; replaced together with
+    /// `raw_cache`.
+    decoded: RefCell<ChunkCache<Decoded>>,
+    /// Persistent store and this file's key in it; set once the file is
+    /// mounted, and never for local files.
+    store: RefCell<Option<(Rc<dyn ChunkStore>, StoryKey)>>,
+    /// Chunks being fetched right now. Concurrent callers for the same
+    /// chunk await the one pending fetch instead of starting another.
+    inflight: Rc<RefCell<InflightTable>>,
+    /// Scheduler network requests queue on; the game's shared one once
+    /// the file is mounted.
+    sched: RefCell<Rc<Scheduler>>,
+    /// Timeouts, retries and abort signal for network requests; the
+    /// game's shared one once the file is mounted.
+    net: RefCell<Rc<Net>>,
+    /// Where cache hits, requests and decoding time are counted; the
+    /// game's shared one once the file is mounted.
+    stats: RefCell<Rc<Stats>>,
+    metadata: RefCell<Option<Rc<StoryMetadata>>>,
+    /// FNV-1a hash of the index blob, the fallback fingerprint.
+    index_hash: u64,
+    /// Chunks supplied by applied patches, served instead of the file.
+    overlay: HashMap<(ChunkType, [u8; 3]), Arc<Vec<u8>>>,
+    /// Fingerprint taken on from the last applied patch.
+    patched_fingerprint: Option<u64>,
 }
 
-#[wasm_bindgen]
-impl CyoaGame {
-    /// Constructs a new `CyoaGame` instance by probing the remote file
-    /// at `path` for its total size and HTTP Range support, then fetching
-    /// and parsing the on‐disk index.
-    ///
-    /// # Parameters
-    ///
-    /// - `path`: URL or filesystem path (relative to the site root) of
-    ///   the `.cyoa` binary file.
-    ///
-    /// # Returns
-    ///
-    /// - `Ok(CyoaGame)`: if the file was probed successfully and its index
-    ///   parsed without error.
-    /// - `Err(JsValue)`: if there was any HTTP error, missing range support,
-    ///   invalid magic, out‐of‐range index pointer, or parse failure.
-    ///
-    /// # Examples
-    ///
-    /// ```ignore
-    /// // In JavaScript:
-    /// const game = await new CyoaGame("/games/mystory.cy");
-    /// ```
-    #[wasm_bindgen(constructor)]
-    pub async fn new(path: String) -> Result<CyoaGame, JsValue> {
-        let url = if path.starts_with('/') {
-            path.clone()
-        } else {
-            format!("/{}", path)
+impl StoryFile {
+    /// Probes the file at `url` for its size and Range support, then
+    /// fetches and parses its header and index.
+    ///
+    /// If the server answers the probe without a 206, the probe's own
+    /// response body is kept as the whole file (reporting download
+    /// progress to `on_progress` as `(loaded, total)` if given; `total` is
+    /// 0 when unknown) and the header and index are read from memory.
+    /// If the server reports the size as `*`, it is taken to end where the
+    /// index, the last part of a story file, does.
+    /// Requests follow `net`'s policy and abort signal; every range
+    /// request, from the header on, is pinned to the probed file with
+    /// `If-Range`.
+    ///
+    /// When the size is known, the header request also reads the
+    /// `BOOTSTRAP_WINDOW` bytes after it. If they start a bootstrap chunk,
+    /// the first screen built from it is passed to `on_first_screen`
+    /// before the index is fetched, and its chunks are cached once the
+    /// index confirms them. An exception thrown by the callback is logged
+    /// and the open carries on.
+    async fn open(
+        url: String,
+        on_progress: Option<&Function>,
+        on_first_screen: Option<&Function>,
+        net: &Rc<Net>,
+    ) -> Result<StoryFile, JsValue> {
+        let probe = net.probe(&url).await?;
+        let size = probe.size;
+        if !probe.ranged {
+            log_info!("{} has no Range support; downloading whole file", url);
+            let bytes = probe.body.read(size.unwrap_or(0), on_progress).await?;
+            return Ok(StoryFile::from_bytes(url, bytes)?);
+        }
+        let signal = net.signal();
+        let validator = probe.validator.as_deref();
+        let head_len = match size {
+            Some(size) => (HEADER_LEN as u64 + BOOTSTRAP_WINDOW).min(size),
+            None => HEADER_LEN as u64,
         };
-        let win = window().ok_or(GameError::Other("No window object".to_string()))?;
-        let (size, supports) = Self::probe_range(&win, &url).await.map_err(JsValue::from)?;
-        let header = if supports {
-            Self::fetch_range(&win, &url, 0, Some((HEADER_LEN - 1) as u64)).await?
+        let head = net
+            .fetch_range(signal.as_ref(), &url, 0, Some(head_len - 1), validator)
+            .await?;
+        let index_offset = CyoaGame::parse_header(&head)?;
+        if size.is_some_and(|size| index_offset >= size) {
+            return Err(GameError::IndexOutOfRange.into());
+        }
+        let bootstrap = Self::read_bootstrap(net, &url, &head, index_offset, validator).await;
+        let screen = bootstrap.as_ref().and_then(Bootstrap::first_screen);
+        if let (Some(cb), Some(Ok(value))) = (on_first_screen, screen.map(|s| to_value(&s)))
+            && let Err(e) = cb.call1(&JsValue::NULL, &value)
+        {
+            log_error!("First screen callback threw: {:?}", e);
+        }
+        let idx_blob = net
+            .fetch_range(signal.as_ref(), &url, index_offset, None, validator)
+            .await?;
+        let size = size.unwrap_or(index_offset + idx_blob.len() as u64);
+        let mut file = StoryFile::from_parts(url, size, true, None, None, &idx_blob)?;
+        file.validator = probe.validator;
+        *file.net.borrow_mut() = net.clone();
+        if let Some(bootstrap) = bootstrap {
+            file.seed(bootstrap.chunks);
+        }
+        Ok(file)
+    }
+
+    /// Returns the bootstrap chunk following the header in `head` (the
+    /// first bytes of `url`), fetching whatever part of it `head` lacks.
+    ///
+    /// The bootstrap is optional, so a missing, malformed or unreachable
+    /// one yields `None` (logged) instead of an error.
+    async fn read_bootstrap(
+        net: &Net,
+        url: &str,
+        head: &[u8],
+        index_offset: u64,
+        validator: Option<&str>,
+    ) -> Option<Bootstrap> {
+        let after = head.get(HEADER_LEN..)?;
+        let len = Bootstrap::chunk_len(after)?;
+        if HEADER_LEN as u64 + len as u64 > index_offset {
+            log_warn!("{}: bootstrap overlaps the index; ignoring it", url);
+            return None;
+        }
+        let raw = if after.len() >= len {
+            after[..len].to_vec()
         } else {
-            return Err(GameError::RangeNotSupported.into());
+            let (start, end) = (head.len() as u64, (HEADER_LEN + len - 1) as u64);
+            let signal = net.signal();
+            match net.fetch_range(signal.as_ref(), url, start, Some(end), validator).await {
+                Ok(rest) => [after, &rest].concat(),
+                Err(e) => {
+                    log_warn!("{}: bootstrap fetch failed: {}", url, e);
+                    return None;
+                }
+            }
         };
-        let index_offset = Self::parse_header(&header)?;
+        Bootstrap::parse(&raw)
+            .inspect_err(|e| log_warn!("{}: ignoring malformed bootstrap: {}", url, e))
+            .ok()
+    }
+
+    /// Caches the bundled chunks that match this file's index, so loading
+    /// the node they were bundled for needs no further requests.
+    fn seed(&self, chunks: Bundle) {
+        let mut cache = self.raw_cache.borrow_mut();
+        for (key, raw) in chunks {
+            if self.find_entry(key.0, &key.1).is_some_and(|e| e.is_intact(&raw)) {
+                let len = raw.len();
+                cache.insert(key, raw, len);
+            }
+        }
+    }
+
+    /// Opens a story held entirely in memory. `url` only names the file
+    /// (for mounts and the achievements storage key).
+    fn from_bytes(url: String, bytes: Vec<u8>) -> Result<StoryFile, GameError> {
+        let header = bytes
+            .get(..HEADER_LEN)
+            .ok_or(GameError::Parse("File shorter than header"))?;
+        let index_offset = CyoaGame::parse_header(header)?;
+        if index_offset >= bytes.len() as u64 {
+            return Err(GameError::IndexOutOfRange);
+        }
+        let whole = Arc::new(bytes);
+        let idx_blob = &whole[index_offset as usize..];
+        let size = whole.len() as u64;
+        StoryFile::from_parts(url, size, false, Some(whole.clone()), None, idx_blob)
+    }
 
+    /// Opens a story from a local `Blob` or `File`, reading only its
+    /// header and index up front.
+    async fn from_blob(url: String, blob: Blob) -> Result<StoryFile, JsValue> {
+        let size = blob.size() as u64;
+        let header = CyoaGame::read_blob(&blob, 0, HEADER_LEN as u64).await?;
+        let index_offset = CyoaGame::parse_header(&header)?;
         if index_offset >= size {
             return Err(GameError::IndexOutOfRange.into());
         }
-        let idx_blob = Self::fetch_range(&win, &url, index_offset, None).await?;
+        let idx_blob = CyoaGame::read_blob(&blob, index_offset, size).await?;
+        Ok(StoryFile::from_parts(url, size, false, None, Some(blob), &idx_blob)?)
+    }
 
-        let index = Self::parse_index(&idx_blob).map_err(JsValue::from)?;
-        Ok(CyoaGame {
+    /// Parses `idx_blob` and assembles a file with empty caches.
+    fn from_parts(
+        url: String,
+        size: u64,
+        supports_range: bool,
+        whole: Option<Arc<Vec<u8>>>,
+        blob: Option<Blob>,
+        idx_blob: &[u8],
+    ) -> Result<StoryFile, GameError> {
+        let index = CyoaGame::parse_index(idx_blob)?;
+        Ok(StoryFile {
             url,
             size,
-            supports_range: supports,
+            supports_range,
+            validator: None,
+            whole,
+            blob,
+            index,
+            raw_cache: RefCell::new(ChunkCache::new(DEFAULT_CACHE_BUDGET)),
+            decoded: RefCell::new(ChunkCache::new(DEFAULT_DECODED_BUDGET)),
+            store: RefCell::new(None),
+            inflight: Rc::new(RefCell::new(HashMap::new())),
+            sched: RefCell::new(Scheduler::new(DEFAULT_MAX_CONCURRENCY)),
+            net: RefCell::new(Rc::new(Net::default())),
+            stats: RefCell::new(Rc::new(Stats::default())),
+            metadata: RefCell::new(None),
+            index_hash: fnv1a64(idx_blob),
+            overlay: HashMap::new(),
+            patched_fingerprint: None,
+        })
+    }
+
+    /// Returns `true` if chunks come from memory or a local blob rather
+    /// than the network.
+    fn is_local(&self) -> bool {
+        self.whole.is_some() || self.blob.is_some()
+    }
+
+    /// Returns this file's fingerprint: the last applied patch's target,
+    /// else the fingerprint metadata key, else the index hash.
+    async fn fingerprint(&self) -> Result<u64, JsValue> {
+        if let Some(fp) = self.patched_fingerprint {
+            return Ok(fp);
+        }
+        let meta = self.story_metadata().await?;
+        Ok(meta.fingerprint.unwrap_or(self.index_hash))
+    }
+
+    /// Returns a copy of this file with `patch` applied on top.
+    ///
+    /// Replaced chunks keep their index position; added chunks are
+    /// appended. The copy starts with empty caches that keep this file's
+    /// cache budget and pins.
+    fn with_patch(&self, patch: &Patch) -> Result<StoryFile, GameError> {
+        let mut index = self.index.clone();
+        let mut overlay = self.overlay.clone();
+        for op in &patch.ops {
+            match op {
+                PatchOp::Remove { chunk_type, id } => {
+                    let t = ChunkType::from_u8(*chunk_type)
+                        .ok_or(GameError::Parse("Unknown chunk type"))?;
+                    // Tombstone rather than drop the entry: removing it
+                    // would renumber every later node.
+                    for e in index.iter_mut().filter(|e| e.chunk_type == t && &e.chunk_id == id) {
+                        e.chunk_type = ChunkType::Removed;
+                        e.length = 0;
+                    }
+                    overlay.remove(&(t, *id));
+                }
+                PatchOp::Upsert {
+                    chunk_type,
+                    id,
+                    data,
+                } => {
+                    let t = ChunkType::from_u8(*chunk_type)
+                        .ok_or(GameError::Parse("Unknown chunk type"))?;
+                    let length = data.len() as u32;
+                    match index
+                        .iter_mut()
+                        .find(|e| e.chunk_type == t && &e.chunk_id == id)
+                    {
+                        Some(e) => e.length = length,
+                        None => index.push(IndexEntry {
+                            chunk_type: t,
+                            chunk_id: *id,
+                            offset: 0,
+                            length,
+                        }),
+                    }
+                    overlay.insert((t, *id), Arc::new(data.clone()));
+                }
+            }
+        }
+        Ok(StoryFile {
+            url: self.url.clone(),
+            size: self.size,
+            supports_range: self.supports_range,
+            validator: self.validator.clone(),
+            whole: self.whole.clone(),
+            blob: self.blob.clone(),
             index,
-            raw_cache: RefCell::new(RawCache::new(100)),
+            raw_cache: RefCell::new(self.raw_cache.borrow().empty_like()),
+            decoded: RefCell::new(self.decoded.borrow().empty_like()),
+            // Unpatched chunks still come from the original file, so keep
+            // its store key.
+            store: RefCell::new(self.store.borrow().clone()),
+            inflight: Rc::new(RefCell::new(HashMap::new())),
+            sched: RefCell::new(self.sched.borrow().clone()),
+            net: RefCell::new(self.net.borrow().clone()),
+            stats: RefCell::new(self.stats.borrow().clone()),
+            metadata: RefCell::new(None),
+            index_hash: self.index_hash,
+            overlay,
+            patched_fingerprint: Some(patch.target_fingerprint),
         })
     }
 
-    /// Returns a JavaScript `Array` of all chunk IDs present in the file’s
-    /// parsed index, formatted as uppercase hex strings.
+    /// Retrieves the raw chunk bytes for `entry` using HTTP Range, or by
+    /// slicing the in-memory file or local blob.
+    /// Uses an LRU cache to avoid re-downloading the same chunk, and joins
+    /// a fetch of the same chunk already in flight. Chunks supplied by
+    /// patches are served from memory.
     ///
-    /// Each entry is the 3‐byte chunk identifier, e.g. `"000102"`.
+    /// # Parameters
     ///
-    /// # Examples
+    /// - `entry`: Reference to an `IndexEntry` describing offset and length.
+    /// - `priority`: Class the request is scheduled at if it goes to the
+    ///   network; a fetch joined in flight is raised to it.
     ///
-    /// ```ignore
-    /// let ids = game.chunk_ids();              // ["000001", "000002", …]
-    /// console.log(ids[0]);                     // "000001"
-    /// ```
-    #[wasm_bindgen]
-    pub fn chunk_ids(&self) -> Array {
-        let arr = Array::new();
-        for e in &self.index {
-            let s = e
-                .chunk_id
-                .iter()
-                .map(|b| format!("{:02X}", b))
-                .collect::<String>();
-            arr.push(&JsValue::from_str(&s));
+    /// # Returns
+    ///
+    /// - `Ok(Arc<Vec<u8>>)` of the chunk’s raw bytes.
+    /// - `Err(JsValue)`: On network errors or missing window, or
+    ///   `GameError::Cancelled` if the queued request was cancelled.
+    async fn get_raw_chunk(
+        &self,
+        entry: &IndexEntry,
+        priority: Priority,
+    ) -> Result<Arc<Vec<u8>>, JsValue> {
+        let stats = self.stats.borrow().clone();
+        let count = |hit| stats.chunk(priority, entry.chunk_type, hit);
+        if let Some(patched) = self.overlay.get(&(entry.chunk_type, entry.chunk_id)) {
+            count(true);
+            return Ok(patched.clone());
         }
-        arr
+        if let Some(cached) = self.raw_cache.borrow_mut().get(&entry.key()) {
+            count(true);
+            return Ok(cached);
+        }
+        if entry.offset + entry.length as u64 > self.size {
+            return Err(ErrorContext::chunk(entry).attach(GameError::IndexOutOfRange.into()));
+        }
+        if let Some(whole) = &self.whole {
+            count(true);
+            let start = entry.offset as usize;
+            // Not cached: the file already holds it, and a cached copy
+            // would count against the budget and evict fetched chunks.
+            return Ok(Arc::new(whole[start..start + entry.length as usize].to_vec()));
+        }
+        if let Some(pending) = self.pending(entry, priority) {
+            count(false);
+            return pending.await.map_err(detach);
+        }
+        if let Some(hit) = self.stored(entry).await {
+            count(true);
+            return Ok(hit);
+        }
+        count(false);
+        // The store lookup yielded, so another caller may have started
+        // the fetch meanwhile.
+        let pending = match self.pending(entry, priority) {
+            Some(pending) => pending,
+            None => {
+                let blob = self.blob.clone();
+                let (start, end) = (entry.offset, entry.offset + entry.length as u64);
+                let priority = Rc::new(Cell::new(priority));
+                let fetch = self.fetch_scheduled(priority.clone(), start, end - 1);
+                self.track(entry, priority, async move {
+                    let data = match blob {
+                        Some(blob) => CyoaGame::read_blob(&blob, start, end).await?,
+                        None => fetch.await?,
+                    };
+                    Ok(Arc::new(data))
+                })
+            }
+        };
+        let arc = pending.await.map_err(detach)?;
+        self.raw_cache
+            .borrow_mut()
+            .insert(entry.key(), arc.clone(), arc.len());
+        Ok(arc)
     }
 
-    /// Loads the node at the given index (into the parsed index vector),
-    /// fully fetching its content text and all outgoing edges—with labels
-    /// and destination indices—all in one batched request (wherever possible).
+    /// Returns the in-flight fetch of `entry`, if there is one, raising
+    /// its priority to at least `priority`.
+    fn pending(&self, entry: &IndexEntry, priority: Priority) -> Option<PendingChunk> {
+        let inflight = self.inflight.borrow();
+        let (pending, queued_at) = inflight.get(&entry.key())?;
+        queued_at.set(queued_at.get().max(priority));
+        Some(pending.clone())
+    }
+
+    /// Returns a fetch of bytes `start..=end` that waits for a scheduler
+    /// permit at `priority` before going to the network.
     ///
-    /// # Parameters
+    /// Requests other than offline downloads take the current load abort
+    /// signal, so `CyoaGame::abort` also drops them while still queued.
+    fn fetch_scheduled(
+        &self,
+        priority: Rc<Cell<Priority>>,
+        start: u64,
+        end: u64,
+    ) -> impl Future<Output = Result<Vec<u8>, GameError>> + 'static {
+        let sched = self.sched.borrow().clone();
+        let net = self.net.borrow().clone();
+        let stats = self.stats.borrow().clone();
+        let url = self.url.clone();
+        let validator = self.validator.clone();
+        let signal = (priority.get() != Priority::Download)
+            .then(|| net.signal())
+            .flatten();
+        async move {
+            let _permit = sched.acquire(priority.clone()).await?;
+            let validator = validator.as_deref();
+            let sent = trace::now();
+            let data = net
+                .fetch_range(signal.as_ref(), &url, start, Some(end), validator)
+                .await?;
+            stats.request(priority.get(), data.len(), trace::now() - sent);
+            Ok(data)
+        }
+    }
+
+    /// Registers `fetch` as the in-flight fetch of `entry`, queued at
+    /// `priority`, and returns it.
     ///
-    /// - `idx`: Zero‐based index into the game’s index entries. Must point
-    ///   at a `ChunkType::Node` entry.
+    /// Once the fetch completes it leaves the in-flight table and, if it
+    /// succeeded, its bytes are written to the persistent store in the
+    /// background; a failure is tagged with `entry`. Callers cache the
+    /// result themselves.
+    fn track(
+        &self,
+        entry: &IndexEntry,
+        priority: Rc<Cell<Priority>>,
+        fetch: impl Future<Output = Result<Arc<Vec<u8>>, JsValue>> + 'static,
+    ) -> PendingChunk {
+        let key = entry.key();
+        let context = ErrorContext::chunk(entry);
+        let inflight = self.inflight.clone();
+        let store = self.store.borrow().clone();
+        let pending = async move {
+            let result = fetch.await.map_err(|e| context.attach(e));
+            inflight.borrow_mut().remove(&key);
+            if let (Ok(data), Some((store, story))) = (&result, store) {
+                let data = data.to_vec();
+                spawn_local(async move {
+                    store.put(&story, key, data).await;
+                });
+            }
+            result
+        }
+        .boxed_local()
+        .shared();
+        self.inflight
+            .borrow_mut()
+            .insert(key, (pending.clone(), priority));
+        pending
+    }
+
+    /// Retrieves the raw bytes of several chunks at once, in the order of
+    /// `entries`.
+    ///
+    /// Chunks missing from the cache and not already in flight are
+    /// planned with `plan_ranges`: spans no more than `max_gap` bytes
+    /// apart are merged, each merged span is fetched with one Range
+    /// request (all in parallel), and the responses are sliced back into
+    /// per-chunk buffers and cached. Chunks another caller is already
+    /// fetching are awaited rather than requested again. Requests are
+    /// scheduled at `priority`.
     ///
     /// # Returns
     ///
-    /// - `Ok(JsValue)`: A JS object with shape `{ content: string, edges: Array< { label: string, dest_idx: number } > }`.
-    /// - `Err(JsValue)`: If `idx` is out of range, not a node chunk, or any
-    ///   network/parse error occurs.
+    /// - `Ok(Vec<Arc<Vec<u8>>>)`: One buffer per entry.
+    /// - `Err(JsValue)`: On network errors, a short range response, or
+    ///   `GameError::Cancelled`.
+    async fn get_raw_chunks(
+        &self,
+        entries: &[&IndexEntry],
+        max_gap: u32,
+        priority: Priority,
+    ) -> Result<Vec<Arc<Vec<u8>>>, JsValue> {
+        if self.is_local() {
+            return try_join_all(entries.iter().map(|e| self.get_raw_chunk(e, priority))).await;
+        }
+        let mut missing: Vec<&IndexEntry> = Vec::new();
+        let mut pending: Vec<(ChunkKey, PendingChunk)> = Vec::new();
+        for e in entries {
+            let dup = missing.iter().any(|m| m.key() == e.key())
+                || pending.iter().any(|(k, _)| *k == e.key());
+            if dup || self.is_cached(e) {
+                continue;
+            }
+            if e.offset + e.length as u64 > self.size {
+                return Err(ErrorContext::chunk(e).attach(GameError::IndexOutOfRange.into()));
+            }
+            match self.pending(e, priority) {
+                Some(p) => pending.push((e.key(), p)),
+                None => missing.push(e),
+            }
+        }
+
+        // Chunks persisted by an earlier session need no request.
+        let mut fetched: HashMap<ChunkKey, Arc<Vec<u8>>> = HashMap::new();
+        let stored = join_all(missing.iter().map(|e| self.stored(e))).await;
+        let stats = self.stats.borrow().clone();
+        for (key, _) in &pending {
+            stats.chunk(priority, key.0, false);
+        }
+        let mut to_fetch = Vec::with_capacity(missing.len());
+        for (e, hit) in missing.into_iter().zip(stored) {
+            stats.chunk(priority, e.chunk_type, hit.is_some());
+            match (hit, self.pending(e, priority)) {
+                (Some(arc), _) => {
+                    fetched.insert(e.key(), arc);
+                }
+                // Started by another caller while the store was checked.
+                (None, Some(p)) => pending.push((e.key(), p)),
+                (None, None) => to_fetch.push(e),
+            }
+        }
+        let missing = to_fetch;
+
+        if !missing.is_empty() {
+            let spans: Vec<(u64, u32)> = missing.iter().map(|e| (e.offset, e.length)).collect();
+            let plan = plan_ranges(&spans, max_gap as u64);
+            log_debug!("Fetching {} chunks in {} range requests", missing.len(), plan.len());
+            for range in &plan {
+                let (start, end) = (range.start, range.end);
+                // Shared by the range's chunks, so joining any one of them
+                // raises the whole request.
+                let queued_at = Rc::new(Cell::new(priority));
+                let fetch = self.fetch_scheduled(queued_at.clone(), start, end);
+                let bytes = async move { fetch.await.map(Rc::new).map_err(JsValue::from) }
+                    .boxed_local()
+                    .shared();
+                for &m in &range.members {
+                    let e = missing[m];
+                    let bytes = bytes.clone();
+                    let (from, len) = ((e.offset - start) as usize, e.length as usize);
+                    let slice = self.track(e, queued_at.clone(), async move {
+                        let bytes = bytes.await.map_err(detach)?;
+                        let slice = bytes
+                            .get(from..from + len)
+                            .ok_or(GameError::Parse("Range response truncated"))?;
+                        Ok(Arc::new(slice.to_vec()))
+                    });
+                    pending.push((e.key(), slice));
+                }
+            }
+        }
+
+        let keys: Vec<ChunkKey> = pending.iter().map(|(k, _)| *k).collect();
+        let done = try_join_all(pending.into_iter().map(|(_, p)| p))
+            .await
+            .map_err(detach)?;
+        for (key, arc) in keys.into_iter().zip(done) {
+            self.raw_cache.borrow_mut().insert(key, arc.clone(), arc.len());
+            fetched.insert(key, arc);
+        }
+
+        let mut out = Vec::with_capacity(entries.len());
+        for e in entries {
+            match fetched.get(&(e.chunk_type, e.chunk_id)) {
+                Some(arc) => out.push(arc.clone()),
+                None => out.push(self.get_raw_chunk(e, priority).await?),
+            }
+        }
+        Ok(out)
+    }
+
+    /// Returns the decoded records of `entries`, in order.
+    ///
+    /// Records found in the decoded cache cost nothing; the rest are
+    /// fetched together through `get_raw_chunks`, decoded and cached with
+    /// their payload size as weight. Fetches are scheduled at `priority`.
+    async fn records<T: Record>(
+        &self,
+        entries: &[&IndexEntry],
+        max_gap: u32,
+        priority: Priority,
+    ) -> Result<Vec<Rc<T>>, JsValue> {
+        let stats = self.stats.borrow().clone();
+        let mut out: Vec<Option<Rc<T>>> = entries
+            .iter()
+            .map(|e| {
+                let rec = self.decoded.borrow_mut().get(&e.key()).and_then(|d| T::unwrap(&d));
+                if rec.is_some() {
+                    stats.chunk(priority, e.chunk_type, true);
+                }
+                rec
+            })
+            .collect();
+        let missing: Vec<&IndexEntry> = entries
+            .iter()
+            .zip(&out)
+            .filter(|(_, rec)| rec.is_none())
+            .map(|(e, _)| *e)
+            .collect();
+        if !missing.is_empty() {
+            let raws = self.get_raw_chunks(&missing, max_gap, priority).await?;
+            let mut fresh = Vec::with_capacity(missing.len());
+            for (e, raw) in missing.iter().zip(raws) {
+                let context = ErrorContext::chunk(e);
+                let started = trace::now();
+                let payload =
+                    CyoaGame::chunk_payload(&raw).map_err(|err| context.attach(err.into()))?;
+                let decoded = trace::now();
+                stats.zstd(priority, decoded - started);
+                let rec = Rc::new(T::decode(&payload).map_err(|err| context.attach(err.into()))?);
+                stats.parse(priority, trace::now() - decoded);
+                self.decoded
+                    .borrow_mut()
+                    .insert(e.key(), T::wrap(rec.clone()), payload.len());
+                fresh.push(rec);
+            }
+            let mut fresh = fresh.into_iter();
+            for slot in out.iter_mut().filter(|rec| rec.is_none()) {
+                *slot = fresh.next();
+            }
+        }
+        Ok(out.into_iter().flatten().collect())
+    }
+
+    /// Returns the decoded record of a single chunk.
+    async fn record<T: Record>(
+        &self,
+        entry: &IndexEntry,
+        priority: Priority,
+    ) -> Result<Rc<T>, JsValue> {
+        let mut recs = self.records::<T>(&[entry], 0, priority).await?;
+        recs.pop()
+            .ok_or_else(|| GameError::Parse("Chunk record missing").into())
+    }
+
+    /// Downloads `entries` into the persistent store, then reads every one
+    /// back to check it.
+    ///
+    /// Chunks the store already holds intact are counted as done without
+    /// a request, so calling this again resumes an interrupted download.
+    /// So are chunks supplied by applied patches: they are held in memory,
+    /// while the store only keeps chunks of the unpatched file.
+    /// The rest are fetched in coalesced ranges, about `DOWNLOAD_BATCH`
+    /// bytes per round; `report` is called after each round and
+    /// `cancelled` is checked before each.
+    ///
+    /// # Errors
+    ///
+    /// - `GameError::Cancelled` once `cancelled` returns `true`.
+    /// - `GameError::Other` if chunks fail the final integrity check.
+    /// - Network errors from the range requests.
+    async fn download(
+        &self,
+        entries: &[&IndexEntry],
+        max_gap: u32,
+        progress: &mut DownloadProgress,
+        report: &dyn Fn(&DownloadProgress) -> Result<(), JsValue>,
+        cancelled: &dyn Fn() -> bool,
+    ) -> Result<(), JsValue> {
+        let Some((store, key)) = self.store.borrow().clone() else {
+            // Local files are already available offline.
+            progress.add(entries);
+            return report(progress);
+        };
+
+        // 1) Skip chunks supplied by patches, which are in memory and not
+        // in the file the store key names, and chunks stored by an earlier,
+        // possibly interrupted, run
+        let (patched, entries): (Vec<&IndexEntry>, Vec<&IndexEntry>) =
+            entries.iter().partition(|e| self.overlay.contains_key(&e.key()));
+        progress.add(&patched);
+        let mut todo = Vec::new();
+        for &e in &entries {
+            match store.get(&key, e.key()).await {
+                Some(data) if e.is_intact(&data) => progress.add(&[e]),
+                _ => todo.push(e),
+            }
+        }
+        report(progress)?;
+
+        // 2) Fetch the rest in coalesced ranges, one batch at a time
+        let spans: Vec<(u64, u32)> = todo.iter().map(|e| (e.offset, e.length)).collect();
+        let plan = plan_ranges(&spans, max_gap as u64);
+        let mut rest = plan.as_slice();
+        while !rest.is_empty() {
+            if cancelled() {
+                return Err(GameError::Cancelled.into());
+            }
+            let mut take = 0;
+            let mut bytes = 0;
+            while take < rest.len() && (take == 0 || bytes < DOWNLOAD_BATCH) {
+                bytes += rest[take].end - rest[take].start + 1;
+                take += 1;
+            }
+            let (batch, tail) = rest.split_at(take);
+            rest = tail;
+            let parts = try_join_all(batch.iter().map(|r| {
+                let fetch =
+                    self.fetch_scheduled(Rc::new(Cell::new(Priority::Download)), r.start, r.end);
+                let context = ErrorContext::range(r.start);
+                async move { fetch.await.map_err(|e| context.attach(e.into())) }
+            }))
+            .await?;
+            for (range, data) in batch.iter().zip(parts) {
+                for &m in &range.members {
+                    let e = todo[m];
+                    let from = (e.offset - range.start) as usize;
+                    let slice = data.get(from..from + e.length as usize).ok_or_else(|| {
+                        let err = GameError::Parse("Range response truncated");
+                        ErrorContext::chunk(e).attach(err.into())
+                    })?;
+                    store.put(&key, e.key(), slice.to_vec()).await;
+                    progress.add(&[e]);
+                }
+            }
+            report(progress)?;
+        }
+
+        // 3) Integrity check
+        let mut bad = 0;
+        for e in &entries {
+            match store.get(&key, e.key()).await {
+                Some(data) if e.is_intact(&data) => {}
+                _ => bad += 1,
+            }
+        }
+        if bad > 0 {
+            return Err(GameError::Other(format!(
+                "{} of {} chunks of {} failed the integrity check",
+                bad,
+                entries.len(),
+                self.url
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Fills the raw cache from the page chunk of `node` if the file has
+    /// one and `node` is not cached yet; see the `page` module.
+    ///
+    /// The page itself is neither cached nor persisted: only the chunks
+    /// it bundles are, so they are not held twice.
+    ///
+    /// A malformed page is logged and ignored, leaving the node's chunks
+    /// to be fetched stage by stage.
+    async fn load_page(&self, node: &IndexEntry) -> Result<(), JsValue> {
+        if self.is_cached(node) {
+            return Ok(());
+        }
+        let Some(page) = self.find_entry(ChunkType::Page, &node.chunk_id) else {
+            return Ok(());
+        };
+        let context = ErrorContext::chunk(page);
+        let stats = self.stats.borrow().clone();
+        let raw = match self.overlay.get(&page.key()) {
+            Some(patched) => {
+                stats.chunk(Priority::Visible, ChunkType::Page, true);
+                patched.clone()
+            }
+            None => {
+                stats.chunk(Priority::Visible, ChunkType::Page, false);
+                let (start, end) = (page.offset, page.offset + page.length as u64 - 1);
+                if end >= self.size {
+                    return Err(context.attach(GameError::IndexOutOfRange.into()));
+                }
+                let priority = Rc::new(Cell::new(Priority::Visible));
+                let data = self.fetch_scheduled(priority, start, end).await;
+                Arc::new(data.map_err(|e| context.attach(e.into()))?)
+            }
+        };
+        let started = trace::now();
+        let payload = CyoaGame::chunk_payload(&raw).map_err(|e| context.attach(e.into()))?;
+        stats.zstd(Priority::Visible, trace::now() - started);
+        match page::read_chunks(&mut Cursor::new(payload.as_slice())) {
+            Ok(chunks) => self.seed(chunks),
+            Err(e) => log_warn!("Ignoring malformed page {}: {}", hex_id(&page.chunk_id), e),
+        }
+        Ok(())
+    }
+
+    /// Looks `entry` up in the persistent store, caching a hit in memory.
+    ///
+    /// Stored data that fails `IndexEntry::is_intact` is treated as a miss.
+    async fn stored(&self, entry: &IndexEntry) -> Option<Arc<Vec<u8>>> {
+        let (store, key) = self.store.borrow().clone()?;
+        let data = store.get(&key, entry.key()).await?;
+        if !entry.is_intact(&data) {
+            return None;
+        }
+        let arc = Arc::new(data);
+        self.raw_cache
+            .borrow_mut()
+            .insert(entry.key(), arc.clone(), arc.len());
+        Some(arc)
+    }
+
+    /// Fetches the chunk for `entry` and returns its decompressed payload.
+    async fn fetch_payload(
+        &self,
+        entry: &IndexEntry,
+        priority: Priority,
+    ) -> Result<Vec<u8>, JsValue> {
+        let raw = self.get_raw_chunk(entry, priority).await?;
+        let started = trace::now();
+        let payload = CyoaGame::chunk_payload(&raw)
+            .map_err(|err| ErrorContext::chunk(entry).attach(err.into()))?;
+        self.stats.borrow().zstd(priority, trace::now() - started);
+        Ok(payload)
+    }
+
+    /// Returns `true` if `entry` can be served without a network fetch.
+    fn is_cached(&self, entry: &IndexEntry) -> bool {
+        self.is_local()
+            || self.overlay.contains_key(&(entry.chunk_type, entry.chunk_id))
+            || self.raw_cache.borrow().contains(&entry.key())
+    }
+
+    /// Walks outward from the nodes in `frontier`, up to `cfg.depth` edges
+    /// deep, loading each node's node, content, edge and label chunks into
+    /// the raw cache one at a time.
+    ///
+    /// Stops when the byte budget is spent, on the first error, or as soon
+    /// as `generation` moves past `started_at` (the player navigated on).
+    /// Links into other story files are not followed.
+    async fn prefetch_neighbours(
+        self: Rc<Self>,
+        mut frontier: Vec<[u8; 3]>,
+        cfg: PrefetchConfig,
+        generation: Rc<Cell<u64>>,
+        started_at: u64,
+    ) -> Option<()> {
+        let mut budget = cfg.byte_budget as u64;
+        let mut seen: Vec<[u8; 3]> = Vec::new();
+        for _ in 0..cfg.depth {
+            let mut next = Vec::new();
+            for cid in frontier {
+                if seen.contains(&cid) {
+                    continue;
+                }
+                seen.push(cid);
+                let Some(node) = self.find_entry(ChunkType::Node, &cid) else {
+                    continue;
+                };
+                let payload = self.prefetch_one(node, &mut budget, &generation, started_at).await?;
+                let contents = CyoaGame::parse_node_content_seq(&payload).ok()?;
+                for c in contents {
+                    if let Some(e) = self.find_entry(ChunkType::Content, &c.content_id) {
+                        self.prefetch_one(e, &mut budget, &generation, started_at).await?;
+                    }
+                }
+                for edge_cid in CyoaGame::parse_node_edges_ids(&payload).ok()? {
+                    let Some(e) = self.find_entry(ChunkType::Edge, &edge_cid) else {
+                        continue;
+                    };
+                    let edge = self.prefetch_one(e, &mut budget, &generation, started_at).await?;
+                    let (label, dest) = CyoaGame::parse_edge_label_dest_cids(&edge).ok()?;
+                    if let Some(l) = self.find_entry(ChunkType::Content, &label) {
+                        self.prefetch_one(l, &mut budget, &generation, started_at).await?;
+                    }
+                    next.push(dest);
+                }
+            }
+            frontier = next;
+        }
+        Some(())
+    }
+
+    /// Prefetches one chunk for `prefetch_neighbours`, charging uncached
+    /// chunks to `budget`, and returns its decompressed payload.
+    ///
+    /// Returns `None` when prefetching should stop.
+    async fn prefetch_one(
+        &self,
+        entry: &IndexEntry,
+        budget: &mut u64,
+        generation: &Cell<u64>,
+        started_at: u64,
+    ) -> Option<Vec<u8>> {
+        if generation.get() != started_at {
+            return None;
+        }
+        if !self.is_cached(entry) {
+            if entry.length as u64 > *budget {
+                return None;
+            }
+            *budget -= entry.length as u64;
+        }
+        match self.fetch_payload(entry, Priority::Prefetch).await {
+            Ok(payload) => Some(payload),
+            Err(e) => {
+                log_debug!("Prefetch stopped: {:?}", e);
+                None
+            }
+        }
+    }
+
+    /// Pins the chunks every load depends on: the root node and all
+    /// `WasmTable` and `ArgBlobPool` chunks.
+    fn pin_defaults(&self, root_idx: Option<u32>) {
+        let mut cache = self.raw_cache.borrow_mut();
+        for e in &self.index {
+            if matches!(e.chunk_type, ChunkType::WasmTable | ChunkType::ArgBlobPool) {
+                cache.pin(e.key());
+            }
+        }
+        if let Some(root) = root_idx.and_then(|i| self.index.get(i as usize)) {
+            cache.pin(root.key());
+        }
+    }
+
+    /// Looks up the index entry for the chunk with the given type and ID.
+    fn find_entry(&self, chunk_type: ChunkType, cid: &[u8; 3]) -> Option<&IndexEntry> {
+        engine::find_entry(&self.index, chunk_type, cid)
+    }
+
+    /// Returns the local index position of the node chunk `cid`, if this
+    /// file contains it.
+    fn node_position(&self, cid: &[u8; 3]) -> Option<usize> {
+        engine::node_position(&self.index, cid)
+    }
+
+    /// Returns the typed story metadata, fetching and decoding every
+    /// known `ChunkType::Metadata` chunk on first use.
+    ///
+    /// `root_idx` is local to this file.
+    async fn story_metadata(&self) -> Result<Rc<StoryMetadata>, JsValue> {
+        if let Some(meta) = self.metadata.borrow().as_ref() {
+            return Ok(meta.clone());
+        }
+        let keyed: Vec<(MetaKey, &IndexEntry)> = self
+            .index
+            .iter()
+            .filter(|e| e.chunk_type == ChunkType::Metadata)
+            .filter_map(|e| MetaKey::from_id(e.chunk_id).map(|k| (k, e)))
+            .collect();
+        let payloads = try_join_all(keyed.iter().map(|(_, e)| self.fetch_payload(e, Priority::Visible))).await?;
+        let mut meta = StoryMetadata::default();
+        for ((key, _), pl) in keyed.into_iter().zip(payloads) {
+            match parse_value(key.kind(), &pl)? {
+                MetaValue::NodeRef(cid) if key == MetaKey::RootPointer => {
+                    meta.root_idx = self.node_position(&cid).map(|i| i as u32);
+                }
+                value => meta.set(key, value),
+            }
+        }
+        meta.engine_compatible = meta
+            .min_engine_version
+            .is_none_or(|min| Version::engine() >= min);
+        let meta = Rc::new(meta);
+        *self.metadata.borrow_mut() = Some(meta.clone());
+        Ok(meta)
+    }
+}
+
+/// Summary of one mounted story file, as returned by `CyoaGame::mounts`.
+#[derive(Serialize)]
+struct MountOutput {
+    url: String,
+    namespace: Option<String>,
+    version: Option<Version>,
+    /// Current fingerprint as 16 hex digits; see `apply_patch`.
+    fingerprint: String,
+    /// Global index of this file's first index entry.
+    first_idx: u32,
+    /// Number of index entries in this file.
+    len: u32,
+}
+
+/// The main game loader exposed to JavaScript via wasm_bindgen.
+/// Handles probing, range-requests, parsing TLV, zstd decompression,
+/// and exposes `load_root_node_full` / `load_node_full` APIs.
+///
+/// The game holds the base story file plus any mounted expansions; see
+/// `mount` and `register_pack`.
+#[wasm_bindgen]
+pub struct CyoaGame {
+    files: RefCell<Vec<Rc<StoryFile>>>,
+    packs: RefCell<HashMap<String, String>>,
+    /// Story files being opened and attached, keyed by URL, with the
+    /// callers waiting for each; see `mount_url`.
+    mounting: RefCell<HashMap<String, Vec<MountWaiter>>>,
+    achievements: RefCell<Option<Rc<Vec<Achievement>>>>,
+    unlocks: RefCell<UnlockStore>,
+    prefetch: Cell<PrefetchConfig>,
+    range_gap: Cell<u32>,
+    /// Byte budget of each mounted file's chunk cache.
+    cache_budget: Cell<usize>,
+    /// Byte budget of each mounted file's decoded record cache.
+    decoded_budget: Cell<usize>,
+    /// Where downloaded chunks are persisted across sessions.
+    store: Rc<dyn ChunkStore>,
+    /// Bumped when an offline download starts or is cancelled; a running
+    /// download stops once it moves.
+    download_gen: Cell<u64>,
+    /// Caps and orders the chunk requests of every mounted file.
+    sched: Rc<Scheduler>,
+    /// Timeouts, retries and the abort signal of every mounted file.
+    net: Rc<Net>,
+    /// Totals and per-load traces of requests, cache hits and time spent.
+    stats: Rc<Stats>,
+    /// Called with each node load's trace; see `set_trace_callback`.
+    on_trace: RefCell<Option<Function>>,
+    /// Bumped on every node load; background prefetches stop once it moves.
+    generation: Rc<Cell<u64>>,
+}
+
+#[wasm_bindgen]
+impl CyoaGame {
+    /// Constructs a new `CyoaGame` instance by probing the remote file
+    /// at `path` for its total size and HTTP Range support, then fetching
+    /// and parsing the on‐disk index.
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: URL or filesystem path (relative to the site root) of
+    ///   the `.cyoa` binary file.
+    /// - `on_progress`: Optional `(loaded, total) => void` callback, called
+    ///   while the whole file downloads on servers without Range support
+    ///   (`total` is 0 when the size is unknown).
+    ///
+    /// On such servers (simple static hosts, `file://`-like WebViews) the
+    /// file is downloaded once and every chunk is sliced from memory.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(CyoaGame)`: if the file was probed successfully and its index
+    ///   parsed without error.
+    /// - `Err(JsValue)`: if there was any HTTP error, invalid magic,
+    ///   out‐of‐range index pointer, or parse failure.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// // In JavaScript:
+    /// const game = await new CyoaGame("/games/mystory.cy");
+    /// const game2 = await new CyoaGame("/games/mystory.cy", (n, total) => bar.update(n, total));
+    /// const game3 = await new CyoaGame("mystory.story", undefined, {
+    ///   baseUrl: "https://cdn.example.com/stories",
+    ///   credentials: "include",
+    ///   headers: { Authorization: `Bearer ${token}` },
+    /// });
+    /// ```
+    #[wasm_bindgen(constructor)]
+    pub async fn new(
+        path: String,
+        on_progress: Option<Function>,
+        options: Option<CyoaGameOptions>,
+    ) -> Result<CyoaGame, JsValue> {
+        let on_first_screen = options
+            .as_ref()
+            .and_then(|o| Reflect::get(o, &JsValue::from_str("onFirstScreen")).ok())
+            .and_then(|f| f.dyn_into::<Function>().ok());
+        let net = Net::from_options(options)?;
+        let url = net.resolve(&path);
+        let mut game = Self::empty(&url);
+        game.net = Rc::new(net);
+        let base = StoryFile::open(
+            url,
+            on_progress.as_ref(),
+            on_first_screen.as_ref(),
+            &game.net,
+        )
+        .await?;
+        game.attach(base, &[]).await?;
+        Ok(game)
+    }
+
+    /// Constructs a `CyoaGame` from the complete contents of a `.story`
+    /// file, e.g. one the player downloaded earlier or a test fixture.
+    ///
+    /// # Parameters
+    ///
+    /// - `bytes`: The whole file.
+    /// - `name`: Name reported by `mounts` and used to key saved
+    ///   achievements; defaults to `"local.story"`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(CyoaGame)`: if the header and index parsed without error.
+    /// - `Err(JsValue)`: on invalid magic, out‐of‐range index pointer, or
+    ///   parse failure.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// const bytes = new Uint8Array(await file.arrayBuffer());
+    /// const game = await CyoaGame.fromBytes(bytes, file.name);
+    /// ```
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub async fn from_bytes(bytes: Uint8Array, name: Option<String>) -> Result<CyoaGame, JsValue> {
+        let url = name.unwrap_or_else(|| LOCAL_NAME.to_string());
+        let game = Self::empty(&url);
+        let base = StoryFile::from_bytes(url, bytes.to_vec())?;
+        game.attach(base, &[]).await?;
+        Ok(game)
+    }
+
+    /// Constructs a `CyoaGame` from a `Blob` or `File`, such as one
+    /// dropped onto the window or picked with `<input type="file">`.
+    ///
+    /// Only the header and index are read up front; chunks are read
+    /// lazily with `Blob.slice()`, so large stories open without copying
+    /// the whole file into memory.
+    ///
+    /// # Parameters
+    ///
+    /// - `blob`: The story file.
+    /// - `name`: Name reported by `mounts` and used to key saved
+    ///   achievements; defaults to the `File`'s name, or `"local.story"`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(CyoaGame)`: if the header and index parsed without error.
+    /// - `Err(JsValue)`: on read errors, invalid magic, out‐of‐range index
+    ///   pointer, or parse failure.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// const game = await CyoaGame.fromBlob(event.dataTransfer.files[0]);
+    /// ```
+    #[wasm_bindgen(js_name = fromBlob)]
+    pub async fn from_blob(blob: Blob, name: Option<String>) -> Result<CyoaGame, JsValue> {
+        let url = name
+            .or_else(|| blob.dyn_ref::<File>().map(|f| f.name()))
+            .unwrap_or_else(|| LOCAL_NAME.to_string());
+        let game = Self::empty(&url);
+        let base = StoryFile::from_blob(url, blob).await?;
+        game.attach(base, &[]).await?;
+        Ok(game)
+    }
+
+    /// Mounts an additional `.story` file (a book or expansion) at `path`.
+    ///
+    /// The file's minimum engine version and declared dependencies are
+    /// checked first; dependencies that are registered via `register_pack`
+    /// but not yet mounted are mounted automatically. Its nodes become
+    /// addressable by global index right after those of earlier files.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(JsValue)`: The mounted file's namespace, or `null` if it
+    ///   declares none.
+    /// - `Err(JsValue)`: On fetch/parse errors, an already-mounted
+    ///   namespace, or `GameError::Dependency` if requirements are unmet.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// await game.mount("/books/book4.story");
+    /// ```
+    #[wasm_bindgen]
+    pub async fn mount(&self, path: String) -> Result<JsValue, JsValue> {
+        let file = self.mount_url(self.net.resolve(&path), &[]).await?;
+        let meta = file.story_metadata().await?;
+        Ok(meta
+            .namespace
+            .as_deref()
+            .map(JsValue::from_str)
+            .unwrap_or(JsValue::NULL))
+    }
+
+    /// Configures background prefetching of likely next nodes.
+    ///
+    /// After each node load, the node, content, edge and label chunks of
+    /// nodes up to `depth` edges away are fetched one at a time into the
+    /// chunk cache, spending at most `byte_budget` bytes per pass. A pass
+    /// stops as soon as another node is loaded. `depth = 0` disables
+    /// prefetching. Defaults: depth 1, 256 KiB.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// game.set_prefetch(2, 512 * 1024);
+    /// ```
+    #[wasm_bindgen]
+    pub fn set_prefetch(&self, depth: u32, byte_budget: u32) {
+        self.prefetch.set(PrefetchConfig { depth, byte_budget });
+    }
+
+    /// Sets the largest gap, in bytes, between two chunks that is bridged
+    /// when merging their ranges into one HTTP request (default 1024).
+    ///
+    /// Higher values mean fewer requests but more unused bytes; `0` only
+    /// merges chunks that are exactly adjacent.
+    #[wasm_bindgen]
+    pub fn set_range_gap(&self, max_gap: u32) {
+        self.range_gap.set(max_gap);
+    }
+
+    /// Sets how many chunk requests may be in flight at once across all
+    /// mounted files (default 6, minimum 1).
+    ///
+    /// Requests over the limit wait in a queue: the visible node's chunks
+    /// first, then choice labels, then prefetching, then offline
+    /// downloads. Loading another node drops queued prefetch requests.
+    #[wasm_bindgen]
+    pub fn set_max_concurrency(&self, max: u32) {
+        self.sched.set_limit(max as usize);
+    }
+
+    /// Configures timeouts and retries of network requests.
+    ///
+    /// Each attempt, response body included, may take `timeout_ms`.
+    /// Network errors, timeouts and 5xx responses are retried up to
+    /// `retries` times, waiting `backoff_ms` before the first retry and
+    /// twice as long before each further one. Defaults: 15 s, 3 retries,
+    /// 250 ms.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// game.set_network_policy(8000, 5, 500);
+    /// ```
+    #[wasm_bindgen]
+    pub fn set_network_policy(&self, timeout_ms: u32, retries: u32, backoff_ms: u32) {
+        self.net.set_policy(RetryPolicy {
+            timeout_ms,
+            retries,
+            backoff_ms,
+        });
+    }
+
+    /// Aborts every request of the loads started so far, in flight or
+    /// queued, e.g. when the player taps another choice before the
+    /// current node arrives. Their calls reject with code `"ABORTED"`.
+    ///
+    /// Loads started afterwards are unaffected, and so are offline
+    /// downloads (see `cancel_download`).
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// game.abort();
+    /// const next = await game.load_node_full(idx);
+    /// ```
+    #[wasm_bindgen]
+    pub fn abort(&self) {
+        self.net.abort();
+    }
+
+    /// Sets a callback receiving the trace of every node load
+    /// (`load_node_full`, `choose`, `load_root_node_full`) once it settles,
+    /// or removes it with `undefined`. The trace has the shape
+    ///
+    /// ```text
+    /// {
+    ///   loads: 1, elapsed_ms, requests, bytes, network_ms, zstd_ms,
+    ///   parse_ms, chunks: [{ chunk_type: "content", hits, misses }, ...]
+    /// }
+    /// ```
+    ///
+    /// It counts the requests and decoding done for the node on screen
+    /// while the load ran; prefetches are left out.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// game.set_trace_callback(t => {
+    ///   if (t.elapsed_ms > 500) report("slow page turn", t);
+    /// });
+    /// ```
+    #[wasm_bindgen]
+    pub fn set_trace_callback(&self, on_trace: Option<Function>) {
+        *self.on_trace.borrow_mut() = on_trace;
+    }
+
+    /// Returns totals since the game was created, in the shape of a
+    /// trace (see `set_trace_callback`), with `loads` counting node loads.
+    /// Unlike traces, the totals include prefetches and offline downloads.
+    #[wasm_bindgen]
+    pub fn stats(&self) -> Result<JsValue, JsValue> {
+        to_value(&self.stats.totals()).map_err(|e| JsValue::from(GameError::Other(e.to_string())))
+    }
+
+    /// Sets how much the engine logs to the browser console, for every
+    /// game: `"off"`, `"error"`, `"warn"` (the release default), `"info"`
+    /// or `"debug"`.
+    ///
+    /// # Errors
+    ///
+    /// - `GameError::Other` for an unknown level name.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// CyoaGame.setLogLevel("debug");
+    /// ```
+    #[wasm_bindgen(js_name = setLogLevel)]
+    pub fn set_log_level(level: LogLevel) -> Result<(), JsValue> {
+        let name = JsValue::from(level).as_string().unwrap_or_default();
+        let level = Level::parse(&name)
+            .ok_or_else(|| GameError::Other(format!("Unknown log level: {}", name)))?;
+        logging::set_level(level);
+        Ok(())
+    }
+
+    /// Sets the byte budget of each mounted file's chunk cache (default
+    /// 8 MiB), evicting least recently used chunks if a cache is now over
+    /// it.
+    ///
+    /// Pinned chunks (see `pin_chunk`) are never evicted but count
+    /// towards the budget.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// game.set_cache_budget(32 * 1024 * 1024);
+    /// ```
+    #[wasm_bindgen]
+    pub fn set_cache_budget(&self, bytes: u32) {
+        self.cache_budget.set(bytes as usize);
+        for f in self.files.borrow().iter() {
+            f.raw_cache.borrow_mut().set_budget(bytes as usize);
+        }
+    }
+
+    /// Sets the budget of each mounted file's decoded record cache
+    /// (default 4 MiB), which holds parsed node and edge records and
+    /// decoded content text so revisited nodes need no decompression.
+    ///
+    /// A record's weight is the size of its decompressed payload.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// game.set_decoded_cache_budget(16 * 1024 * 1024);
+    /// ```
+    #[wasm_bindgen]
+    pub fn set_decoded_cache_budget(&self, bytes: u32) {
+        self.decoded_budget.set(bytes as usize);
+        for f in self.files.borrow().iter() {
+            f.decoded.borrow_mut().set_budget(bytes as usize);
+        }
+    }
+
+    /// Downloads every chunk of every mounted story into the persistent
+    /// chunk store, so the game can later be played offline.
+    ///
+    /// Chunks are fetched in coalesced ranges. Chunks already stored are
+    /// skipped, so calling this again after a cancellation or network
+    /// failure resumes the download. Once everything is fetched, every
+    /// chunk is read back from the store and checked.
+    ///
+    /// # Parameters
+    ///
+    /// - `on_progress`: Optional callback receiving
+    ///   `{ bytes_done, bytes_total, chunks_done, chunks_total }` after
+    ///   each batch.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(JsValue)`: The final progress object.
+    /// - `Err(JsValue)`: Code `"CANCELLED"` after `cancel_download`,
+    ///   an integrity check failure, or a network error.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// await game.download_all(p => bar.update(p.bytes_done, p.bytes_total));
+    /// ```
+    #[wasm_bindgen]
+    pub async fn download_all(&self, on_progress: Option<Function>) -> Result<JsValue, JsValue> {
+        let plan = self
+            .files
+            .borrow()
+            .iter()
+            .map(|f| {
+                let live = (0..f.index.len())
+                    .filter(|&i| f.index[i].chunk_type != ChunkType::Removed)
+                    .collect();
+                (f.clone(), live)
+            })
+            .collect();
+        self.download_plan(plan, on_progress).await
+    }
+
+    /// Downloads the chunks needed to play one chapter offline: every node
+    /// tagged `chapter=<chapter>` with its content, edges, labels and
+    /// cross-file links.
+    ///
+    /// Node chunks of every mounted file are fetched first to read their
+    /// tags. Otherwise behaves like `download_all`.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// await game.download_chapter("3", onProgress);
+    /// ```
+    #[wasm_bindgen]
+    pub async fn download_chapter(
+        &self,
+        chapter: String,
+        on_progress: Option<Function>,
+    ) -> Result<JsValue, JsValue> {
+        let max_gap = self.range_gap.get();
+        let files = self.files.borrow().clone();
+        let mut plan = Vec::with_capacity(files.len());
+        for f in files {
+            let nodes: Vec<&IndexEntry> = f
+                .index
+                .iter()
+                .filter(|e| e.chunk_type == ChunkType::Node)
+                .collect();
+            let records = f
+                .records::<NodeRecord>(&nodes, max_gap, Priority::Download)
+                .await?;
+            let mut wanted: HashSet<ChunkKey> = HashSet::new();
+            let mut edge_entries = Vec::new();
+            for (e, rec) in nodes.iter().zip(&records) {
+                if !rec
+                    .tags
+                    .iter()
+                    .any(|(k, v)| k == "chapter" && *v == chapter)
+                {
+                    continue;
+                }
+                wanted.insert(e.key());
+                wanted.extend(
+                    rec.content_seq
+                        .iter()
+                        .map(|c| (ChunkType::Content, c.content_id)),
+                );
+                for cid in &rec.edge_ids {
+                    if let Some(edge) = f.find_entry(ChunkType::Edge, cid) {
+                        wanted.insert(edge.key());
+                        edge_entries.push(edge);
+                    }
+                }
+            }
+            for edge in f
+                .records::<EdgeRecord>(&edge_entries, max_gap, Priority::Download)
+                .await?
+            {
+                wanted.insert((ChunkType::Content, edge.label));
+                wanted.insert((ChunkType::Link, edge.dest));
+            }
+            let positions: Vec<usize> = f
+                .index
+                .iter()
+                .enumerate()
+                .filter(|(_, e)| wanted.contains(&e.key()))
+                .map(|(i, _)| i)
+                .collect();
+            plan.push((f.clone(), positions));
+        }
+        self.download_plan(plan, on_progress).await
+    }
+
+    /// Cancels a running `download_all` or `download_chapter` after its
+    /// current batch. Chunks stored so far are kept.
+    #[wasm_bindgen]
+    pub fn cancel_download(&self) {
+        self.download_gen.set(self.download_gen.get() + 1);
+    }
+
+    /// Pins the chunk at global index `idx` in its file's cache, so once
+    /// loaded it is never evicted.
+    ///
+    /// The root node and all `WasmTable`/`ArgBlobPool` chunks are pinned
+    /// automatically when a file is mounted.
+    ///
+    /// # Errors
+    ///
+    /// - `GameError::Parse` if `idx` is past the last index entry.
+    #[wasm_bindgen]
+    pub fn pin_chunk(&self, idx: usize) -> Result<(), JsValue> {
+        let (file, local) = self.locate(idx)?;
+        file.raw_cache.borrow_mut().pin(file.index[local].key());
+        Ok(())
+    }
+
+    /// Declares where the story file for `namespace` can be found, without
+    /// fetching it.
+    ///
+    /// The file is mounted on demand the first time an edge into that
+    /// namespace is taken, or when another file depends on it.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// game.register_pack("magium.book4", "/books/book4.story");
+    /// ```
+    #[wasm_bindgen]
+    pub fn register_pack(&self, namespace: String, path: String) {
+        self.packs
+            .borrow_mut()
+            .insert(namespace, self.net.resolve(&path));
+    }
+
+    /// Fetches the patch file at `path` and applies it to the mounted story
+    /// whose fingerprint matches the patch's base fingerprint.
+    ///
+    /// Apply a patch chain by calling this once per patch, oldest first:
+    /// each patch gives the story a new fingerprint that the next patch
+    /// must name as its base.
+    ///
+    /// Removed chunks leave a `ChunkType::Removed` tombstone in their index
+    /// slot and replaced chunks keep theirs, so no existing index moves.
+    /// Added chunks are appended to the patched file's index, which shifts
+    /// the global indices of every file mounted after it; apply patches
+    /// before mounting further files or loading nodes from them.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(JsValue)`: The story's new fingerprint as 16 hex digits.
+    /// - `Err(JsValue)`: On fetch errors, a malformed patch, or
+    ///   `GameError::Patch` if no mounted story matches the base fingerprint.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// for (const p of ["/patches/1.storypatch", "/patches/2.storypatch"]) {
+    ///   await game.apply_patch(p);
+    /// }
+    /// ```
+    #[wasm_bindgen]
+    pub async fn apply_patch(&self, path: String) -> Result<JsValue, JsValue> {
+        let bytes = self.net.fetch_full(&self.net.resolve(&path)).await?;
+        let patch = Patch::parse(&bytes).map_err(GameError::from)?;
+        let files = self.files.borrow().clone();
+        let mut target = None;
+        for (i, f) in files.iter().enumerate() {
+            if f.fingerprint().await? == patch.base_fingerprint {
+                target = Some(i);
+                break;
+            }
+        }
+        let i = target.ok_or_else(|| {
+            GameError::Patch(format!(
+                "no mounted story has fingerprint {:016X}",
+                patch.base_fingerprint
+            ))
+        })?;
+        let patched = Rc::new(files[i].with_patch(&patch)?);
+        log_info!("Applied {} patch ops to {}", patch.ops.len(), patched.url);
+        self.files.borrow_mut()[i] = patched;
+        *self.achievements.borrow_mut() = None;
+        Ok(JsValue::from_str(&format!("{:016X}", patch.target_fingerprint)))
+    }
+
+    /// Returns a JS `Array` describing every mounted story file, in mount
+    /// order: `{ url, namespace, version, fingerprint, first_idx, len }`.
+    #[wasm_bindgen]
+    pub async fn mounts(&self) -> Result<JsValue, JsValue> {
+        let files = self.files.borrow().clone();
+        let mut out = Vec::with_capacity(files.len());
+        let mut first_idx = 0;
+        for f in files {
+            let meta = f.story_metadata().await?;
+            out.push(MountOutput {
+                url: f.url.clone(),
+                namespace: meta.namespace.clone(),
+                version: meta.version,
+                fingerprint: format!("{:016X}", f.fingerprint().await?),
+                first_idx: first_idx as u32,
+                len: f.index.len() as u32,
+            });
+            first_idx += f.index.len();
+        }
+        to_value(&out).map_err(|e| JsValue::from(GameError::Other(e.to_string())))
+    }
+
+    /// Returns a JavaScript `Array` of all chunk IDs present in the mounted
+    /// files’ parsed indexes, formatted as uppercase hex strings. Positions
+    /// in this array are the global indices used by `load_node_full`.
+    ///
+    /// Each entry is the 3‐byte chunk identifier, e.g. `"000102"`.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let ids = game.chunk_ids();              // ["000001", "000002", …]
+    /// console.log(ids[0]);                     // "000001"
+    /// ```
+    #[wasm_bindgen]
+    pub fn chunk_ids(&self) -> Array {
+        let arr = Array::new();
+        for f in self.files.borrow().iter() {
+            for e in &f.index {
+                arr.push(&JsValue::from_str(&hex_id(&e.chunk_id)));
+            }
+        }
+        arr
+    }
+
+    /// Returns the chunk ID of the node at global index `idx`, as
+    /// uppercase hex, or `undefined` if `idx` is not a node.
+    ///
+    /// Unlike the index, the ID still names the same node in a newer
+    /// version of the story file; resolve it there with `node_index`.
+    #[wasm_bindgen]
+    pub fn node_chunk_id(&self, idx: usize) -> Option<String> {
+        let (file, local) = self.locate(idx).ok()?;
+        let entry = &file.index[local];
+        (entry.chunk_type == ChunkType::Node).then(|| hex_id(&entry.chunk_id))
+    }
+
+    /// Returns the global index of the node with chunk ID `id` (hex, as
+    /// `node_chunk_id` returns it), or `undefined` if no mounted file has
+    /// one. Files are searched in mount order.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// const id = oldGame.node_chunk_id(idx);
+    /// const again = newGame.node_index(id); // same node after a redeploy
+    /// ```
+    #[wasm_bindgen]
+    pub fn node_index(&self, id: &str) -> Option<usize> {
+        let mut first = 0;
+        for f in self.files.borrow().iter() {
+            let found = f.index.iter().position(|e| {
+                e.chunk_type == ChunkType::Node && hex_id(&e.chunk_id).eq_ignore_ascii_case(id)
+            });
+            if let Some(local) = found {
+                return Some(first + local);
+            }
+            first += f.index.len();
+        }
+        None
+    }
+
+    /// Loads the node at the given index (into the parsed index vector),
+    /// fully fetching its content text and all outgoing edges—with labels
+    /// and destination indices—all in one batched request (wherever possible).
+    ///
+    /// # Parameters
+    ///
+    /// - `idx`: Zero‐based index into the game’s index entries. Must point
+    ///   at a `ChunkType::Node` entry, or at the link entry an edge into
+    ///   another namespace reports as its `dest_idx`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(JsValue)`: A JS object with shape `{ content: string, edges: Array< { label: string, dest_idx: number } >, unlocked: Array<Achievement> }`.
+    /// - `Err(JsValue)`: If `idx` is out of range, not a node chunk, or any
+    ///   network/parse error occurs.
+    ///
+    /// # Errors
+    ///
+    /// - `GameError::Parse("not a node chunk")` if the indexed entry isn’t a node.
+    /// - `GameError::Http` if any range‐request fails.
+    /// - `GameError::Parse(...)` for TLV or decompression failures.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let node = await game.load_node_full(3);
+    /// console.log(node.content);               // "You stand at a crossroads..."
+    /// console.log(node.edges.length);          // e.g. 2
+    /// ```
+    #[wasm_bindgen]
+    pub async fn load_node_full(&self, idx: usize) -> Result<JsValue, JsValue> {
+        let load = self.load_node_with_unlocks(idx, Vec::new());
+        self.traced(load)
+            .await
+            .map_err(|e| ErrorContext::node(idx).attach(e))
+    }
+
+    /// Follows the `choice`-th outgoing edge of the node at `from_idx`:
+    /// applies the edge's effects, then loads the destination node exactly
+    /// like `load_node_full`.
+    ///
+    /// Achievements unlocked by the edge are reported in the returned
+    /// node's `unlocked` list, ahead of any unlocked by the destination's
+    /// entry functions.
+    ///
+    /// # Errors
+    ///
+    /// - `GameError::Parse("choice out of range")` if the node has fewer
+    ///   than `choice + 1` edges.
+    /// - All other errors are forwarded from `load_node_full`.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let next = await game.choose(3, 1);
+    /// next.unlocked.forEach(a => toast(a.title));
+    /// ```
+    #[wasm_bindgen]
+    pub async fn choose(&self, from_idx: usize, choice: usize) -> Result<JsValue, JsValue> {
+        self.traced(self.follow_edge(from_idx, choice)).await
+    }
+
+    /// Implementation of `choose`, without tracing.
+    async fn follow_edge(&self, from_idx: usize, choice: usize) -> Result<JsValue, JsValue> {
+        let (dest_idx, unlocked) = async {
+            let (file, local) = self.locate(from_idx)?;
+            let entry = Some(&file.index[local])
+                .filter(|e| e.chunk_type == ChunkType::Node)
+                .ok_or(GameError::Parse("not a node chunk"))?;
+            let node = file.record::<NodeRecord>(entry, Priority::Visible).await?;
+            let edge_cid = engine::edge_id(&node, choice)?;
+            let edge_entry = file
+                .find_entry(ChunkType::Edge, &edge_cid)
+                .ok_or(GameError::Parse("edge chunk not found"))?;
+            let edge = file.record::<EdgeRecord>(edge_entry, Priority::Visible).await?;
+            let unlocked = self.apply_effects(&edge.effects).await?;
+            let dest_idx = self.resolve_node(&file, &edge.dest).await?;
+            Ok::<_, JsValue>((dest_idx, unlocked))
+        }
+        .await
+        .map_err(|e| ErrorContext::node(from_idx).attach(e))?;
+        self.load_node_with_unlocks(dest_idx, unlocked)
+            .await
+            .map_err(|e| ErrorContext::node(dest_idx).attach(e))
+    }
+
+    /// Returns the full achievements catalog as a JS `Array` of
+    /// `{ id, title, description, hidden, unlocked }` objects.
+    ///
+    /// The catalog is fetched from the file's achievements chunks on first
+    /// use and kept in memory afterwards. Stories without a catalog yield
+    /// an empty array.
+    #[wasm_bindgen]
+    pub async fn achievements(&self) -> Result<JsValue, JsValue> {
+        let catalog = self.achievement_catalog().await?;
+        let unlocks = self.unlocks.borrow();
+        let out: Vec<AchievementOutput> = catalog
+            .iter()
+            .map(|a| a.to_output(unlocks.contains(&a.id)))
+            .collect();
+        to_value(&out).map_err(|e| JsValue::from(GameError::Other(e.to_string())))
+    }
+
+    /// Returns the IDs of all unlocked achievements, as uppercase hex
+    /// strings in the order they were unlocked.
+    ///
+    /// Unlocks persist across playthroughs and are not part of any save.
+    #[wasm_bindgen]
+    pub fn unlocked_achievements(&self) -> Array {
+        self.unlocks
+            .borrow()
+            .ids()
+            .iter()
+            .map(|id| JsValue::from_str(&hex_id(id)))
+            .collect()
+    }
+
+    /// Returns the story's typed metadata as a JS object:
     ///
-    /// # Errors
+    /// ```text
+    /// {
+    ///   title, authors: string[], version: "1.2.0", description,
+    ///   content_warnings: string[], cover_asset, min_engine_version,
+    ///   root_idx: number | null, engine_compatible: boolean
+    /// }
+    /// ```
     ///
-    /// - `GameError::Parse("not a node chunk")` if the indexed entry isn’t a node.
-    /// - `GameError::Http` if any range‐request fails.
-    /// - `GameError::Parse(...)` for TLV or decompression failures.
+    /// This describes the base story file; see `mounts` for expansions.
+    /// Missing keys are `null` (or empty arrays). Metadata chunks are
+    /// fetched on first call and kept in memory afterwards.
     ///
     /// # Examples
     ///
     /// ```ignore
-    /// let node = await game.load_node_full(3);
-    /// console.log(node.content);               // "You stand at a crossroads..."
-    /// console.log(node.edges.length);          // e.g. 2
+    /// const meta = await game.metadata();
+    /// document.title = meta.title ?? "Untitled";
     /// ```
     #[wasm_bindgen]
-    pub async fn load_node_full(&self, idx: usize) -> Result<JsValue, JsValue> {
-        // 1) Validate and fetch raw node chunk
-        let entry = self
-            .index
-            .get(idx)
-            .ok_or(GameError::Parse("node index out of range"))
-            .map_err(JsValue::from)?;
+    pub async fn metadata(&self) -> Result<JsValue, JsValue> {
+        let base = self.base_file()?;
+        let meta = base.story_metadata().await?;
+        to_value(&*meta).map_err(|e| JsValue::from(GameError::Other(e.to_string())))
+    }
+
+    /// Runs the node load `load` under a trace, handing the trace to the
+    /// `set_trace_callback` callback whether the load succeeds or not.
+    async fn traced(
+        &self,
+        load: impl Future<Output = Result<JsValue, JsValue>>,
+    ) -> Result<JsValue, JsValue> {
+        let trace = self.stats.begin();
+        let result = load.await;
+        let trace = trace.finish();
+        log_debug!(
+            "Node load: {} requests, {} bytes, {:.1} ms",
+            trace.requests,
+            trace.bytes,
+            trace.elapsed_ms
+        );
+        let on_trace = self.on_trace.borrow().clone();
+        if let (Some(cb), Ok(value)) = (on_trace, to_value(&trace))
+            && let Err(e) = cb.call1(&JsValue::NULL, &value)
+        {
+            log_error!("Trace callback threw: {:?}", e);
+        }
+        result
+    }
+
+    /// Shared implementation of `load_node_full` and `choose`: runs the
+    /// node's entry functions and loads it, prepending `unlocked` to the
+    /// achievements reported in the result.
+    async fn load_node_with_unlocks(
+        &self,
+        idx: usize,
+        mut unlocked: Vec<AchievementOutput>,
+    ) -> Result<JsValue, JsValue> {
+        // 1) Validate the node chunk, load its page if it has one, and
+        //    decode it
+        let started_at = self.generation.get() + 1;
+        self.generation.set(started_at);
+        self.sched.cancel(Priority::Prefetch);
+        let idx = self.follow_link(idx).await?;
+        let (file, local) = self.locate(idx)?;
+        let entry = &file.index[local];
         if entry.chunk_type != ChunkType::Node {
             return Err(GameError::Parse("not a node chunk").into());
         }
-        let raw_node = self.get_raw_chunk(entry).await?;
+        let max_gap = self.range_gap.get();
+        file.load_page(entry).await?;
+        let node = file.record::<NodeRecord>(entry, Priority::Visible).await?;
 
-        // 2) Parse TLV header and decompress node payload
-        let (_t, _id, flags, comp_len, un_len_opt, hdr_len) =
-            Self::parse_tlv_header(&raw_node).map_err(JsValue::from)?;
-        let payload = Self::decompress_payload(
-            flags,
-            &raw_node[hdr_len..hdr_len + comp_len as usize],
-            un_len_opt,
-        )
-        .map_err(JsValue::from)?;
-
-        // 3) Parse content sequence entries (with guards)
-        let seq_entries =
-            Self::parse_node_content_seq(&payload).map_err(JsValue::from)?; // Vec<ContentEntry>
-
-        // 4) Run guards and collect content IDs to include
-        let mut wanted_ids = Vec::new();
-        for entry in seq_entries {
-            if let Some((func_id, guard_bytes)) = entry.guard {
-                if !run_guard(func_id, &guard_bytes) {
-                    continue; // skip this segment
-                }
-            }
-            wanted_ids.push(entry.content_id);
-        }
+        // 2) Run entry functions (e.g. achievement unlocks)
+        unlocked.extend(self.apply_effects(&node.entry_funcs).await?);
 
-        // 5) Find index entries for the surviving content IDs
+        // 3) Run guards and collect content IDs to include
+        let wanted_ids = engine::visible_content(&node);
+
+        // 4) Find index entries for the surviving content IDs
         let content_indexes: Vec<&IndexEntry> = wanted_ids
             .iter()
             .map(|cid| {
-                self.index
-                    .iter()
-                    .find(|e| e.chunk_type == ChunkType::Content && e.chunk_id == *cid)
+                file.find_entry(ChunkType::Content, cid)
                     .ok_or(GameError::Parse("content chunk not found"))
             })
             .collect::<Result<_, _>>()
             .map_err(JsValue::from)?;
 
-        // 6) Fetch all content chunks in parallel
-        let raw_contents = try_join_all(content_indexes.iter().map(|e| self.get_raw_chunk(e)))
-            .await
-            .map_err(JsValue::from)?;
-
-        // 7) Decompress & parse each content text, concatenate
-        let mut full_text = String::new();
-        for raw_c in raw_contents {
-            let (_t2, _i2, f2, c2, u2_opt, h2) =
-                Self::parse_tlv_header(&raw_c).map_err(JsValue::from)?;
-            let pl = Self::decompress_payload(f2, &raw_c[h2..h2 + c2 as usize], u2_opt)
-                .map_err(JsValue::from)?;
-            let txt = Self::parse_content_text(&pl).map_err(JsValue::from)?;
-            full_text.push_str(&txt);
-        }
-
-        // 8) Edge parsing (identical to original implementation)
-        //    a) extract edge CIDs
-        let edge_cids =
-            Self::parse_node_edges_ids(&payload).map_err(JsValue::from)?;
-        //    b) locate edge index entries
-        let edge_entries: Vec<&IndexEntry> = edge_cids
+        // 5) Decode all content chunks (merging adjacent ranges) and
+        //    concatenate their text
+        let texts = file
+            .records::<ContentText>(&content_indexes, max_gap, Priority::Visible)
+            .await?;
+        let full_text: String = texts.iter().map(|t| t.0.as_str()).collect();
+
+        // 6) Edges
+        //    a) locate edge index entries
+        let edge_entries: Vec<&IndexEntry> = node
+            .edge_ids
             .iter()
             .map(|cid| {
-                self.index
-                    .iter()
-                    .find(|e| e.chunk_type == ChunkType::Edge && e.chunk_id == *cid)
+                file.find_entry(ChunkType::Edge, cid)
                     .ok_or(GameError::Parse("edge chunk not found"))
             })
             .collect::<Result<_, _>>()
             .map_err(JsValue::from)?;
-        //    c) fetch all edge chunks
-        let raw_edges = try_join_all(edge_entries.iter().map(|e| self.get_raw_chunk(e)))
-            .await
-            .map_err(JsValue::from)?;
-        //    d) parse edge metadata into (label_cid, dest_cid)
-        let mut edge_meta = Vec::with_capacity(edge_entries.len());
-        for raw_e in raw_edges {
-            let (_t3, _i3, f3, c3, u3_opt, h3) =
-                Self::parse_tlv_header(&raw_e).map_err(JsValue::from)?;
-            let pl = Self::decompress_payload(f3, &raw_e[h3..h3 + c3 as usize], u3_opt)
-                .map_err(JsValue::from)?;
-            let (label_cid, dest_cid) =
-                Self::parse_edge_label_dest_cids(&pl).map_err(JsValue::from)?;
-            edge_meta.push((label_cid, dest_cid));
-        }
-        //    e) fetch all label content chunks
-        let label_entries: Vec<&IndexEntry> = edge_meta
+        //    b) decode all edge chunks into (label, dest, effects)
+        let edges = file
+            .records::<EdgeRecord>(&edge_entries, max_gap, Priority::Visible)
+            .await?;
+        //    c) decode all label content chunks
+        let label_entries: Vec<&IndexEntry> = edges
             .iter()
-            .map(|(lc, _)| {
-                self.index
-                    .iter()
-                    .find(|e| e.chunk_type == ChunkType::Content && &e.chunk_id == lc)
+            .map(|e| {
+                file.find_entry(ChunkType::Content, &e.label)
                     .ok_or(GameError::Parse("label content not found"))
             })
             .collect::<Result<_, _>>()
             .map_err(JsValue::from)?;
-        let raw_labels = try_join_all(label_entries.iter().map(|e| self.get_raw_chunk(e)))
-            .await
-            .map_err(JsValue::from)?;
-        //    f) build EdgeOutput list
-        let mut edges_out = Vec::with_capacity(edge_meta.len());
-        for (raw_lbl, (_, dest_cid)) in raw_labels.into_iter().zip(edge_meta) {
-            let (_t4, _i4, f4, c4, u4_opt, h4) =
-                Self::parse_tlv_header(&raw_lbl).map_err(JsValue::from)?;
-            let pl = Self::decompress_payload(f4, &raw_lbl[h4..h4 + c4 as usize], u4_opt)
-                .map_err(JsValue::from)?;
-            let label_text = Self::parse_content_text(&pl).map_err(JsValue::from)?;
-            let dest_idx = self
-                .index
-                .iter()
-                .position(|e| e.chunk_type == ChunkType::Node && e.chunk_id == dest_cid)
-                .ok_or(GameError::Parse("edge destination node not found"))
-                .map_err(JsValue::from)?;
+        let labels = file
+            .records::<ContentText>(&label_entries, max_gap, Priority::Label)
+            .await?;
+        //    d) build EdgeOutput list
+        let neighbours: Vec<[u8; 3]> = edges.iter().map(|e| e.dest).collect();
+        let mut edges_out = Vec::with_capacity(edges.len());
+        for (label, edge) in labels.iter().zip(&edges) {
+            let dest_idx = self.edge_dest_idx(&file, &edge.dest)?;
             edges_out.push(EdgeOutput {
-                label: label_text,
+                label: label.0.clone(),
                 dest_idx: dest_idx as u32,
             });
         }
 
-        // 9) Serialize and return original NodeOutput shape
+        // 7) Prefetch likely next nodes in the background
+        let cfg = self.prefetch.get();
+        if cfg.depth > 0 && self.generation.get() == started_at {
+            let generation = self.generation.clone();
+            spawn_local(async move {
+                file.prefetch_neighbours(neighbours, cfg, generation, started_at)
+                    .await;
+            });
+        }
+
+        // 8) Serialize and return original NodeOutput shape
         let node = NodeOutput {
             content: full_text,
             edges:   edges_out,
+            unlocked,
         };
-        to_value(&node).map_err(|e| JsValue::from_str(&format!("{:?}", e)))
+        to_value(&node).map_err(|e| JsValue::from(GameError::Other(e.to_string())))
     }
 
     /// Loads the “root” node as specified by the metadata chunk
@@ -451,21 +2301,20 @@ impl CyoaGame {
     /// ```
     #[wasm_bindgen]
     pub async fn load_root_node_full(&self) -> Result<JsValue, JsValue> {
-        let meta_idx = self
+        let base = self.base_file()?;
+        let meta_idx = base
             .index
             .iter()
             .position(|e| e.chunk_type == ChunkType::Metadata && e.chunk_id == ID_ROOT_POINTER)
             .ok_or(GameError::MissingRoot)
             .map_err(JsValue::from)?;
-        let entry = &self.index[meta_idx];
-        let raw = self.get_raw_chunk(entry).await?;
+        let entry = &base.index[meta_idx];
+        let raw = base.get_raw_chunk(entry, Priority::Visible).await?;
         let (_t, _i, _f, _c, _u, h) = Self::parse_tlv_header(&raw).map_err(JsValue::from)?;
         let mut cid = [0u8; 3];
         cid.copy_from_slice(&raw[h..h + 3]);
-        let node_idx = self
-            .index
-            .iter()
-            .position(|e| e.chunk_type == ChunkType::Node && e.chunk_id == cid)
+        let node_idx = base
+            .node_position(&cid)
             .ok_or(GameError::Parse("root node chunk not found"))
             .map_err(JsValue::from)?;
         self.load_node_full(node_idx).await
@@ -473,104 +2322,6 @@ impl CyoaGame {
 
     // -- HTTP & parsing helpers --
 
-    /// Probes the remote file at `url` by requesting the first byte (bytes=0-0)
-    /// to determine:
-    /// 1. The total file size.
-    /// 2. Whether the server supports HTTP Range requests.
-    ///
-    /// # Parameters
-    ///
-    /// - `win`: Browser window object, used to perform the fetch.
-    /// - `url`: URL of the `.cyoa` file to probe.
-    ///
-    /// # Returns
-    ///
-    /// - `Ok((size, ranged))`:
-    ///   - `size`: Total size of the file in bytes.
-    ///   - `ranged`: `true` if the server responded with 206 Partial Content.
-    /// - `Err(GameError)`: On network errors, missing headers, or parse failures.
-    async fn probe_range(win: &Window, url: &str) -> Result<(u64, bool), GameError> {
-        let mut init = RequestInit::new();
-        init.set_method("GET");
-        init.set_mode(RequestMode::SameOrigin);
-        let mut hdrs = Headers::new().map_err(|e| GameError::Other(format!("{:?}", e)))?;
-        hdrs.append("Range", "bytes=0-0")
-            .map_err(|e| GameError::Other(format!("{:?}", e)))?;
-        init.set_headers(&hdrs.into());
-        let resp = JsFuture::from(win.fetch_with_str_and_init(url, &init))
-            .await
-            .map_err(|_| GameError::Http(0))?
-            .dyn_into::<Response>()
-            .map_err(|_| GameError::Other("Invalid response".to_string()))?;
-        let status = resp.status();
-        let ranged = status == 206;
-        let size = if ranged {
-            let cr = resp
-                .headers()
-                .get("content-range")
-                .map_err(|_| GameError::Other("No Content-Range".to_string()))?
-                .ok_or(GameError::Other("Bad Content-Range".to_string()))?;
-            cr.split('/')
-                .nth(1)
-                .ok_or(GameError::Other("Bad Content-Range".to_string()))?
-                .parse()
-                .map_err(|_| GameError::Other("Bad Content-Range".to_string()))?
-        } else {
-            let cl = resp
-                .headers()
-                .get("content-length")
-                .map_err(|_| GameError::Other("No Content-Length".to_string()))?
-                .ok_or(GameError::Other("Bad Content-Length".to_string()))?;
-            cl.parse()
-                .map_err(|_| GameError::Other("Bad Content-Length".to_string()))?
-        };
-        Ok((size, ranged))
-    }
-
-    /// Fetches a specific byte range `[start..=end]` from the remote file
-    /// via HTTP Range requests.
-    ///
-    /// # Parameters
-    ///
-    /// - `win`: Browser window object.
-    /// - `url`: URL of the `.cyoa` file.
-    /// - `start`: Starting byte offset.
-    /// - `end`: Optional ending byte offset (inclusive). If `None`, fetches until EOF.
-    ///
-    /// # Returns
-    ///
-    /// - `Ok(Vec<u8>)`: The raw bytes of the requested range.
-    /// - `Err(JsValue)`: On HTTP errors or failure to read the response buffer.
-    async fn fetch_range(
-        win: &Window,
-        url: &str,
-        start: u64,
-        end: Option<u64>,
-    ) -> Result<Vec<u8>, JsValue> {
-        let mut init = RequestInit::new();
-        init.set_method("GET");
-        init.set_mode(RequestMode::SameOrigin);
-        let mut hdrs = Headers::new().map_err(|e| JsValue::from_str(&format!("{:?}", e)))?;
-        let range = match end {
-            Some(e) => format!("bytes={}-{}", start, e),
-            None => format!("bytes={}-", start),
-        };
-        hdrs.append("Range", &range)
-            .map_err(|e| JsValue::from_str(&format!("{:?}", e)))?;
-        init.set_headers(&hdrs.into());
-        let resp = JsFuture::from(win.fetch_with_str_and_init(url, &init))
-            .await?
-            .dyn_into::<Response>()?;
-        if !resp.ok() {
-            return Err(GameError::Http(resp.status()).into());
-        }
-        let buf = JsFuture::from(resp.array_buffer()?).await?;
-        let arr = Uint8Array::new(&buf);
-        let mut v = vec![0; arr.length() as usize];
-        arr.copy_to(&mut v);
-        Ok(v)
-    }
-
     /// Parses the fixed‐length file header and returns the byte offset
     /// where the index blob begins.
     ///
@@ -583,7 +2334,7 @@ impl CyoaGame {
     /// - `Ok(offset)`: Index start offset.
     /// - `Err(GameError::InvalidMagic)`: If the magic bytes ≠ `b"CYOA"`.
     /// - `Err(GameError::Parse(_))`: On any I/O parsing errors.
-    fn parse_header(header: &[u8]) -> Result<u64, GameError> {
+    pub(crate) fn parse_header(header: &[u8]) -> Result<u64, GameError> {
         let mut c = Cursor::new(header);
         let mut magic = [0; 4];
         c.read_exact(&mut magic)
@@ -606,8 +2357,9 @@ impl CyoaGame {
     /// # Returns
     ///
     /// - `Ok(entries)`: Parsed list of index entries.
-    /// - `Err(GameError::Parse(_))`: On any malformed data.
-    fn parse_index(blob: &[u8]) -> Result<Vec<IndexEntry>, GameError> {
+    /// - `Err(GameError::Parse(_))`: On any malformed data, including
+    ///   empty chunks and spans past `u64::MAX`.
+    pub(crate) fn parse_index(blob: &[u8]) -> Result<Vec<IndexEntry>, GameError> {
         let mut c = Cursor::new(blob);
         let cnt = c
             .read_u32::<LittleEndian>()
@@ -616,15 +2368,8 @@ impl CyoaGame {
         for _ in 0..cnt {
             let t = c.read_u8().map_err(|_| GameError::Parse("Read u8"))?;
 
-            let chunk_type = match t {
-                0x01 => ChunkType::Node,
-                0x02 => ChunkType::Edge,
-                0x03 => ChunkType::Content,
-                0x04 => ChunkType::Metadata,
-                0xFD => ChunkType::ArgBlobPool,
-                0xFE => ChunkType::WasmTable,
-                _ => return Err(GameError::Parse("Unknown chunk type")),
-            };
+            let chunk_type =
+                ChunkType::from_u8(t).ok_or(GameError::Parse("Unknown chunk type"))?;
 
             let mut id = [0; 3];
             c.read_exact(&mut id)
@@ -635,6 +2380,11 @@ impl CyoaGame {
             let len = c
                 .read_u32::<LittleEndian>()
                 .map_err(|_| GameError::Parse("Read length"))?;
+            // Every chunk has a TLV header, so an empty one is corrupt; its
+            // last byte, `offset + length - 1`, would not exist.
+            if len == 0 || off.checked_add(len as u64).is_none() {
+                return Err(GameError::Parse("Invalid chunk span"));
+            }
             out.push(IndexEntry {
                 chunk_type,
                 chunk_id: id,
@@ -656,9 +2406,7 @@ impl CyoaGame {
     ///
     /// - `Ok((t, id, flags, comp_len, un_len, hlen))`: Parsed header fields.
     /// - `Err(GameError::Parse(_))`: On any read failures.
-    fn parse_tlv_header(
-        raw: &[u8],
-    ) -> Result<(u8, [u8; 3], u8, u32, Option<u32>, usize), GameError> {
+    pub(crate) fn parse_tlv_header(raw: &[u8]) -> Result<TlvHeader, GameError> {
         let mut c = Cursor::new(raw);
         let t = c.read_u8().map_err(|_| GameError::Parse("Read type"))?;
         let mut id = [0; 3];
@@ -681,6 +2429,22 @@ impl CyoaGame {
         Ok((t, id, flags, comp, un, hlen))
     }
 
+    /// Parses the TLV header of a raw chunk and returns its decompressed
+    /// payload.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Vec<u8>)`: The payload.
+    /// - `Err(GameError)`: On a malformed header, truncated chunk or
+    ///   decompression failure.
+    pub(crate) fn chunk_payload(raw: &[u8]) -> Result<Vec<u8>, GameError> {
+        let (_t, _id, flags, comp_len, un_len, hdr_len) = Self::parse_tlv_header(raw)?;
+        let body = raw
+            .get(hdr_len..hdr_len + comp_len as usize)
+            .ok_or(GameError::Parse("Chunk payload truncated"))?;
+        Self::decompress_payload(flags, body, un_len)
+    }
+
     /// Decompresses the given `data` slice with zstd if `flags & 1 != 0`,
     /// otherwise returns `data` directly.
     ///
@@ -694,7 +2458,7 @@ impl CyoaGame {
     ///
     /// - `Ok(Vec<u8>)`: Decompressed or identity copy.
     /// - `Err(GameError::Other)` or `Err(GameError::Parse)`: On errors.
-    fn decompress_payload(flags: u8, data: &[u8], un: Option<u32>) -> Result<Vec<u8>, GameError> {
+    pub(crate) fn decompress_payload(flags: u8, data: &[u8], un: Option<u32>) -> Result<Vec<u8>, GameError> {
         if flags & 1 != 0 {
             let target = un.ok_or(GameError::Parse("Missing uncompressed length"))? as usize;
             let mut out = vec![0u8; target];
@@ -707,7 +2471,44 @@ impl CyoaGame {
         }
     }
 
-    /// Extracts the content‐CID (3‐byte ID) from a node’s TLV payload.
+    /// Extracts the tags from a node’s payload as `(key, value)` pairs.
+    ///
+    /// Tags follow the node ID and default language; each is a u8-length
+    /// key and a u8-length value, both UTF-8.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Vec<(String, String)>)`: The tags in file order.
+    /// - `Err(GameError::Parse(_))`: On malformed TLV or invalid UTF-8.
+    pub(crate) fn parse_node_tags(data: &[u8]) -> Result<Vec<(String, String)>, GameError> {
+        let mut c = Cursor::new(data);
+
+        Self::skip_node_fields(&mut c, NodeField::Language)?;
+
+        // Read tags
+        let tag_cnt = c
+            .read_u16::<LittleEndian>()
+            .map_err(|_| GameError::Parse("Read tag count"))?;
+        let mut read_str = |what: &'static str| -> Result<String, GameError> {
+            let len = c.read_u8().map_err(|_| GameError::Parse(what))?;
+            let mut buf = vec![0; len as usize];
+            c.read_exact(&mut buf).map_err(|_| GameError::Parse(what))?;
+            String::from_utf8(buf).map_err(|_| GameError::Parse("Invalid UTF-8"))
+        };
+        let mut tags = Vec::with_capacity(tag_cnt as usize);
+        for _ in 0..tag_cnt {
+            let key = read_str("Read tag key")?;
+            let value = read_str("Read tag value")?;
+            tags.push((key, value));
+        }
+        Ok(tags)
+    }
+
+    /// Extracts the entry functions from a node’s payload.
+    ///
+    /// Entry functions follow the tags and are stored as
+    /// `u32 func_id, u32 arg_off, u32 arg_len`, where the argument bytes
+    /// are the slice `arg_off..arg_off + arg_len` of the payload itself.
     ///
     /// # Parameters
     ///
@@ -715,23 +2516,44 @@ impl CyoaGame {
     ///
     /// # Returns
     ///
-    /// - `Ok(cid)`: The 3‐byte content chunk ID.
+    /// - `Ok(Vec<FuncCall>)`: Each function ID with its argument bytes.
     /// - `Err(GameError::Parse(_))`: If the TLV structure is malformed.
-    fn parse_node_content_cid(data: &[u8]) -> Result<[u8; 3], GameError> {
+    pub(crate) fn parse_node_entry_funcs(data: &[u8]) -> Result<Vec<FuncCall>, GameError> {
         let mut c = Cursor::new(data);
-        Self::skip_node_header_fields(&mut c)?;
 
-        let tr_cnt = c.read_u16::<LittleEndian>().map_err(|_| GameError::Parse("Read translation count"))?;
-        if tr_cnt == 0 {
-            return Err(GameError::Parse("No translations available"));
-        }
+        Self::skip_node_fields(&mut c, NodeField::Tags)?;
 
-        let lang_len = c.read_u8().map_err(|_| GameError::Parse("Read lang length"))?;
-        c.seek(SeekFrom::Current(lang_len as i64)).map_err(|_| GameError::Parse("Skip lang bytes"))?;
+        // Read entry functions
+        let ef = c.read_u16::<LittleEndian>()
+            .map_err(|_| GameError::Parse("Read entry_funcs count"))?;
+        Self::read_func_calls(&mut c, data, ef)
+    }
 
-        let mut cid = [0u8; 3];
-        c.read_exact(&mut cid).map_err(|_| GameError::Parse("Read translation CID"))?;
-        Ok(cid)
+    /// Reads `cnt` function references (`u32 func_id, u32 arg_off,
+    /// u32 arg_len`) from `c`, resolving each argument slice against `data`.
+    fn read_func_calls(
+        c: &mut Cursor<&[u8]>,
+        data: &[u8],
+        cnt: u16,
+    ) -> Result<Vec<FuncCall>, GameError> {
+        let mut out = Vec::with_capacity(cnt as usize);
+        for _ in 0..cnt {
+            let func_id = c
+                .read_u32::<LittleEndian>()
+                .map_err(|_| GameError::Parse("Read func_id"))?;
+            let arg_off = c
+                .read_u32::<LittleEndian>()
+                .map_err(|_| GameError::Parse("Read arg_off"))? as usize;
+            let arg_len = c
+                .read_u32::<LittleEndian>()
+                .map_err(|_| GameError::Parse("Read arg_len"))? as usize;
+            let args = data
+                .get(arg_off..arg_off + arg_len)
+                .ok_or(GameError::Parse("Function args out of range"))?
+                .to_vec();
+            out.push((func_id, args));
+        }
+        Ok(out)
     }
 
     /// Extracts all outgoing edge‐CIDs (3‐byte IDs) from a node’s payload.
@@ -744,40 +2566,10 @@ impl CyoaGame {
     ///
     /// - `Ok(Vec<[u8;3]>)`: All referenced edge chunk IDs.
     /// - `Err(GameError::Parse(_))`: On malformed TLV.
-        fn parse_node_edges_ids(data: &[u8]) -> Result<Vec<[u8; 3]>, GameError> {
+    pub(crate) fn parse_node_edges_ids(data: &[u8]) -> Result<Vec<[u8; 3]>, GameError> {
         let mut c = Cursor::new(data);
 
-        // Skip Node ID
-        let id_len = c.read_u16::<LittleEndian>()
-            .map_err(|_| GameError::Parse("Read ID length"))?;
-        c.seek(SeekFrom::Current(id_len as i64))
-            .map_err(|_| GameError::Parse("Skip ID"))?;
-
-        // Skip default language
-        let dl = c.read_u8()
-            .map_err(|_| GameError::Parse("Read default language length"))?;
-        c.seek(SeekFrom::Current(dl as i64))
-            .map_err(|_| GameError::Parse("Skip default language"))?;
-
-        // Skip tags
-        let tag_cnt = c.read_u16::<LittleEndian>()
-            .map_err(|_| GameError::Parse("Read tag count"))?;
-        for _ in 0..tag_cnt {
-            let k = c.read_u8()
-                .map_err(|_| GameError::Parse("Read tag key length"))?;
-            c.seek(SeekFrom::Current(k as i64))
-                .map_err(|_| GameError::Parse("Skip tag key"))?;
-            let v = c.read_u8()
-                .map_err(|_| GameError::Parse("Read tag value length"))?;
-            c.seek(SeekFrom::Current(v as i64))
-                .map_err(|_| GameError::Parse("Skip tag value"))?;
-        }
-
-        // Skip entry functions
-        let ef = c.read_u16::<LittleEndian>()
-            .map_err(|_| GameError::Parse("Read entry_funcs count"))?;
-        c.seek(SeekFrom::Current((ef as i64) * 12))
-            .map_err(|_| GameError::Parse("Skip entry_funcs"))?;
+        Self::skip_node_fields(&mut c, NodeField::EntryFuncs)?;
 
         // Now read outgoing edges
         let out_cnt = c.read_u16::<LittleEndian>()
@@ -791,7 +2583,8 @@ impl CyoaGame {
         }
 
         Ok(ids)
-    }    
+    }
+
     /// Reads a UTF-8 text string from a `ChunkType::Content` payload.
     ///
     /// # Parameters
@@ -802,7 +2595,7 @@ impl CyoaGame {
     ///
     /// - `Ok(String)`: Parsed text.
     /// - `Err(GameError::Parse(_))`: On I/O or UTF-8 errors.
-    fn parse_content_text(data: &[u8]) -> Result<String, GameError> {
+    pub(crate) fn parse_content_text(data: &[u8]) -> Result<String, GameError> {
         let mut c = Cursor::new(data);
         let id_len = c
             .read_u16::<LittleEndian>()
@@ -828,7 +2621,7 @@ impl CyoaGame {
     ///
     /// - `Ok((label_cid, dest_cid))`: The 3-byte IDs for the label and destination node.
     /// - `Err(GameError::Parse(_))`: On missing labels or malformed TLV.
-    fn parse_edge_label_dest_cids(data: &[u8]) -> Result<([u8; 3], [u8; 3]), GameError> {
+    pub(crate) fn parse_edge_label_dest_cids(data: &[u8]) -> Result<([u8; 3], [u8; 3]), GameError> {
         let mut c = Cursor::new(data);
         let id_len = c
             .read_u16::<LittleEndian>()
@@ -860,9 +2653,73 @@ impl CyoaGame {
         Ok((label_cid, dest_cid))
     }
 
+    /// Parses a `ChunkType::Link` payload: a u8-length-prefixed UTF-8
+    /// namespace followed by the 3-byte node ID in that namespace.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok((namespace, node_cid))` on success.
+    /// - `Err(GameError::Parse(_))`: On truncated data or invalid UTF-8.
+    pub(crate) fn parse_link(data: &[u8]) -> Result<(String, [u8; 3]), GameError> {
+        let mut c = Cursor::new(data);
+        let ns_len = c.read_u8().map_err(|_| GameError::Parse("Read namespace len"))?;
+        let mut ns = vec![0; ns_len as usize];
+        c.read_exact(&mut ns)
+            .map_err(|_| GameError::Parse("Read namespace"))?;
+        let mut cid = [0u8; 3];
+        c.read_exact(&mut cid)
+            .map_err(|_| GameError::Parse("Read link target"))?;
+        let ns = String::from_utf8(ns).map_err(|_| GameError::Parse("Invalid UTF-8"))?;
+        Ok((ns, cid))
+    }
+
+    /// Parses the optional effects list of an edge’s payload.
+    ///
+    /// Effects trail the label translations as a u16 count followed by
+    /// `u32 func_id, u32 arg_off, u32 arg_len` entries. Edges written
+    /// before effects existed simply end after their labels, which yields
+    /// an empty list.
+    ///
+    /// # Parameters
+    ///
+    /// - `data`: Decompressed TLV payload of a `ChunkType::Edge`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Vec<FuncCall>)`: Each effect’s function ID and argument bytes.
+    /// - `Err(GameError::Parse(_))`: On malformed TLV.
+    pub(crate) fn parse_edge_effects(data: &[u8]) -> Result<Vec<FuncCall>, GameError> {
+        let mut c = Cursor::new(data);
+        let id_len = c
+            .read_u16::<LittleEndian>()
+            .map_err(|_| GameError::Parse("Read id_len"))?;
+        c.seek(SeekFrom::Current(id_len as i64 + 6))
+            .map_err(|_| GameError::Parse("Seek from/dest"))?;
+        let guard_cnt = c
+            .read_u16::<LittleEndian>()
+            .map_err(|_| GameError::Parse("Read guard cnt"))?;
+        c.seek(SeekFrom::Current((guard_cnt as i64) * 12))
+            .map_err(|_| GameError::Parse("Seek guard"))?;
+        let label_cnt = c
+            .read_u16::<LittleEndian>()
+            .map_err(|_| GameError::Parse("Read label cnt"))?;
+        for _ in 0..label_cnt {
+            let lang_len = c.read_u8().map_err(|_| GameError::Parse("Read lang len"))?;
+            c.seek(SeekFrom::Current(lang_len as i64 + 3))
+                .map_err(|_| GameError::Parse("Seek label"))?;
+        }
+        if c.position() as usize >= data.len() {
+            return Ok(Vec::new());
+        }
+        let effect_cnt = c
+            .read_u16::<LittleEndian>()
+            .map_err(|_| GameError::Parse("Read effect cnt"))?;
+        Self::read_func_calls(&mut c, data, effect_cnt)
+    }
+
     /// Parse the content_sequence entries from a Node payload slice.
     ///
-    /// Uses `skip_node_header_fields` to position the cursor, then reads:
+    /// Uses `skip_node_fields` to position the cursor, then reads:
     /// - u16: number of sequence entries
     /// - for each entry:
     ///   - u8: has_guard flag
@@ -877,10 +2734,10 @@ impl CyoaGame {
     ///
     /// - `Ok(Vec<ContentEntry>)` with all parsed sequence entries
     /// - `Err(GameError::Parse(_))` on any malformed data or I/O error
-    fn parse_node_content_seq(data: &[u8]) -> Result<Vec<ContentEntry>, GameError> {
+    pub(crate) fn parse_node_content_seq(data: &[u8]) -> Result<Vec<ContentEntry>, GameError> {
         let mut c = Cursor::new(data);
         // Skip all the common node fields
-        Self::skip_node_header_fields(&mut c)?;
+        Self::skip_node_fields(&mut c, NodeField::Translations)?;
 
         // How many sequence entries?
         let seq_cnt = c
@@ -936,9 +2793,10 @@ impl CyoaGame {
         Ok(out)
     }
     
-    /// Skip over common Node payload fields up to the content_sequence.
+    /// Skips a Node payload's fields in file order, up to and including
+    /// `through`.
     ///
-    /// Advances the cursor past:
+    /// The fields before the content_sequence are:
     /// 1. Node ID (u16 length + bytes)
     /// 2. Default language (u8 length + bytes)
     /// 3. Tags (u16 count + repeated key/value lengths + bytes)
@@ -949,12 +2807,14 @@ impl CyoaGame {
     /// # Parameters
     ///
     /// - `c`: Cursor over a Node chunk payload implementing `Read + Seek`.
+    /// - `through`: Last field to skip; `NodeField::Translations` leaves
+    ///   the cursor at the start of the content_sequence.
     ///
     /// # Returns
     ///
-    /// - `Ok(())` on success, with the cursor positioned at the start of the content_sequence.
+    /// - `Ok(())` on success, with the cursor positioned after `through`.
     /// - `Err(GameError::Parse(_))` if any read or seek operation fails.
-    fn skip_node_header_fields<R: Read + Seek>(c: &mut R) -> Result<(), GameError> {
+    fn skip_node_fields<R: Read + Seek>(c: &mut R, through: NodeField) -> Result<(), GameError> {
         // Skip Node ID
         let id_len = c.read_u16::<LittleEndian>().map_err(|_| GameError::Parse("Skip ID length"))?;
         c.seek(SeekFrom::Current(id_len as i64)).map_err(|_| GameError::Parse("Skip ID"))?;
@@ -962,6 +2822,9 @@ impl CyoaGame {
         // Skip default language
         let dl = c.read_u8().map_err(|_| GameError::Parse("Skip default_language length"))?;
         c.seek(SeekFrom::Current(dl as i64)).map_err(|_| GameError::Parse("Skip default_language"))?;
+        if through == NodeField::Language {
+            return Ok(());
+        }
 
         // Skip tags
         let tag_cnt = c.read_u16::<LittleEndian>().map_err(|_| GameError::Parse("Skip tag count"))?;
@@ -971,14 +2834,23 @@ impl CyoaGame {
             let v = c.read_u8().map_err(|_| GameError::Parse("Skip tag value length"))?;
             c.seek(SeekFrom::Current(v as i64)).map_err(|_| GameError::Parse("Skip tag value"))?;
         }
+        if through == NodeField::Tags {
+            return Ok(());
+        }
 
         // Skip entry functions
         let ef = c.read_u16::<LittleEndian>().map_err(|_| GameError::Parse("Skip entry_funcs count"))?;
         c.seek(SeekFrom::Current((ef as i64) * (4 + 4 + 4))).map_err(|_| GameError::Parse("Skip entry_funcs"))?;
+        if through == NodeField::EntryFuncs {
+            return Ok(());
+        }
 
         // Skip outgoing edges
         let out_cnt = c.read_u16::<LittleEndian>().map_err(|_| GameError::Parse("Skip outgoing count"))?;
         c.seek(SeekFrom::Current((out_cnt as i64) * 3)).map_err(|_| GameError::Parse("Skip outgoing IDs"))?;
+        if through == NodeField::Edges {
+            return Ok(());
+        }
 
         // Skip translations
         let tr_cnt = c.read_u16::<LittleEndian>().map_err(|_| GameError::Parse("Skip translations count"))?;
@@ -991,91 +2863,561 @@ impl CyoaGame {
         Ok(())
     }
 
-    /// Fetches the entire file at `url` without using Range requests.
-    ///
-    /// # Parameters
+    /// Builds a game with no mounted files, keying saved achievements by
+    /// `url`.
+    fn empty(url: &str) -> CyoaGame {
+        CyoaGame {
+            files: RefCell::new(Vec::new()),
+            packs: RefCell::new(HashMap::new()),
+            mounting: RefCell::new(HashMap::new()),
+            achievements: RefCell::new(None),
+            unlocks: RefCell::new(UnlockStore::load(url)),
+            prefetch: Cell::new(PrefetchConfig::default()),
+            range_gap: Cell::new(DEFAULT_RANGE_GAP),
+            cache_budget: Cell::new(DEFAULT_CACHE_BUDGET),
+            decoded_budget: Cell::new(DEFAULT_DECODED_BUDGET),
+            store: match CacheApiStore::available() {
+                Some(s) => Rc::new(s),
+                None => Rc::new(MemoryStore::default()),
+            },
+            download_gen: Cell::new(0),
+            sched: Scheduler::new(DEFAULT_MAX_CONCURRENCY),
+            net: Rc::new(Net::default()),
+            stats: Rc::new(Stats::default()),
+            on_trace: RefCell::new(None),
+            generation: Rc::new(Cell::new(0)),
+        }
+    }
+
+    /// Reads bytes `start..end` (end exclusive) of a local `Blob`.
+    async fn read_blob(blob: &Blob, start: u64, end: u64) -> Result<Vec<u8>, JsValue> {
+        let part = blob.slice_with_f64_and_f64(start as f64, end as f64)?;
+        let buf = JsFuture::from(part.array_buffer()).await?;
+        Ok(Uint8Array::new(&buf).to_vec())
+    }
+
+    /// Returns the base story file (the first one mounted).
+    fn base_file(&self) -> Result<Rc<StoryFile>, GameError> {
+        self.files
+            .borrow()
+            .first()
+            .cloned()
+            .ok_or(GameError::Other("No story mounted".to_string()))
+    }
+
+    /// Maps a global index to its story file and the local index within it.
+    fn locate(&self, idx: usize) -> Result<(Rc<StoryFile>, usize), GameError> {
+        let mut local = idx;
+        for f in self.files.borrow().iter() {
+            if local < f.index.len() {
+                return Ok((f.clone(), local));
+            }
+            local -= f.index.len();
+        }
+        Err(GameError::Parse("node index out of range"))
+    }
+
+    /// Returns the global index of `file`'s first index entry.
+    fn first_index_of(&self, file: &Rc<StoryFile>) -> usize {
+        self.files
+            .borrow()
+            .iter()
+            .take_while(|f| !Rc::ptr_eq(f, file))
+            .map(|f| f.index.len())
+            .sum()
+    }
+
+    /// Returns the global index an edge to `cid` in `file` reports as its
+    /// destination, without mounting anything.
     ///
-    /// - `win`: Browser window object.
-    /// - `url`: URL of the `.cyoa` file.
+    /// Local nodes map to their own index. An edge into another namespace
+    /// maps to the index of its `ChunkType::Link` chunk, which
+    /// `follow_link` resolves once that index is loaded, so expansions are
+    /// only mounted when a link is actually taken.
+    fn edge_dest_idx(&self, file: &Rc<StoryFile>, cid: &[u8; 3]) -> Result<usize, GameError> {
+        Ok(self.first_index_of(file) + engine::edge_dest_position(&file.index, cid)?)
+    }
+
+    /// Returns the global index of the node `idx` stands for: `idx` itself,
+    /// or the linked node if it is a `ChunkType::Link` entry (see
+    /// `edge_dest_idx`), mounting the linked file if needed.
+    async fn follow_link(&self, idx: usize) -> Result<usize, JsValue> {
+        let (file, local) = self.locate(idx)?;
+        let entry = &file.index[local];
+        if entry.chunk_type != ChunkType::Link {
+            return Ok(idx);
+        }
+        self.resolve_node(&file, &entry.chunk_id).await
+    }
+
+    /// Resolves an edge destination `cid` in `file` to a global node index.
     ///
-    /// # Returns
+    /// Local nodes resolve directly. Otherwise `cid` must name a
+    /// `ChunkType::Link` chunk pointing at a node in another namespace,
+    /// which is mounted on demand if it was registered but not yet loaded.
+    async fn resolve_node(&self, file: &Rc<StoryFile>, cid: &[u8; 3]) -> Result<usize, JsValue> {
+        if let Some(pos) = file.node_position(cid) {
+            return Ok(self.first_index_of(file) + pos);
+        }
+        let link = file
+            .find_entry(ChunkType::Link, cid)
+            .ok_or(GameError::Parse("edge destination node not found"))?;
+        let (namespace, target) = Self::parse_link(&file.fetch_payload(link, Priority::Visible).await?)?;
+        let target_file = self.file_for_namespace(&namespace, &[]).await?;
+        let pos = target_file
+            .node_position(&target)
+            .ok_or(GameError::Parse("linked node not found"))?;
+        Ok(self.first_index_of(&target_file) + pos)
+    }
+
+    /// Returns the mounted file declaring `namespace`, mounting the
+    /// registered pack for it if necessary.
     ///
-    /// - `Ok(Vec<u8>)`: All bytes of the file.
-    /// - `Err(JsValue)`: On HTTP errors or read failures.
-    async fn fetch_full(win: &Window, url: &str) -> Result<Vec<u8>, JsValue> {
-        let resp = JsFuture::from(win.fetch_with_str(url))
-            .await?
-            .dyn_into::<Response>()?;
-        if !resp.ok() {
-            return Err(GameError::Http(resp.status()).into());
+    /// `chain` lists the URLs whose dependencies are being resolved; see
+    /// `mount_url`.
+    async fn file_for_namespace(
+        &self,
+        namespace: &str,
+        chain: &[String],
+    ) -> Result<Rc<StoryFile>, JsValue> {
+        let files = self.files.borrow().clone();
+        for f in files {
+            if f.story_metadata().await?.namespace.as_deref() == Some(namespace) {
+                return Ok(f);
+            }
         }
-        let buf = JsFuture::from(resp.array_buffer()?).await?;
-        let arr = Uint8Array::new(&buf);
-        let mut v = vec![0; arr.length() as usize];
-        arr.copy_to(&mut v);
-        Ok(v)
+        let url = self
+            .packs
+            .borrow()
+            .get(namespace)
+            .cloned()
+            .ok_or_else(|| {
+                GameError::Dependency(format!("namespace {} is not mounted or registered", namespace))
+            })?;
+        self.mount_url(url, chain).await
     }
 
-    /// Issues multiple `fetch_range` calls in parallel for each byte range,
-    /// returning a `Vec` of each fetched segment.
+    /// Opens the story file at `url` and attaches it to the mounted files.
     ///
-    /// # Parameters
+    /// Mounting an already-mounted URL returns the existing file, and
+    /// mounting one that is still being opened waits for that mount
+    /// instead of opening it twice. `chain` lists the URLs whose
+    /// dependencies are being resolved; reaching one of them again means
+    /// the files depend on each other.
     ///
-    /// - `win`: Browser window object.
-    /// - `url`: URL of the `.cyoa` file.
-    /// - `ranges`: Slice of `(start, end)` tuples to fetch.
+    /// # Errors
     ///
-    /// # Returns
+    /// - `GameError::Dependency` if `url` is already in `chain`.
+    async fn mount_url(&self, url: String, chain: &[String]) -> Result<Rc<StoryFile>, JsValue> {
+        if let Some(f) = self.files.borrow().iter().find(|f| f.url == url) {
+            return Ok(f.clone());
+        }
+        if chain.contains(&url) {
+            return Err(GameError::Dependency(format!("dependency cycle through {}", url)).into());
+        }
+        let joined = self.mounting.borrow_mut().get_mut(&url).map(|waiters| {
+            let (tx, rx) = oneshot::channel();
+            waiters.push(tx);
+            rx
+        });
+        if let Some(rx) = joined {
+            return match rx.await {
+                Ok(result) => result.map_err(detach),
+                Err(_) => Err(GameError::Cancelled.into()),
+            };
+        }
+        let mount = PendingMount::start(&self.mounting, url.clone());
+        let result = match StoryFile::open(url, None, None, &self.net).await {
+            Ok(file) => self.attach(file, chain).await,
+            Err(e) => Err(e),
+        };
+        mount.finish(&result);
+        result
+    }
+
+    /// Checks an opened file's engine version and dependencies, then
+    /// appends it to the mounted files.
+    ///
+    /// `chain` lists the URLs whose dependencies are being resolved; see
+    /// `mount_url`.
+    fn attach<'a>(
+        &'a self,
+        file: StoryFile,
+        chain: &'a [String],
+    ) -> LocalBoxFuture<'a, Result<Rc<StoryFile>, JsValue>> {
+        async move {
+            let chain = [chain, std::slice::from_ref(&file.url)].concat();
+            let file = Rc::new(file);
+            let meta = file.story_metadata().await?;
+            if !meta.engine_compatible {
+                return Err(GameError::Dependency(format!(
+                    "{} requires engine {} or newer",
+                    file.url,
+                    meta.min_engine_version.unwrap_or(Version::engine())
+                ))
+                .into());
+            }
+            for dep in &meta.dependencies {
+                let dep_file = self.file_for_namespace(&dep.namespace, &chain).await?;
+                let dep_version = dep_file.story_metadata().await?.version;
+                if let Some(min) = dep.min_version
+                    && dep_version.is_none_or(|v| v < min)
+                {
+                    return Err(GameError::Dependency(format!(
+                        "{} requires {} {} or newer",
+                        file.url, dep.namespace, min
+                    ))
+                    .into());
+                }
+            }
+            if let Some(ns) = meta.namespace.as_deref() {
+                let files = self.files.borrow().clone();
+                for f in files {
+                    if f.story_metadata().await?.namespace.as_deref() == Some(ns) {
+                        return Err(GameError::Other(format!("namespace {} already mounted", ns)).into());
+                    }
+                }
+            }
+            file.raw_cache.borrow_mut().set_budget(self.cache_budget.get());
+            file.decoded.borrow_mut().set_budget(self.decoded_budget.get());
+            file.pin_defaults(meta.root_idx);
+            *file.sched.borrow_mut() = self.sched.clone();
+            *file.net.borrow_mut() = self.net.clone();
+            *file.stats.borrow_mut() = self.stats.clone();
+            if !file.is_local() {
+                let key = StoryKey {
+                    url: file.url.clone(),
+                    fingerprint: file.fingerprint().await?,
+                };
+                self.store.prune(&key).await;
+                *file.store.borrow_mut() = Some((self.store.clone(), key));
+            }
+            log_info!("Mounted {} ({} index entries)", file.url, file.index.len());
+            self.files.borrow_mut().push(file.clone());
+            *self.achievements.borrow_mut() = None;
+            Ok(file)
+        }
+        .boxed_local()
+    }
+
+    /// Downloads the index positions in `plan` of each file, reporting
+    /// overall progress to `on_progress`.
     ///
-    /// - `Ok(Vec<Vec<u8>>)` with each element the bytes for one range.
-    /// - `Err(JsValue)`: If any individual fetch fails.
-    async fn fetch_ranges(
+    /// Starting a download cancels any other one still running.
+    async fn download_plan(
         &self,
-        win: &Window,
-        url: &str,
-        ranges: &[(u64, u64)],
-    ) -> Result<Vec<Vec<u8>>, JsValue> {
-        let fetches = ranges
+        plan: Vec<(Rc<StoryFile>, Vec<usize>)>,
+        on_progress: Option<Function>,
+    ) -> Result<JsValue, JsValue> {
+        let started = self.download_gen.get() + 1;
+        self.download_gen.set(started);
+        let mut progress = DownloadProgress::default();
+        for (f, positions) in &plan {
+            for &i in positions {
+                progress.bytes_total += f.index[i].length as u64;
+                progress.chunks_total += 1;
+            }
+        }
+        let report = |p: &DownloadProgress| -> Result<(), JsValue> {
+            if let Some(cb) = &on_progress {
+                let value =
+                    to_value(p).map_err(|e| JsValue::from(GameError::Other(e.to_string())))?;
+                cb.call1(&JsValue::NULL, &value)?;
+            }
+            Ok(())
+        };
+        let cancelled = || self.download_gen.get() != started;
+        let max_gap = self.range_gap.get();
+        for (f, positions) in &plan {
+            let entries: Vec<&IndexEntry> = positions.iter().map(|&i| &f.index[i]).collect();
+            f.download(&entries, max_gap, &mut progress, &report, &cancelled)
+                .await?;
+        }
+        to_value(&progress).map_err(|e| JsValue::from(GameError::Other(e.to_string())))
+    }
+
+    /// Returns the achievements catalog, fetching and parsing every
+    /// `ChunkType::Achievements` chunk of every mounted file on first use.
+    async fn achievement_catalog(&self) -> Result<Rc<Vec<Achievement>>, JsValue> {
+        if let Some(catalog) = self.achievements.borrow().as_ref() {
+            return Ok(catalog.clone());
+        }
+        let files = self.files.borrow().clone();
+        let entries: Vec<(&Rc<StoryFile>, &IndexEntry)> = files
             .iter()
-            .map(|&(s, e)| Self::fetch_range(win, url, s, Some(e)));
-        let parts = try_join_all(fetches).await?;
-        Ok(parts)
+            .flat_map(|f| f.index.iter().map(move |e| (f, e)))
+            .filter(|(_, e)| e.chunk_type == ChunkType::Achievements)
+            .collect();
+        let payloads = try_join_all(entries.iter().map(|(f, e)| f.fetch_payload(e, Priority::Visible))).await?;
+        let mut catalog = Vec::new();
+        for pl in payloads {
+            catalog.extend(parse_catalog(&pl)?);
+        }
+        let catalog = Rc::new(catalog);
+        *self.achievements.borrow_mut() = Some(catalog.clone());
+        Ok(catalog)
     }
 
-    /// Retrieves the raw chunk bytes for `entry`, using HTTP Range if supported,
-    /// otherwise falling back to a full fetch. Uses an LRU cache to avoid
-    /// re-downloading the same chunk.
-    ///
-    /// # Parameters
-    ///
-    /// - `entry`: Reference to an `IndexEntry` describing offset and length.
-    ///
-    /// # Returns
+    /// Applies the engine-level effects among `calls`, returning the
+    /// achievements that were newly unlocked.
     ///
-    /// - `Ok(Arc<Vec<u8>>)` of the chunk’s raw bytes.
-    /// - `Err(JsValue)`: On network errors or missing window.
-    async fn get_raw_chunk(&self, entry: &IndexEntry) -> Result<Arc<Vec<u8>>, JsValue> {
-        if let Some(cached) = self.raw_cache.borrow_mut().get(&entry.chunk_id) {
-            return Ok(cached);
+    /// Function IDs the engine does not handle itself are ignored here.
+    async fn apply_effects(&self, calls: &[FuncCall]) -> Result<Vec<AchievementOutput>, JsValue> {
+        if !engine::unlocks_any(calls) {
+            return Ok(Vec::new());
         }
-        let win = window()
-            .ok_or(GameError::Other("No window".to_string()))
-            .map_err(JsValue::from)?;
-        let data = if self.supports_range {
-            Self::fetch_range(
-                &win,
-                &self.url,
-                entry.offset,
-                Some(entry.offset + entry.length as u64 - 1),
-            )
-            .await?
-        } else {
-            Self::fetch_full(&win, &self.url).await?
+        let catalog = self.achievement_catalog().await?;
+        let mut unlocks = self.unlocks.borrow_mut();
+        let unlocked = engine::apply_effects(calls, &catalog, |id| {
+            let fresh = unlocks.unlock(id);
+            if fresh {
+                log_debug!("Unlocked achievement {}", hex_id(&id));
+            }
+            fresh
+        })?;
+        Ok(unlocked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::TestRuntime;
+    use crate::transport::mock::{MockConfig, MockTransport};
+    use futures::executor::block_on;
+
+    const URL: &str = "/magium.story";
+
+    /// A story file holding `chunk` as content chunk `000001`, then its
+    /// index.
+    fn story(chunk: &[u8]) -> Vec<u8> {
+        story_of(&[(ChunkType::Content, [0, 0, 1], chunk.to_vec())])
+    }
+
+    /// A story file holding the raw `chunks` back to back, then their
+    /// index.
+    fn story_of(chunks: &[(ChunkType, [u8; 3], Vec<u8>)]) -> Vec<u8> {
+        let body: usize = chunks.iter().map(|(_, _, raw)| raw.len()).sum();
+        let mut bytes = b"CYOA".to_vec();
+        bytes.extend([0; 10]);
+        bytes.extend(((HEADER_LEN + body) as u64).to_le_bytes());
+        let mut index = (chunks.len() as u32).to_le_bytes().to_vec();
+        for (chunk_type, id, raw) in chunks {
+            index.push(*chunk_type as u8);
+            index.extend(id);
+            index.extend((bytes.len() as u64).to_le_bytes());
+            index.extend((raw.len() as u32).to_le_bytes());
+            bytes.extend(raw);
+        }
+        bytes.extend(index);
+        bytes
+    }
+
+    /// An uncompressed TLV chunk.
+    fn tlv(chunk_type: ChunkType, id: [u8; 3], payload: &[u8]) -> Vec<u8> {
+        let mut raw = vec![chunk_type as u8, id[0], id[1], id[2], 0];
+        raw.extend((payload.len() as u32).to_le_bytes());
+        raw.extend(payload);
+        raw
+    }
+
+    /// Opens `bytes` through a mock server, returning the file, the mock
+    /// and the backoff waits made.
+    fn open(bytes: Vec<u8>, config: MockConfig) -> (StoryFile, Rc<MockTransport>, Rc<TestRuntime>) {
+        let runtime = Rc::new(TestRuntime::default());
+        let mock = Rc::new(MockTransport::new(URL, bytes, config, runtime.clone()));
+        let net = Rc::new(Net::with_parts(mock.clone(), runtime.clone()));
+        let file = block_on(StoryFile::open(URL.to_string(), None, None, &net))
+            .unwrap_or_else(|_| panic!("open failed"));
+        (file, mock, runtime)
+    }
+
+    /// Fetches `file`'s only chunk from the network, bypassing caches.
+    fn fetch_chunk(file: &StoryFile) -> Result<Vec<u8>, GameError> {
+        let entry = &file.index[0];
+        let end = entry.offset + entry.length as u64 - 1;
+        let priority = Rc::new(Cell::new(Priority::Visible));
+        block_on(file.fetch_scheduled(priority, entry.offset, end))
+    }
+
+    #[test]
+    fn rejects_empty_and_overflowing_index_entries() {
+        let entry = |offset: u64, length: u32| {
+            let mut blob = 1u32.to_le_bytes().to_vec();
+            blob.push(ChunkType::Content as u8);
+            blob.extend([0, 0, 1]);
+            blob.extend(offset.to_le_bytes());
+            blob.extend(length.to_le_bytes());
+            CyoaGame::parse_index(&blob)
         };
-        let arc = Arc::new(data);
-        self.raw_cache
-            .borrow_mut()
-            .insert(entry.chunk_id, arc.clone());
-        Ok(arc)
+        assert_eq!(entry(22, 9).unwrap()[0].length, 9);
+        assert!(matches!(entry(0, 0), Err(GameError::Parse(_))));
+        assert!(matches!(entry(u64::MAX - 3, 9), Err(GameError::Parse(_))));
+    }
+
+    #[test]
+    fn loads_whole_file_when_range_is_ignored() {
+        let chunk = vec![0xAB; 100];
+        let (file, mock, _) = open(
+            story(&chunk),
+            MockConfig {
+                ignore_range: true,
+                ..Default::default()
+            },
+        );
+        assert!(file.whole.is_some());
+        let entry = file.index[0].clone();
+        let raw = block_on(file.get_raw_chunk(&entry, Priority::Visible))
+            .unwrap_or_else(|_| panic!("chunk load failed"));
+        assert_eq!(*raw, chunk);
+        assert!(!file.raw_cache.borrow().contains(&entry.key()));
+        assert_eq!(mock.requests.get(), 1);
+    }
+
+    #[test]
+    fn retries_failed_requests_through_a_load() {
+        for fail_status in [None, Some(502)] {
+            let chunk = vec![0xAB; 100];
+            let (file, mock, runtime) = open(
+                story(&chunk),
+                MockConfig {
+                    fail_every: 2,
+                    fail_status,
+                    ..Default::default()
+                },
+            );
+            assert!(file.whole.is_none());
+            let entry = file.index[0].clone();
+            let raw = block_on(file.get_raw_chunk(&entry, Priority::Visible))
+                .unwrap_or_else(|_| panic!("chunk load failed"));
+            assert_eq!(*raw, chunk);
+            // Every second request fails: the header, index and chunk
+            // requests once each.
+            assert_eq!(mock.requests.get(), 7);
+            assert_eq!(runtime.sleeps.borrow().len(), 3);
+        }
+    }
+
+    #[test]
+    fn joins_a_chunk_already_in_flight() {
+        let chunk = vec![0xAB; 100];
+        let (file, mock, _) = open(story(&chunk), MockConfig::default());
+        // Hold the only permit so the first load stays queued while the
+        // second one arrives.
+        let sched = Scheduler::new(1);
+        *file.sched.borrow_mut() = sched.clone();
+        let permit = block_on(sched.acquire(Rc::new(Cell::new(Priority::Visible)))).unwrap();
+        let requests = mock.requests.get();
+        let entry = file.index[0].clone();
+        let mut both = futures::future::join(
+            file.get_raw_chunk(&entry, Priority::Prefetch),
+            file.get_raw_chunk(&entry, Priority::Visible),
+        )
+        .boxed_local();
+        let mut cx = std::task::Context::from_waker(futures::task::noop_waker_ref());
+        assert!(both.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(file.inflight.borrow().len(), 1);
+        drop(permit);
+        for raw in <[_; 2]>::from(block_on(both)) {
+            assert_eq!(*raw.unwrap_or_else(|_| panic!("chunk load failed")), chunk);
+        }
+        assert_eq!(mock.requests.get(), requests + 1);
+        assert!(file.inflight.borrow().is_empty());
+    }
+
+    #[test]
+    fn gives_up_on_truncated_chunks() {
+        // The header request reads the bootstrap window too; truncate only
+        // replies longer than that.
+        let head_len = HEADER_LEN + BOOTSTRAP_WINDOW as usize;
+        let (file, mock, _) = open(
+            story(&vec![0xAB; head_len + 100]),
+            MockConfig {
+                truncate_to: Some(head_len),
+                ..Default::default()
+            },
+        );
+        let requests = mock.requests.get();
+        let err = fetch_chunk(&file).unwrap_err();
+        assert!(matches!(err, GameError::BadResponse(_)), "{:?}", err);
+        let retries = file.net.borrow().policy().retries;
+        assert_eq!(mock.requests.get(), requests + 1 + retries);
+    }
+
+    #[test]
+    fn downloads_around_patched_chunks() {
+        let (a, b) = ([0, 0, 1], [0, 0, 2]);
+        let content = |id, text: &[u8]| (ChunkType::Content, id, tlv(ChunkType::Content, id, text));
+        let bytes = story_of(&[content(a, b"old text"), content(b, b"kept")]);
+        let (file, mock, _) = open(bytes, MockConfig::default());
+        let store = Rc::new(MemoryStore::default());
+        let key = StoryKey {
+            url: URL.to_string(),
+            fingerprint: 1,
+        };
+        *file.store.borrow_mut() = Some((store.clone(), key.clone()));
+        let c = [0, 0, 3];
+        let upsert = |id: [u8; 3], text: &[u8]| PatchOp::Upsert {
+            chunk_type: ChunkType::Content as u8,
+            id,
+            data: tlv(ChunkType::Content, id, text),
+        };
+        let patch = Patch {
+            base_fingerprint: 1,
+            target_fingerprint: 2,
+            ops: vec![upsert(a, b"replaced text"), upsert(c, b"added")],
+        };
+        let file = file.with_patch(&patch).unwrap();
+        let requests = mock.requests.get();
+        let entries: Vec<&IndexEntry> = file.index.iter().collect();
+        let mut progress = DownloadProgress::default();
+        block_on(file.download(&entries, 0, &mut progress, &|_| Ok(()), &|| false))
+            .unwrap_or_else(|_| panic!("download failed"));
+        assert_eq!(progress.chunks_done, 3);
+        assert_eq!(mock.requests.get(), requests + 1);
+        let stored = |id| block_on(store.get(&key, (ChunkType::Content, id)));
+        assert_eq!(stored(b), Some(tlv(ChunkType::Content, b, b"kept")));
+        assert_eq!(stored(a), None);
+        assert_eq!(stored(c), None);
+    }
+
+    #[test]
+    fn caches_page_contents_but_not_the_page() {
+        let id = [0, 0, 1];
+        let node = tlv(ChunkType::Node, id, b"node record");
+        let mut page = 1u16.to_le_bytes().to_vec();
+        page.extend(&node);
+        let (file, mock, _) = open(
+            story_of(&[
+                (ChunkType::Node, id, node.clone()),
+                (ChunkType::Page, id, tlv(ChunkType::Page, id, &page)),
+            ]),
+            MockConfig::default(),
+        );
+        let entry = file.index[0].clone();
+        block_on(file.load_page(&entry)).unwrap_or_else(|_| panic!("page load failed"));
+        assert!(file.raw_cache.borrow().contains(&entry.key()));
+        assert!(!file.raw_cache.borrow().contains(&(ChunkType::Page, id)));
+        assert!(file.inflight.borrow().is_empty());
+        let requests = mock.requests.get();
+        let raw = block_on(file.get_raw_chunk(&entry, Priority::Visible))
+            .unwrap_or_else(|_| panic!("chunk load failed"));
+        assert_eq!(*raw, node);
+        assert_eq!(mock.requests.get(), requests);
+    }
+
+    #[test]
+    fn reports_a_story_replaced_after_opening() {
+        let (file, mock, _) = open(
+            story(&[0xAB; 100]),
+            MockConfig {
+                change_after: Some(3),
+                ..Default::default()
+            },
+        );
+        assert_eq!(mock.requests.get(), 3);
+        let err = fetch_chunk(&file).unwrap_err();
+        assert!(matches!(err, GameError::StoryChanged), "{:?}", err);
+        assert_eq!(mock.requests.get(), 4);
     }
 }
@@ -0,0 +1,180 @@
+//! # Story Engine
+//!
+//! The synchronous rules of playing a story, shared by the async
+//! `CyoaGame` and the native `StoryReader`: which content a node shows,
+//! which achievements its effects unlock and where its edges lead.
+//!
+//! Nothing here fetches anything. Callers look chunks up, decode them into
+//! records and hand those in, so the two front ends differ only in how
+//! they get at the bytes.
+
+use crate::achievements::{Achievement, AchievementOutput, FN_UNLOCK_ACHIEVEMENT};
+use crate::decoded::NodeRecord;
+use crate::decoder::{ChunkType, FuncCall, GameError, IndexEntry};
+use crate::wasmtable::run_guard;
+
+/// Returns `true` if any of `calls` unlocks an achievement, so a caller
+/// knows whether to load the catalog before `apply_effects`.
+pub(crate) fn unlocks_any(calls: &[FuncCall]) -> bool {
+    calls
+        .iter()
+        .any(|(func_id, _)| *func_id == FN_UNLOCK_ACHIEVEMENT)
+}
+
+/// Applies the engine-level effects among `calls`.
+///
+/// `unlock` records an achievement ID and returns `false` if it was
+/// already unlocked; unlocks persist across playthroughs, so where they
+/// are kept is up to the caller.
+///
+/// # Returns
+///
+/// - `Ok(Vec<AchievementOutput>)`: The newly unlocked achievements found
+///   in `catalog`, in effect order.
+/// - `Err(GameError::Parse)`: If an unlock effect has malformed arguments.
+pub(crate) fn apply_effects(
+    calls: &[FuncCall],
+    catalog: &[Achievement],
+    mut unlock: impl FnMut([u8; 3]) -> bool,
+) -> Result<Vec<AchievementOutput>, GameError> {
+    let mut fresh = Vec::new();
+    for (func_id, args) in calls {
+        if *func_id != FN_UNLOCK_ACHIEVEMENT {
+            continue;
+        }
+        let id: [u8; 3] = args
+            .get(..3)
+            .and_then(|b| b.try_into().ok())
+            .ok_or(GameError::Parse("Bad achievement effect args"))?;
+        if !unlock(id) {
+            continue;
+        }
+        if let Some(a) = catalog.iter().find(|a| a.id == id) {
+            fresh.push(a.to_output(true));
+        }
+    }
+    Ok(fresh)
+}
+
+/// Returns the IDs of the content chunks `node` shows, in display order:
+/// every segment whose guard passes.
+pub(crate) fn visible_content(node: &NodeRecord) -> Vec<[u8; 3]> {
+    node.content_seq
+        .iter()
+        .filter(|seg| {
+            seg.guard
+                .as_ref()
+                .is_none_or(|(func_id, guard_bytes)| run_guard(*func_id, guard_bytes))
+        })
+        .map(|seg| seg.content_id)
+        .collect()
+}
+
+/// Returns the ID of the edge chunk behind choice `choice` of `node`.
+pub(crate) fn edge_id(node: &NodeRecord, choice: usize) -> Result<[u8; 3], GameError> {
+    node.edge_ids
+        .get(choice)
+        .copied()
+        .ok_or(GameError::Parse("choice out of range"))
+}
+
+/// Looks up the index entry for the chunk with the given type and ID.
+pub(crate) fn find_entry<'a>(
+    index: &'a [IndexEntry],
+    chunk_type: ChunkType,
+    cid: &[u8; 3],
+) -> Option<&'a IndexEntry> {
+    index
+        .iter()
+        .find(|e| e.chunk_type == chunk_type && &e.chunk_id == cid)
+}
+
+/// Returns the position of the node chunk `cid` in `index`, if present.
+pub(crate) fn node_position(index: &[IndexEntry], cid: &[u8; 3]) -> Option<usize> {
+    index
+        .iter()
+        .position(|e| e.chunk_type == ChunkType::Node && &e.chunk_id == cid)
+}
+
+/// Returns the position in `index` an edge to `cid` leads to: the node
+/// chunk `cid`, or else the `ChunkType::Link` chunk `cid` for an edge into
+/// another story file. Links are only followed when that position is
+/// loaded.
+pub(crate) fn edge_dest_position(index: &[IndexEntry], cid: &[u8; 3]) -> Result<usize, GameError> {
+    node_position(index, cid)
+        .or_else(|| {
+            index
+                .iter()
+                .position(|e| e.chunk_type == ChunkType::Link && &e.chunk_id == cid)
+        })
+        .ok_or(GameError::Parse("edge destination node not found"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(chunk_type: ChunkType, id: u8) -> IndexEntry {
+        IndexEntry {
+            chunk_type,
+            chunk_id: [0, 0, id],
+            offset: 0,
+            length: 0,
+        }
+    }
+
+    fn achievement(id: u8) -> Achievement {
+        Achievement {
+            id: [0, 0, id],
+            title: format!("A{}", id),
+            description: String::new(),
+            hidden: false,
+        }
+    }
+
+    #[test]
+    fn unlocks_each_achievement_once() {
+        let catalog = [achievement(1), achievement(2)];
+        let calls = vec![
+            (FN_UNLOCK_ACHIEVEMENT, vec![0, 0, 1]),
+            (7, vec![0, 0, 2]),
+            (FN_UNLOCK_ACHIEVEMENT, vec![0, 0, 2]),
+            (FN_UNLOCK_ACHIEVEMENT, vec![0, 0, 1]),
+        ];
+        let mut unlocked = vec![[0, 0, 2]];
+        let fresh = apply_effects(&calls, &catalog, |id| {
+            let new = !unlocked.contains(&id);
+            if new {
+                unlocked.push(id);
+            }
+            new
+        })
+        .unwrap();
+        assert_eq!(
+            fresh.iter().map(|a| a.title.as_str()).collect::<Vec<_>>(),
+            ["A1"]
+        );
+        assert_eq!(unlocked, [[0, 0, 2], [0, 0, 1]]);
+        assert!(unlocks_any(&calls));
+        assert!(!unlocks_any(&calls[1..2]));
+    }
+
+    #[test]
+    fn rejects_short_unlock_args() {
+        let calls = vec![(FN_UNLOCK_ACHIEVEMENT, vec![0, 1])];
+        assert!(apply_effects(&calls, &[], |_| true).is_err());
+    }
+
+    #[test]
+    fn resolves_edges_to_nodes_before_links() {
+        let index = [
+            entry(ChunkType::Content, 1),
+            entry(ChunkType::Link, 2),
+            entry(ChunkType::Node, 1),
+            entry(ChunkType::Link, 1),
+        ];
+        assert_eq!(edge_dest_position(&index, &[0, 0, 1]).unwrap(), 2);
+        assert_eq!(edge_dest_position(&index, &[0, 0, 2]).unwrap(), 1);
+        assert!(edge_dest_position(&index, &[0, 0, 3]).is_err());
+    }
+}
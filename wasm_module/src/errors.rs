@@ -0,0 +1,149 @@
+//! # JavaScript Errors
+//!
+//! Every failure reaches JavaScript as an `Error` object carrying a stable
+//! `code`, whether it is worth retrying, and where it happened: the chunk,
+//! node and byte offset involved, and the HTTP status if there was one.
+//! Callers branch on `code`; the message is for people only.
+//!
+//! Context is added on the way out: the code that knows which chunk or
+//! node it was working on attaches it with `ErrorContext::attach`, which
+//! never overwrites a field set closer to the failure.
+
+use js_sys::{Error, Object, Reflect};
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::*;
+
+use crate::decoder::{ChunkType, GameError, IndexEntry};
+use crate::utils::hex_id;
+
+#[wasm_bindgen(typescript_custom_section)]
+const CYOA_ERROR: &str = r#"
+/** Stable codes of the errors thrown by `CyoaGame`. */
+export type CyoaErrorCode =
+  | "HTTP"
+  | "INVALID_MAGIC"
+  | "INDEX_OUT_OF_RANGE"
+  | "PARSE"
+  | "MISSING_ROOT"
+  | "DEPENDENCY"
+  | "PATCH"
+  | "CANCELLED"
+  | "NETWORK"
+  | "TIMEOUT"
+  | "ABORTED"
+  | "BAD_RESPONSE"
+  | "STORY_CHANGED"
+  | "OTHER";
+
+/** Error thrown by `CyoaGame`; fields are absent when not applicable. */
+export interface CyoaError extends Error {
+  name: "CyoaError";
+  code: CyoaErrorCode;
+  /** Type of the chunk involved, e.g. "node" or "content". */
+  chunkType?: string;
+  /** ID of the chunk involved, as uppercase hex, e.g. "000102". */
+  chunkId?: string;
+  /** Index of the node being loaded. */
+  nodeId?: number;
+  /** Byte offset in the story file of the chunk or range involved. */
+  offset?: number;
+  /** HTTP status of the failed response. */
+  status?: number;
+  /** Whether trying again may succeed (network errors, 5xx, timeouts). */
+  retryable: boolean;
+}
+"#;
+
+impl From<GameError> for JsValue {
+    fn from(err: GameError) -> JsValue {
+        let js = Error::new(&err.to_string());
+        js.set_name("CyoaError");
+        set(&js, "code", &JsValue::from_str(err.code()));
+        set(&js, "retryable", &JsValue::from_bool(err.is_transient()));
+        if let GameError::Http(status) = err {
+            set(&js, "status", &JsValue::from(status));
+        }
+        js.into()
+    }
+}
+
+/// Where a failure happened; see the module docs.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct ErrorContext {
+    pub(crate) chunk: Option<(ChunkType, [u8; 3])>,
+    pub(crate) node: Option<usize>,
+    pub(crate) offset: Option<u64>,
+}
+
+impl ErrorContext {
+    /// Context of a failure while reading or decoding `entry`.
+    pub(crate) fn chunk(entry: &IndexEntry) -> Self {
+        ErrorContext {
+            chunk: Some(entry.key()),
+            node: None,
+            offset: Some(entry.offset),
+        }
+    }
+
+    /// Context of a failure while loading node `idx`.
+    pub(crate) fn node(idx: usize) -> Self {
+        ErrorContext {
+            node: Some(idx),
+            ..Default::default()
+        }
+    }
+
+    /// Context of a failure fetching the range starting at `offset`.
+    pub(crate) fn range(offset: u64) -> Self {
+        ErrorContext {
+            offset: Some(offset),
+            ..Default::default()
+        }
+    }
+
+    /// Adds this context to `err` and returns it. Fields `err` already has
+    /// are kept; values that are not objects (exceptions thrown by
+    /// callbacks) are returned unchanged.
+    pub(crate) fn attach(&self, err: JsValue) -> JsValue {
+        if !err.is_object() {
+            return err;
+        }
+        let fill = |key: &str, value: JsValue| {
+            let missing = Reflect::get(&err, &JsValue::from_str(key))
+                .map(|v| v.is_undefined())
+                .unwrap_or(false);
+            if missing {
+                set(&err, key, &value);
+            }
+        };
+        if let Some((chunk_type, id)) = self.chunk {
+            fill("chunkType", JsValue::from_str(chunk_type.name()));
+            fill("chunkId", JsValue::from_str(&hex_id(&id)));
+        }
+        if let Some(node) = self.node {
+            fill("nodeId", JsValue::from_f64(node as f64));
+        }
+        if let Some(offset) = self.offset {
+            fill("offset", JsValue::from_f64(offset as f64));
+        }
+        err
+    }
+}
+
+/// Returns a copy of an error shared by several callers (a joined
+/// in-flight fetch), so context one of them attaches does not show up on
+/// the others' errors.
+pub(crate) fn detach(err: JsValue) -> JsValue {
+    let Some(original) = err.dyn_ref::<Error>() else {
+        return err;
+    };
+    let copy = Error::new(&String::from(original.message()));
+    copy.set_name(&String::from(original.name()));
+    Object::assign(&copy, original);
+    copy.into()
+}
+
+/// Sets `obj[key] = value`, ignoring failures (e.g. frozen objects).
+fn set(obj: &JsValue, key: &str, value: &JsValue) {
+    let _ = Reflect::set(obj, &JsValue::from_str(key), value);
+}
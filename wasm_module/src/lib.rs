@@ -17,6 +17,14 @@
 extern crate cfg_if;
 extern crate wasm_bindgen;
 
+/// Leveled console logging.
+///
+/// The `logging` module provides the `log_error!` .. `log_debug!` macros
+/// used throughout the crate, with a level that can be changed at runtime.
+/// It comes first so the macros are in scope in every other module.
+#[macro_use]
+mod logging;
+
 /// Core decoding logic for the CYOA format.
 ///
 /// The `decoder` module implements the `CyoaGame` struct and its associated
@@ -24,6 +32,107 @@ extern crate wasm_bindgen;
 /// and the public WASM-bindgen interface.
 mod decoder;
 
+/// Achievements catalog parsing and persistent unlock tracking.
+///
+/// The `achievements` module reads the catalog chunk and records which
+/// achievements the player has unlocked, independently of per-run saves.
+mod achievements;
+
+/// Typed story metadata registry.
+///
+/// The `metadata` module maps metadata chunk IDs to typed keys (title,
+/// authors, versions, ...) and decodes their values.
+mod metadata;
+
+/// Binary patch format for story updates.
+///
+/// The `patch` module parses and writes patch files, computes story
+/// fingerprints and diffs two story files into a patch. It is public so
+/// the `story_diff` tool can use it.
+pub mod patch;
+
+/// Synchronous reader for story files on local disk.
+///
+/// The `reader` module decodes a complete in-memory or memory-mapped
+/// story without any browser APIs. It is public so the Tauri shell can
+/// serve stories natively.
+pub mod reader;
+
+/// Story rules shared by the async game and the native reader.
+///
+/// The `engine` module decides which content a node shows, what its
+/// effects unlock and where its edges lead, given already decoded records.
+mod engine;
+
+/// Bootstrap section for a fast first screen.
+///
+/// The `bootstrap` module parses the optional chunk after the header that
+/// bundles the root node with its content, edges and labels.
+mod bootstrap;
+
+/// Per-node page chunks.
+///
+/// The `page` module reads the chunk lists of page chunks, which bundle
+/// everything one node needs, and of the bootstrap section.
+mod page;
+
+/// Byte-budgeted chunk cache.
+///
+/// The `cache` module holds raw chunks keyed by type and ID, evicting the
+/// least recently used ones once a byte budget is exceeded.
+mod cache;
+
+/// Decoded chunk records.
+///
+/// The `decoded` module holds parsed node and edge records and content
+/// text, cached per file so revisiting a node needs no decompression.
+mod decoded;
+
+/// Planning and checking of HTTP Range requests.
+///
+/// The `ranges` module merges the byte spans of the chunks a load needs
+/// into as few requests as possible, and extracts the requested bytes from
+/// whatever form of response the server sends back.
+mod ranges;
+
+/// Scheduling of chunk requests.
+///
+/// The `sched` module caps concurrent requests and runs queued ones by
+/// priority, visible loads before prefetching and offline downloads.
+mod sched;
+
+/// Structured errors for JavaScript.
+///
+/// The `errors` module turns a `GameError` into a JS `Error` with a stable
+/// code, and attaches the chunk, node and byte offset a failure involved.
+mod errors;
+
+/// Network options, timeouts, retries and cancellation.
+///
+/// The `net` module builds each HTTP request from the game's options (base
+/// URL, mode, credentials, headers) and runs it under a timeout and an
+/// abort signal, retrying transient failures with exponential backoff.
+mod net;
+
+/// Network transports.
+///
+/// The `transport` module defines how requests reach story files: through
+/// the browser's `fetch`, or in unit tests through an in-memory mock that
+/// simulates misbehaving servers.
+mod transport;
+
+/// Load instrumentation.
+///
+/// The `trace` module counts requests, bytes, cache hits and time spent
+/// per node load and in total.
+mod trace;
+
+/// Persistent chunk stores.
+///
+/// The `store` module keeps downloaded chunks across sessions, keyed by
+/// story fingerprint, in the browser's Cache API or in memory.
+mod store;
+
 mod wasmtable;
 
 /// Utility helpers and browser integration code.
@@ -0,0 +1,116 @@
+//! # Logging
+//!
+//! Leveled logging to the browser console. The level is global and can be
+//! changed at runtime with `CyoaGame.setLogLevel`, so release builds can be
+//! asked for details on a player's device. Debug builds start at `debug`,
+//! release builds at `warn`.
+//!
+//! Messages below the current level are not even formatted.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(typescript_custom_section)]
+const LOG_LEVEL: &str = r#"
+/** Console log level of the engine, for `CyoaGame.setLogLevel`. */
+export type LogLevel = "off" | "error" | "warn" | "info" | "debug";
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    /// Level name passed to `CyoaGame.setLogLevel`.
+    #[wasm_bindgen(typescript_type = "LogLevel")]
+    pub type LogLevel;
+}
+
+/// Severity of a message; a level shows its own messages and all more
+/// severe ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub(crate) enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    /// Parses a `LogLevel` name.
+    pub(crate) fn parse(name: &str) -> Option<Level> {
+        Some(match name {
+            "off" => Level::Off,
+            "error" => Level::Error,
+            "warn" => Level::Warn,
+            "info" => Level::Info,
+            "debug" => Level::Debug,
+            _ => return None,
+        })
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(if cfg!(debug_assertions) {
+    Level::Debug as u8
+} else {
+    Level::Warn as u8
+});
+
+/// Sets the most verbose level that is logged.
+pub(crate) fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Returns `true` if messages at `level` are logged.
+pub(crate) fn enabled(level: Level) -> bool {
+    level != Level::Off && level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Writes `msg` to the console method matching `level`.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn write(level: Level, msg: &str) {
+    let msg = JsValue::from_str(msg);
+    match level {
+        Level::Off => {}
+        Level::Error => web_sys::console::error_1(&msg),
+        Level::Warn => web_sys::console::warn_1(&msg),
+        Level::Info => web_sys::console::info_1(&msg),
+        Level::Debug => web_sys::console::log_1(&msg),
+    }
+}
+
+/// Writes `msg` to stderr; native builds (unit tests) have no console.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn write(level: Level, msg: &str) {
+    if level != Level::Off {
+        eprintln!("[{:?}] {}", level, msg);
+    }
+}
+
+/// Logs a `format!` message at `level` if that level is enabled.
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)*) => {{
+        if $crate::logging::enabled($level) {
+            $crate::logging::write($level, &format!($($arg)*));
+        }
+    }};
+}
+
+/// Logs an error; see `log_at!`.
+macro_rules! log_error {
+    ($($arg:tt)*) => { log_at!($crate::logging::Level::Error, $($arg)*) };
+}
+
+/// Logs a warning; see `log_at!`.
+macro_rules! log_warn {
+    ($($arg:tt)*) => { log_at!($crate::logging::Level::Warn, $($arg)*) };
+}
+
+/// Logs progress worth seeing in a bug report; see `log_at!`.
+macro_rules! log_info {
+    ($($arg:tt)*) => { log_at!($crate::logging::Level::Info, $($arg)*) };
+}
+
+/// Logs details for debugging; see `log_at!`.
+macro_rules! log_debug {
+    ($($arg:tt)*) => { log_at!($crate::logging::Level::Debug, $($arg)*) };
+}
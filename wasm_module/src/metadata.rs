@@ -0,0 +1,295 @@
+//! # Story Metadata
+//!
+//! Registry of the typed keys stored in `ChunkType::Metadata` chunks. Each
+//! metadata chunk's 3-byte ID names the key; its payload encodes the value
+//! according to the key's [`MetaKind`].
+//!
+//! | ID         | Key                  | Kind       |
+//! |------------|----------------------|------------|
+//! | `00 00 01` | root pointer         | node ref   |
+//! | `00 00 02` | title                | text       |
+//! | `00 00 03` | authors              | text list  |
+//! | `00 00 04` | story version        | version    |
+//! | `00 00 05` | description          | text       |
+//! | `00 00 06` | content warnings     | text list  |
+//! | `00 00 07` | cover asset          | text       |
+//! | `00 00 08` | minimum engine version | version  |
+//! | `00 00 09` | namespace            | text       |
+//! | `00 00 0A` | dependencies         | text list  |
+//! | `00 00 0B` | fingerprint          | u64        |
+//!
+//! Unknown IDs are ignored so newer files stay readable by older engines.
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::io::{Cursor, Read};
+
+use crate::decoder::GameError;
+
+/// ID used in metadata to point to the root node.
+pub const ID_ROOT_POINTER: [u8; 3] = [0, 0, 1];
+/// ID of the metadata chunk holding the story fingerprint used by patches.
+pub const ID_FINGERPRINT: [u8; 3] = [0, 0, 0x0B];
+
+/// How a metadata value is encoded in its chunk payload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetaKind {
+    /// A 3-byte node chunk ID.
+    NodeRef,
+    /// The whole payload as UTF-8 text.
+    Text,
+    /// u16 count, then u16 length-prefixed UTF-8 strings.
+    TextList,
+    /// Three u16 values: major, minor, patch.
+    Version,
+    /// A single u64.
+    U64,
+}
+
+/// All metadata keys understood by this engine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetaKey {
+    /// Node the story starts at.
+    RootPointer,
+    /// Human-readable story title.
+    Title,
+    /// Story authors, in credit order.
+    Authors,
+    /// Version of the story content.
+    StoryVersion,
+    /// Short blurb for the About panel and story picker.
+    Description,
+    /// Content warnings shown before starting the story.
+    ContentWarnings,
+    /// Path or URL of the cover image.
+    CoverAsset,
+    /// Oldest engine version able to play the story.
+    MinEngineVersion,
+    /// Name other story files use to link into this one, e.g. `"magium"`.
+    Namespace,
+    /// Story files that must be mounted first, as `"namespace>=1.2.0"`.
+    Dependencies,
+    /// Content fingerprint checked by patches; see the `patch` module.
+    Fingerprint,
+}
+
+impl MetaKey {
+    /// Maps a metadata chunk ID to its key, if known.
+    pub fn from_id(id: [u8; 3]) -> Option<Self> {
+        Some(match id {
+            ID_ROOT_POINTER => MetaKey::RootPointer,
+            [0, 0, 2] => MetaKey::Title,
+            [0, 0, 3] => MetaKey::Authors,
+            [0, 0, 4] => MetaKey::StoryVersion,
+            [0, 0, 5] => MetaKey::Description,
+            [0, 0, 6] => MetaKey::ContentWarnings,
+            [0, 0, 7] => MetaKey::CoverAsset,
+            [0, 0, 8] => MetaKey::MinEngineVersion,
+            [0, 0, 9] => MetaKey::Namespace,
+            [0, 0, 0x0A] => MetaKey::Dependencies,
+            ID_FINGERPRINT => MetaKey::Fingerprint,
+            _ => return None,
+        })
+    }
+
+    /// The value encoding used by this key.
+    pub fn kind(self) -> MetaKind {
+        match self {
+            MetaKey::RootPointer => MetaKind::NodeRef,
+            MetaKey::Title | MetaKey::Description | MetaKey::CoverAsset | MetaKey::Namespace => {
+                MetaKind::Text
+            }
+            MetaKey::Authors | MetaKey::ContentWarnings | MetaKey::Dependencies => {
+                MetaKind::TextList
+            }
+            MetaKey::StoryVersion | MetaKey::MinEngineVersion => MetaKind::Version,
+            MetaKey::Fingerprint => MetaKind::U64,
+        }
+    }
+}
+
+/// A `major.minor.patch` version number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl Version {
+    /// The version of this engine, taken from the crate version.
+    pub fn engine() -> Self {
+        Self::parse(env!("CARGO_PKG_VERSION")).unwrap_or(Version {
+            major: 0,
+            minor: 0,
+            patch: 0,
+        })
+    }
+
+    /// Parses a dotted `major.minor.patch` string; missing parts are zero.
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.trim().split('.').map(|p| p.parse::<u16>());
+        let major = parts.next()?.ok()?;
+        let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+        let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+        Some(Version {
+            major,
+            minor,
+            patch,
+        })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl Serialize for Version {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+/// A dependency of one story file on another mounted file.
+#[derive(Clone, Debug, Serialize)]
+pub struct Dependency {
+    /// Namespace of the required story file.
+    pub namespace: String,
+    /// Oldest acceptable story version of that file, if constrained.
+    pub min_version: Option<Version>,
+}
+
+impl Dependency {
+    /// Parses `"namespace"` or `"namespace>=major.minor.patch"`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (namespace, min_version) = match s.split_once(">=") {
+            Some((ns, v)) => (ns, Some(Version::parse(v)?)),
+            None => (s, None),
+        };
+        let namespace = namespace.trim();
+        if namespace.is_empty() {
+            return None;
+        }
+        Some(Dependency {
+            namespace: namespace.to_string(),
+            min_version,
+        })
+    }
+}
+
+/// A decoded metadata value.
+#[derive(Clone, Debug)]
+pub enum MetaValue {
+    NodeRef([u8; 3]),
+    Text(String),
+    TextList(Vec<String>),
+    Version(Version),
+    U64(u64),
+}
+
+/// Decodes a metadata payload according to `kind`.
+///
+/// # Returns
+///
+/// - `Ok(MetaValue)` with the typed value.
+/// - `Err(GameError::Parse(_))` on truncated data or invalid UTF-8.
+pub fn parse_value(kind: MetaKind, data: &[u8]) -> Result<MetaValue, GameError> {
+    let mut c = Cursor::new(data);
+    Ok(match kind {
+        MetaKind::NodeRef => {
+            let mut cid = [0u8; 3];
+            c.read_exact(&mut cid)
+                .map_err(|_| GameError::Parse("Read metadata node ref"))?;
+            MetaValue::NodeRef(cid)
+        }
+        MetaKind::Text => MetaValue::Text(
+            String::from_utf8(data.to_vec()).map_err(|_| GameError::Parse("Invalid UTF-8"))?,
+        ),
+        MetaKind::TextList => {
+            let cnt = c
+                .read_u16::<LittleEndian>()
+                .map_err(|_| GameError::Parse("Read metadata list count"))?;
+            let mut items = Vec::with_capacity(cnt as usize);
+            for _ in 0..cnt {
+                let len = c
+                    .read_u16::<LittleEndian>()
+                    .map_err(|_| GameError::Parse("Read metadata item length"))?;
+                let mut buf = vec![0; len as usize];
+                c.read_exact(&mut buf)
+                    .map_err(|_| GameError::Parse("Read metadata item"))?;
+                items.push(String::from_utf8(buf).map_err(|_| GameError::Parse("Invalid UTF-8"))?);
+            }
+            MetaValue::TextList(items)
+        }
+        MetaKind::Version => {
+            let mut v = [0u16; 3];
+            for part in v.iter_mut() {
+                *part = c
+                    .read_u16::<LittleEndian>()
+                    .map_err(|_| GameError::Parse("Read metadata version"))?;
+            }
+            MetaValue::Version(Version {
+                major: v[0],
+                minor: v[1],
+                patch: v[2],
+            })
+        }
+        MetaKind::U64 => MetaValue::U64(
+            c.read_u64::<LittleEndian>()
+                .map_err(|_| GameError::Parse("Read metadata u64"))?,
+        ),
+    })
+}
+
+/// All typed metadata of a story, as exposed to JavaScript.
+///
+/// Keys missing from the file are `null` (or empty lists) on the JS side.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct StoryMetadata {
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub version: Option<Version>,
+    pub description: Option<String>,
+    pub content_warnings: Vec<String>,
+    pub cover_asset: Option<String>,
+    pub min_engine_version: Option<Version>,
+    pub namespace: Option<String>,
+    pub dependencies: Vec<Dependency>,
+    /// Not exposed to JS: u64 does not fit a JS number.
+    #[serde(skip)]
+    pub fingerprint: Option<u64>,
+    /// Index of the root node in the chunk index, if the root pointer
+    /// resolves to a node.
+    pub root_idx: Option<u32>,
+    /// Whether this engine satisfies `min_engine_version`.
+    pub engine_compatible: bool,
+}
+
+impl StoryMetadata {
+    /// Stores a decoded value under `key`, ignoring kind mismatches.
+    ///
+    /// The root pointer is resolved separately by the caller, since it
+    /// needs the chunk index.
+    pub fn set(&mut self, key: MetaKey, value: MetaValue) {
+        match (key, value) {
+            (MetaKey::Title, MetaValue::Text(t)) => self.title = Some(t),
+            (MetaKey::Authors, MetaValue::TextList(l)) => self.authors = l,
+            (MetaKey::StoryVersion, MetaValue::Version(v)) => self.version = Some(v),
+            (MetaKey::Description, MetaValue::Text(t)) => self.description = Some(t),
+            (MetaKey::ContentWarnings, MetaValue::TextList(l)) => self.content_warnings = l,
+            (MetaKey::CoverAsset, MetaValue::Text(t)) => self.cover_asset = Some(t),
+            (MetaKey::MinEngineVersion, MetaValue::Version(v)) => {
+                self.min_engine_version = Some(v)
+            }
+            (MetaKey::Namespace, MetaValue::Text(t)) => self.namespace = Some(t),
+            (MetaKey::Fingerprint, MetaValue::U64(f)) => self.fingerprint = Some(f),
+            (MetaKey::Dependencies, MetaValue::TextList(l)) => {
+                self.dependencies = l.iter().filter_map(|d| Dependency::parse(d)).collect()
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,727 @@
+//! # Network Policy
+//!
+//! How the HTTP requests behind story loads are made: where relative paths
+//! point, which `Transport` carries them (set up from `CyoaGameOptions`),
+//! how their replies are checked, and timeouts, retries and cancellation.
+//!
+//! Each attempt runs under an `AbortSignal` that fires when the attempt
+//! times out or when the game aborts its loads, whichever comes first.
+//! The timeout only covers the attempt itself: a response body read after
+//! it returns (the whole-file fallback) can take as long as it needs.
+//! Network failures, timeouts and 5xx responses are retried with
+//! exponential backoff; anything else, and aborts, fail at once.
+//!
+//! Range requests carry the probe's `ETag` (or `Last-Modified`) as
+//! `If-Range`, so a story redeployed mid-session is noticed instead of
+//! read at offsets taken from the old file's index.
+//!
+//! Signals, timeouts and backoff waits come from a `Runtime`: the page's
+//! `AbortController` and `setTimeout` in the browser, and a stand-in
+//! without JavaScript in native unit tests.
+
+use futures::FutureExt;
+use futures::future::LocalBoxFuture;
+use js_sys::{Array, Error, Promise, Reflect};
+use serde::Deserialize;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::rc::Rc;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{AbortController, AbortSignal, RequestCredentials, RequestMode, window};
+
+use crate::decoder::GameError;
+use crate::ranges::{RangeError, extract_range, parse_content_range};
+use crate::transport::{Body, BrowserTransport, Transport};
+
+/// How long each attempt may take and how failed ones are retried.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RetryPolicy {
+    /// Time allowed for one attempt, in milliseconds.
+    pub(crate) timeout_ms: u32,
+    /// Attempts made after the first one fails.
+    pub(crate) retries: u32,
+    /// Wait before the first retry, in milliseconds; doubled after each.
+    pub(crate) backoff_ms: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            timeout_ms: 15_000,
+            retries: 3,
+            backoff_ms: 250,
+        }
+    }
+}
+
+#[wasm_bindgen(typescript_custom_section)]
+const CYOA_GAME_OPTIONS: &str = r#"
+/** Transport options for `new CyoaGame(path, onProgress, options)`. */
+export interface CyoaGameOptions {
+  /** Prefix for relative story, pack and patch paths, e.g. a CDN origin. */
+  baseUrl?: string;
+  /**
+   * Request mode; defaults to "cors" for URLs on another origin than the
+   * page (through `baseUrl` or an absolute path), else "same-origin".
+   */
+  mode?: "same-origin" | "cors";
+  /** Whether cookies and HTTP auth are sent; the browser default if unset. */
+  credentials?: "omit" | "same-origin" | "include";
+  /** Extra headers sent with every request, e.g. auth or cache-busting. */
+  headers?: Record<string, string>;
+  /** See `set_network_policy`. */
+  timeoutMs?: number;
+  retries?: number;
+  backoffMs?: number;
+  /**
+   * Called with the root node, shaped like `load_root_node_full`'s result,
+   * as soon as the file's bootstrap section has been read; before the
+   * index, so before the constructor resolves. Not called for files
+   * without one. Exceptions it throws are logged and do not fail the load.
+   */
+  onFirstScreen?: (node: {
+    content: string;
+    edges: { label: string; dest_idx: number }[];
+    unlocked: [];
+  }) => void;
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    /// Options object passed to the `CyoaGame` constructor.
+    #[wasm_bindgen(typescript_type = "CyoaGameOptions")]
+    pub type CyoaGameOptions;
+}
+
+/// `CyoaGameOptions` as read from JavaScript.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct OptionsInput {
+    base_url: Option<String>,
+    mode: Option<String>,
+    credentials: Option<String>,
+    headers: BTreeMap<String, String>,
+    timeout_ms: Option<u32>,
+    retries: Option<u32>,
+    backoff_ms: Option<u32>,
+}
+
+/// What a probe learned about a story file.
+pub(crate) struct Probe {
+    /// Total size of the file in bytes, if the server told: the
+    /// `Content-Range` total (which may be `*`), or the `Content-Length`
+    /// of a non-ranged response.
+    pub(crate) size: Option<u64>,
+    /// `true` if the server responded with 206 Partial Content.
+    pub(crate) ranged: bool,
+    /// The file's `ETag` or `Last-Modified`, sent as `If-Range` later.
+    pub(crate) validator: Option<String>,
+    /// The unread probe body; when not ranged, the whole file.
+    pub(crate) body: Body,
+}
+
+/// Transport, retry policy and abort controller shared by a game's
+/// requests.
+pub(crate) struct Net {
+    /// Prefix of relative paths, without a trailing `/`.
+    base_url: Option<String>,
+    transport: Rc<dyn Transport>,
+    runtime: Rc<dyn Runtime>,
+    policy: Cell<RetryPolicy>,
+    /// Aborted by `abort`; created on first use so the controller of an
+    /// aborted generation is never reused.
+    loads: RefCell<Option<AbortController>>,
+}
+
+impl Default for Net {
+    fn default() -> Self {
+        Net::with_parts(
+            Rc::new(BrowserTransport::default()),
+            Rc::new(BrowserRuntime),
+        )
+    }
+}
+
+impl Net {
+    /// Builds default settings that send requests through `transport`,
+    /// with signals and timers from `runtime`.
+    pub(crate) fn with_parts(transport: Rc<dyn Transport>, runtime: Rc<dyn Runtime>) -> Net {
+        Net {
+            base_url: None,
+            transport,
+            runtime,
+            policy: Cell::new(RetryPolicy::default()),
+            loads: RefCell::new(None),
+        }
+    }
+
+    /// Builds the settings described by a JS `CyoaGameOptions` object;
+    /// `None` gives the defaults.
+    ///
+    /// # Errors
+    ///
+    /// - `GameError::Other` if the object has the wrong shape or names an
+    ///   unsupported mode or credentials policy.
+    pub(crate) fn from_options(options: Option<CyoaGameOptions>) -> Result<Net, GameError> {
+        let Some(options) = options else {
+            return Ok(Net::default());
+        };
+        let input: OptionsInput = serde_wasm_bindgen::from_value(options.into())
+            .map_err(|e| GameError::Other(format!("Invalid options: {}", e)))?;
+        let mode = match input.mode.as_deref() {
+            Some("same-origin") => Some(RequestMode::SameOrigin),
+            Some("cors") => Some(RequestMode::Cors),
+            None => None,
+            Some(other) => {
+                return Err(GameError::Other(format!(
+                    "Unsupported request mode: {}",
+                    other
+                )));
+            }
+        };
+        let credentials = match input.credentials.as_deref() {
+            Some("omit") => Some(RequestCredentials::Omit),
+            Some("same-origin") => Some(RequestCredentials::SameOrigin),
+            Some("include") => Some(RequestCredentials::Include),
+            None => None,
+            Some(other) => {
+                return Err(GameError::Other(format!(
+                    "Unsupported credentials: {}",
+                    other
+                )));
+            }
+        };
+        let defaults = RetryPolicy::default();
+        Ok(Net {
+            base_url: input.base_url.map(|b| b.trim_end_matches('/').to_string()),
+            transport: Rc::new(BrowserTransport {
+                mode,
+                credentials,
+                headers: input.headers.into_iter().collect(),
+            }),
+            runtime: Rc::new(BrowserRuntime),
+            policy: Cell::new(RetryPolicy {
+                timeout_ms: input.timeout_ms.unwrap_or(defaults.timeout_ms),
+                retries: input.retries.unwrap_or(defaults.retries),
+                backoff_ms: input.backoff_ms.unwrap_or(defaults.backoff_ms),
+            }),
+            loads: RefCell::new(None),
+        })
+    }
+
+    /// Turns a story, pack or patch path into the URL it is fetched from.
+    ///
+    /// Absolute URLs (anything with `://`, e.g. the desktop build's
+    /// `story://` scheme) are kept as they are. Other paths are appended to
+    /// the base URL if one is set, else made site-relative with a leading
+    /// `/`.
+    pub(crate) fn resolve(&self, path: &str) -> String {
+        if path.contains("://") {
+            return path.to_string();
+        }
+        match &self.base_url {
+            Some(base) => format!("{}/{}", base, path.trim_start_matches('/')),
+            None if path.starts_with('/') => path.to_string(),
+            None => format!("/{}", path),
+        }
+    }
+
+    /// Probes `url` for its size, Range support and validator.
+    ///
+    /// # Errors
+    ///
+    /// - On network errors, error statuses, or a `Content-Range` that does
+    ///   not start at byte 0.
+    pub(crate) async fn probe(&self, url: &str) -> Result<Probe, GameError> {
+        let signal = self.signal();
+        let reply = self
+            .run(signal.as_ref(), |attempt| {
+                self.transport.probe(url, attempt)
+            })
+            .await?;
+        let ranged = reply.status == 206;
+        // Status 0 is what `file://`-like WebView schemes report on success.
+        if !ranged && !(200..300).contains(&reply.status) && reply.status != 0 {
+            return Err(GameError::Http(reply.status));
+        }
+        let size = if ranged {
+            let cr = reply
+                .content_range
+                .as_deref()
+                .and_then(parse_content_range)
+                .filter(|cr| cr.span.is_some_and(|(s, _)| s == 0))
+                .ok_or(GameError::BadResponse("probe Content-Range".to_string()))?;
+            cr.total
+        } else {
+            reply.content_length
+        };
+        Ok(Probe {
+            size,
+            ranged,
+            validator: reply.validator().map(str::to_string),
+            body: reply.body,
+        })
+    }
+
+    /// Fetches bytes `start..=end` of `url` (to the end of the file
+    /// without `end`) under the load abort `signal`, if any.
+    ///
+    /// The reply is checked against the request with `extract_range`, so
+    /// a server that ignores `Range` or answers with several parts still
+    /// yields exactly the requested bytes. A reply that does not hold them
+    /// (e.g. a truncated body) is retried like a network error.
+    ///
+    /// With a `validator` from the probe, it is sent as `If-Range`. A reply
+    /// with a different validator, or a 200 without one (the server's
+    /// answer to a failed `If-Range`), means the file was replaced.
+    ///
+    /// # Errors
+    ///
+    /// - `GameError::StoryChanged` if the file no longer matches
+    ///   `validator`.
+    /// - `GameError::Http` on error statuses, `BadResponse`, `Network` or
+    ///   `Timeout` once retries are used up, `Aborted` if `signal` fires.
+    pub(crate) async fn fetch_range(
+        &self,
+        signal: Option<&AbortSignal>,
+        url: &str,
+        start: u64,
+        end: Option<u64>,
+        validator: Option<&str>,
+    ) -> Result<Vec<u8>, GameError> {
+        self.run(signal, |attempt| async move {
+            let reply = self
+                .transport
+                .fetch_range(url, start, end, validator, attempt)
+                .await?;
+            if let Some(expected) = validator {
+                let changed = match reply.validator() {
+                    Some(now) => now != expected,
+                    None => reply.status == 200,
+                };
+                if changed {
+                    return Err(GameError::StoryChanged);
+                }
+            }
+            extract_range(
+                reply.status,
+                reply.content_range.as_deref(),
+                reply.content_type.as_deref(),
+                reply.body,
+                start,
+                end,
+            )
+            .map_err(|e| match e {
+                RangeError::Status(status) => GameError::Http(status),
+                RangeError::Invalid(msg) => GameError::BadResponse(msg),
+            })
+        })
+        .await
+    }
+
+    /// Fetches all of `url` without a Range header.
+    ///
+    /// # Errors
+    ///
+    /// - As for `fetch_range`.
+    pub(crate) async fn fetch_full(&self, url: &str) -> Result<Vec<u8>, GameError> {
+        let signal = self.signal();
+        self.run(signal.as_ref(), |attempt| async move {
+            let reply = self.transport.fetch_full(url, attempt).await?;
+            if !(200..300).contains(&reply.status) {
+                return Err(GameError::Http(reply.status));
+            }
+            Ok(reply.body)
+        })
+        .await
+    }
+
+    /// Returns the current retry policy.
+    pub(crate) fn policy(&self) -> RetryPolicy {
+        self.policy.get()
+    }
+
+    /// Replaces the retry policy; requests already running keep theirs.
+    pub(crate) fn set_policy(&self, policy: RetryPolicy) {
+        self.policy.set(policy);
+    }
+
+    /// Returns the signal of the current load generation, or `None` if the
+    /// runtime cannot create an `AbortController`.
+    pub(crate) fn signal(&self) -> Option<AbortSignal> {
+        let mut loads = self.loads.borrow_mut();
+        if loads.is_none() {
+            *loads = self.runtime.controller();
+        }
+        loads.as_ref().map(|c| c.signal())
+    }
+
+    /// Aborts every request made under the current signal, including ones
+    /// still queued. Later requests get a fresh signal.
+    pub(crate) fn abort(&self) {
+        if let Some(controller) = self.loads.borrow_mut().take() {
+            controller.abort();
+        }
+    }
+
+    /// Runs `attempt` until it succeeds, fails permanently, or runs out of
+    /// retries.
+    ///
+    /// `attempt` receives the signal to pass to `fetch`: it fires if the
+    /// attempt outlasts the policy's timeout, or when `signal` (if any) is
+    /// aborted. It is `None` where the runtime has no timers.
+    ///
+    /// # Errors
+    ///
+    /// - `GameError::Aborted` once `signal` is aborted; a retry waiting
+    ///   out its backoff stops before its next attempt.
+    /// - The last attempt's error otherwise.
+    pub(crate) async fn run<T, F, Fut>(
+        &self,
+        signal: Option<&AbortSignal>,
+        mut attempt: F,
+    ) -> Result<T, GameError>
+    where
+        F: FnMut(Option<AbortSignal>) -> Fut,
+        Fut: Future<Output = Result<T, GameError>>,
+    {
+        let policy = self.policy();
+        let mut n = 0;
+        loop {
+            if signal.is_some_and(|s| s.aborted()) {
+                return Err(GameError::Aborted);
+            }
+            let result = {
+                let timer = self.runtime.deadline(policy.timeout_ms, signal)?;
+                attempt(timer.as_ref().map(|t| t.signal.clone())).await
+            };
+            match result {
+                Err(e) if n < policy.retries && e.is_transient() => {
+                    let wait = policy.backoff_ms.saturating_mul(1 << n.min(16));
+                    log_warn!("{}; retrying in {} ms", e, wait);
+                    self.runtime.sleep(wait).await;
+                    n += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Where `Net` gets abort signals, attempt deadlines and backoff waits.
+pub(crate) trait Runtime {
+    /// Returns the controller of a new load generation, or `None` if
+    /// there are no abort signals.
+    fn controller(&self) -> Option<AbortController>;
+
+    /// Starts the deadline of one attempt: a timer whose signal fires
+    /// after `ms` milliseconds or when `load` does. `None` without timers.
+    fn deadline(&self, ms: u32, load: Option<&AbortSignal>) -> Result<Option<Timer>, GameError>;
+
+    /// Resolves after `ms` milliseconds.
+    fn sleep(&self, ms: u32) -> LocalBoxFuture<'static, ()>;
+}
+
+/// The page's `AbortController` and `setTimeout`.
+pub(crate) struct BrowserRuntime;
+
+impl Runtime for BrowserRuntime {
+    fn controller(&self) -> Option<AbortController> {
+        AbortController::new().ok()
+    }
+
+    fn deadline(&self, ms: u32, load: Option<&AbortSignal>) -> Result<Option<Timer>, GameError> {
+        Timer::start(ms, load).map(Some)
+    }
+
+    fn sleep(&self, ms: u32) -> LocalBoxFuture<'static, ()> {
+        sleep(ms).boxed_local()
+    }
+}
+
+/// Runtime for native unit tests, where no JavaScript can run: there are
+/// no signals or timeouts, and waits return at once after being recorded.
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct TestRuntime {
+    /// Every wait asked for, in milliseconds.
+    pub(crate) sleeps: RefCell<Vec<u32>>,
+}
+
+#[cfg(test)]
+impl Runtime for TestRuntime {
+    fn controller(&self) -> Option<AbortController> {
+        None
+    }
+
+    fn deadline(&self, _ms: u32, _load: Option<&AbortSignal>) -> Result<Option<Timer>, GameError> {
+        Ok(None)
+    }
+
+    fn sleep(&self, ms: u32) -> LocalBoxFuture<'static, ()> {
+        self.sleeps.borrow_mut().push(ms);
+        futures::future::ready(()).boxed_local()
+    }
+}
+
+/// Aborts its signal with a `TimeoutError` after a delay, unless dropped
+/// first, and with the load signal's reason when that one fires.
+pub(crate) struct Timer {
+    /// Fires on timeout or when the load signal does.
+    signal: AbortSignal,
+    handle: Option<i32>,
+    _fire: Closure<dyn FnMut()>,
+    /// The load signal and the listener forwarding its abort, where
+    /// `AbortSignal.any` is missing; removed on drop.
+    follow: Option<(AbortSignal, Closure<dyn FnMut()>)>,
+}
+
+impl Timer {
+    fn start(ms: u32, load: Option<&AbortSignal>) -> Result<Timer, GameError> {
+        let controller = AbortController::new().map_err(fetch_error)?;
+        let target = controller.clone();
+        let fire = Closure::<dyn FnMut()>::new(move || {
+            let reason = Error::new("Request timed out");
+            reason.set_name("TimeoutError");
+            target.abort_with_reason(&reason);
+        });
+        let handle = window().and_then(|win| {
+            win.set_timeout_with_callback_and_timeout_and_arguments_0(
+                fire.as_ref().unchecked_ref(),
+                ms as i32,
+            )
+            .ok()
+        });
+        let mut follow = None;
+        let signal = match load {
+            None => controller.signal(),
+            Some(load) if has_signal_any() => {
+                AbortSignal::any(&Array::of2(load, &controller.signal()))
+            }
+            Some(load) => {
+                // Older WebViews lack `AbortSignal.any`: forward the load
+                // signal's abort to our controller by hand.
+                let (target, source) = (controller.clone(), load.clone());
+                let forward = Closure::<dyn FnMut()>::new(move || {
+                    target.abort_with_reason(&source.reason());
+                });
+                load.add_event_listener_with_callback("abort", forward.as_ref().unchecked_ref())
+                    .map_err(fetch_error)?;
+                follow = Some((load.clone(), forward));
+                controller.signal()
+            }
+        };
+        Ok(Timer {
+            signal,
+            handle,
+            _fire: fire,
+            follow,
+        })
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if let (Some(win), Some(handle)) = (window(), self.handle) {
+            win.clear_timeout_with_handle(handle);
+        }
+        if let Some((load, forward)) = &self.follow {
+            let _ = load
+                .remove_event_listener_with_callback("abort", forward.as_ref().unchecked_ref());
+        }
+    }
+}
+
+/// Returns `true` if the browser has `AbortSignal.any` (Chrome 116 and
+/// Safari 17.4 onwards).
+fn has_signal_any() -> bool {
+    Reflect::get(&js_sys::global(), &JsValue::from_str("AbortSignal"))
+        .and_then(|ctor| Reflect::has(&ctor, &JsValue::from_str("any")))
+        .unwrap_or(false)
+}
+
+/// Returns the request mode for `url` when the options set none: `cors`
+/// if it is an absolute URL on another origin than `page_origin` (a CDN,
+/// or the desktop shell's `story://` scheme), else `same-origin`.
+pub(crate) fn default_mode(url: &str, page_origin: Option<&str>) -> RequestMode {
+    match origin(url) {
+        Some(o) if page_origin.is_none_or(|p| !p.eq_ignore_ascii_case(&o)) => RequestMode::Cors,
+        _ => RequestMode::SameOrigin,
+    }
+}
+
+/// Returns the `scheme://host[:port]` origin of an absolute URL.
+fn origin(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    Some(format!("{}://{}", scheme, host))
+}
+
+/// Maps a rejected `fetch` or body read to a `GameError`, keeping the
+/// browser's message.
+pub(crate) fn fetch_error(err: JsValue) -> GameError {
+    let field = |name: &str| {
+        Reflect::get(&err, &JsValue::from_str(name))
+            .ok()
+            .and_then(|v| v.as_string())
+    };
+    match field("name").as_deref() {
+        Some("TimeoutError") => GameError::Timeout,
+        Some("AbortError") => GameError::Aborted,
+        _ => GameError::Network(field("message").unwrap_or_else(|| format!("{:?}", err))),
+    }
+}
+
+/// Resolves after `ms` milliseconds.
+async fn sleep(ms: u32) {
+    let promise = Promise::new(&mut |resolve, _reject| match window() {
+        Some(win) => {
+            let _ = win.set_timeout_with_callback_and_timeout_and_arguments_0(
+                resolve.unchecked_ref(),
+                ms as i32,
+            );
+        }
+        None => {
+            let _ = resolve.call0(&JsValue::NULL);
+        }
+    });
+    let _ = JsFuture::from(promise).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::mock::{MockConfig, MockTransport};
+    use futures::executor::block_on;
+
+    const URL: &str = "/magium.story";
+
+    /// A `Net` over a mock serving bytes 0 to 255, and the mock and
+    /// runtime behind it.
+    fn mock_net(config: MockConfig) -> (Net, Rc<MockTransport>, Rc<TestRuntime>) {
+        let runtime = Rc::new(TestRuntime::default());
+        let mock = Rc::new(MockTransport::new(
+            URL,
+            (0..=255).collect(),
+            config,
+            runtime.clone(),
+        ));
+        let net = Net::with_parts(mock.clone(), runtime.clone());
+        (net, mock, runtime)
+    }
+
+    #[test]
+    fn slices_ranges_out_of_full_replies() {
+        let (net, _, _) = mock_net(MockConfig {
+            ignore_range: true,
+            ..Default::default()
+        });
+        let probe = block_on(net.probe(URL)).unwrap();
+        assert!(!probe.ranged);
+        assert_eq!(probe.size, Some(256));
+        let validator = probe.validator.as_deref();
+        let bytes = block_on(net.fetch_range(None, URL, 10, Some(19), validator)).unwrap();
+        assert_eq!(bytes, (10..20).collect::<Vec<u8>>());
+        let tail = block_on(net.fetch_range(None, URL, 250, None, validator)).unwrap();
+        assert_eq!(tail, (250..=255).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn retries_truncated_replies_then_gives_up() {
+        let (net, mock, runtime) = mock_net(MockConfig {
+            truncate_to: Some(5),
+            ..Default::default()
+        });
+        let short = block_on(net.fetch_range(None, URL, 0, Some(4), None)).unwrap();
+        assert_eq!(short, [0, 1, 2, 3, 4]);
+        let err = block_on(net.fetch_range(None, URL, 10, Some(19), None)).unwrap_err();
+        assert!(matches!(err, GameError::BadResponse(_)), "{:?}", err);
+        assert_eq!(mock.requests.get(), 5);
+        assert_eq!(*runtime.sleeps.borrow(), [250, 500, 1000]);
+    }
+
+    #[test]
+    fn retries_transient_failures() {
+        for fail_status in [None, Some(503)] {
+            let (net, mock, runtime) = mock_net(MockConfig {
+                fail_every: 2,
+                fail_status,
+                ..Default::default()
+            });
+            block_on(net.fetch_range(None, URL, 0, Some(9), None)).unwrap();
+            let bytes = block_on(net.fetch_range(None, URL, 10, Some(19), None)).unwrap();
+            assert_eq!(bytes, (10..20).collect::<Vec<u8>>());
+            assert_eq!(mock.requests.get(), 3);
+            assert_eq!(*runtime.sleeps.borrow(), [250]);
+        }
+    }
+
+    #[test]
+    fn fails_once_retries_are_used_up_or_on_client_errors() {
+        let (net, mock, _) = mock_net(MockConfig {
+            fail_every: 1,
+            ..Default::default()
+        });
+        let err = block_on(net.fetch_full(URL)).unwrap_err();
+        assert!(matches!(err, GameError::Network(_)), "{:?}", err);
+        assert_eq!(mock.requests.get(), 4);
+
+        let (net, mock, runtime) = mock_net(MockConfig {
+            fail_every: 1,
+            fail_status: Some(404),
+            ..Default::default()
+        });
+        let err = block_on(net.fetch_range(None, URL, 0, Some(9), None)).unwrap_err();
+        assert!(matches!(err, GameError::Http(404)), "{:?}", err);
+        assert_eq!(mock.requests.get(), 1);
+        assert!(runtime.sleeps.borrow().is_empty());
+    }
+
+    #[test]
+    fn waits_out_latency_on_every_attempt() {
+        let (net, mock, runtime) = mock_net(MockConfig {
+            latency_ms: 300,
+            fail_every: 2,
+            ..Default::default()
+        });
+        block_on(net.fetch_range(None, URL, 0, Some(9), None)).unwrap();
+        block_on(net.fetch_range(None, URL, 10, Some(19), None)).unwrap();
+        assert_eq!(mock.requests.get(), 3);
+        // The failed attempt is delayed too, then backed off from.
+        assert_eq!(*runtime.sleeps.borrow(), [300, 300, 250, 300]);
+    }
+
+    #[test]
+    fn reports_a_replaced_story_without_retrying() {
+        let (net, mock, _) = mock_net(MockConfig {
+            change_after: Some(2),
+            ..Default::default()
+        });
+        let probe = block_on(net.probe(URL)).unwrap();
+        assert!(probe.ranged);
+        let validator = probe.validator.as_deref();
+        block_on(net.fetch_range(None, URL, 0, Some(21), validator)).unwrap();
+        let err = block_on(net.fetch_range(None, URL, 22, None, validator)).unwrap_err();
+        assert!(matches!(err, GameError::StoryChanged), "{:?}", err);
+        assert_eq!(mock.requests.get(), 3);
+    }
+
+    #[test]
+    fn defaults_to_cors_for_other_origins() {
+        let page = Some("https://play.example");
+        assert_eq!(default_mode("/magium.story", page), RequestMode::SameOrigin);
+        assert_eq!(default_mode("https://play.example/a.story", page), RequestMode::SameOrigin);
+        assert_eq!(default_mode("https://PLAY.example/a.story", page), RequestMode::SameOrigin);
+        assert_eq!(default_mode("https://cdn.example/a.story", page), RequestMode::Cors);
+        assert_eq!(default_mode("https://play.example:8443/a", page), RequestMode::Cors);
+        assert_eq!(
+            default_mode("story://localhost/magium.story", Some("tauri://localhost")),
+            RequestMode::Cors
+        );
+        assert_eq!(default_mode("https://cdn.example/a.story", None), RequestMode::Cors);
+    }
+}
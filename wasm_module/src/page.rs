@@ -0,0 +1,50 @@
+//! # Page Chunks
+//!
+//! A story file may carry one `ChunkType::Page` chunk per node, under the
+//! node's own chunk ID, holding copies of everything showing that node
+//! needs: the node record, the content of all its segments (guarded or
+//! not), its edge records, their labels and the `Link` chunks its edges
+//! go through, in every language the story has. A node with a page loads
+//! in one request instead of a round of requests per stage.
+//!
+//! Its payload is a little-endian u16 chunk count followed by that many
+//! complete TLV chunks back to back. The bootstrap section starts with
+//! the same list.
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Arc;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::cache::ChunkKey;
+use crate::decoder::{ChunkType, CyoaGame, GameError};
+
+/// Raw TLV chunks copied out of a page or bootstrap chunk.
+pub(crate) type Bundle = HashMap<ChunkKey, Arc<Vec<u8>>>;
+
+/// Reads a chunk count and that many chunks from `c`, leaving it after
+/// the last one.
+///
+/// # Errors
+///
+/// - `GameError::Parse` if the list is truncated or holds a malformed
+///   chunk or an unknown chunk type.
+pub(crate) fn read_chunks(c: &mut Cursor<&[u8]>) -> Result<Bundle, GameError> {
+    let count = c
+        .read_u16::<LittleEndian>()
+        .map_err(|_| GameError::Parse("Chunk list truncated"))?;
+    let mut chunks = HashMap::new();
+    for _ in 0..count {
+        let rest = &c.get_ref()[c.position() as usize..];
+        let (t, id, _flags, comp_len, _un, hdr_len) = CyoaGame::parse_tlv_header(rest)?;
+        let chunk_type = ChunkType::from_u8(t).ok_or(GameError::Parse("Unknown chunk type"))?;
+        let len = hdr_len + comp_len as usize;
+        let chunk = rest
+            .get(..len)
+            .ok_or(GameError::Parse("Chunk list truncated"))?;
+        chunks.insert((chunk_type, id), Arc::new(chunk.to_vec()));
+        c.set_position(c.position() + len as u64);
+    }
+    Ok(chunks)
+}
@@ -0,0 +1,357 @@
+//! # Story Patches
+//!
+//! A patch adds, replaces or removes whole chunks of a `.story` file,
+//! addressed by `(chunk type, chunk id)`. Patches are applied in a chain on
+//! top of a base file at load time, so a story fix only ships the chunks
+//! that changed.
+//!
+//! ## Format
+//!
+//! All integers are little-endian.
+//!
+//! - 4 bytes: magic `CYPT`
+//! - u16: format version (currently `1`)
+//! - u64: fingerprint of the story this patch applies to
+//! - u64: fingerprint of the story after applying it
+//! - u32: number of operations
+//! - for each operation:
+//!   - u8: op (`0` = add/replace, `1` = remove)
+//!   - u8: chunk type
+//!   - 3 bytes: chunk ID
+//!   - add/replace only: u32 length + the full TLV chunk bytes
+//!
+//! ## Fingerprints
+//!
+//! A story's fingerprint is the u64 stored in its fingerprint metadata
+//! chunk (`00 00 0B`) when present, otherwise the FNV-1a hash of its
+//! index blob. After a patch is applied the story takes on the patch's
+//! target fingerprint, which is what the next patch in a chain checks.
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::fmt;
+use std::io::{Cursor, Read};
+
+use crate::decoder::{CyoaGame, IndexEntry};
+use crate::metadata::ID_FINGERPRINT;
+
+/// Magic bytes at the start of every patch file.
+const PATCH_MAGIC: &[u8; 4] = b"CYPT";
+/// Patch format version written by this crate.
+const PATCH_VERSION: u16 = 1;
+
+/// Errors raised while reading, creating or diffing patches.
+#[derive(Debug)]
+pub enum PatchError {
+    /// File magic did not match `CYPT`.
+    InvalidMagic,
+    /// The patch was written by a newer, unsupported format version.
+    UnsupportedVersion(u16),
+    /// The patch or story ended early or had a malformed field.
+    Malformed(&'static str),
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::InvalidMagic => write!(f, "Invalid patch magic"),
+            PatchError::UnsupportedVersion(v) => write!(f, "Unsupported patch version {}", v),
+            PatchError::Malformed(msg) => write!(f, "Malformed data: {}", msg),
+        }
+    }
+}
+
+/// One chunk-level change.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PatchOp {
+    /// Add the chunk, or replace it if `(chunk_type, id)` already exists.
+    Upsert {
+        chunk_type: u8,
+        id: [u8; 3],
+        /// Full TLV chunk bytes, exactly as stored in a `.story` file.
+        data: Vec<u8>,
+    },
+    /// Remove the chunk `(chunk_type, id)`.
+    Remove { chunk_type: u8, id: [u8; 3] },
+}
+
+/// A parsed patch file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Patch {
+    /// Fingerprint the story must have before this patch applies.
+    pub base_fingerprint: u64,
+    /// Fingerprint of the story once this patch is applied.
+    pub target_fingerprint: u64,
+    /// Operations, applied in order.
+    pub ops: Vec<PatchOp>,
+}
+
+impl Patch {
+    /// Parses a patch file.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Patch)` on success.
+    /// - `Err(PatchError)` on bad magic, unknown version or truncated data.
+    pub fn parse(bytes: &[u8]) -> Result<Patch, PatchError> {
+        let mut c = Cursor::new(bytes);
+        let mut magic = [0; 4];
+        c.read_exact(&mut magic)
+            .map_err(|_| PatchError::InvalidMagic)?;
+        if &magic != PATCH_MAGIC {
+            return Err(PatchError::InvalidMagic);
+        }
+        let version = c
+            .read_u16::<LittleEndian>()
+            .map_err(|_| PatchError::Malformed("Read version"))?;
+        if version > PATCH_VERSION {
+            return Err(PatchError::UnsupportedVersion(version));
+        }
+        let base_fingerprint = c
+            .read_u64::<LittleEndian>()
+            .map_err(|_| PatchError::Malformed("Read base fingerprint"))?;
+        let target_fingerprint = c
+            .read_u64::<LittleEndian>()
+            .map_err(|_| PatchError::Malformed("Read target fingerprint"))?;
+        let cnt = c
+            .read_u32::<LittleEndian>()
+            .map_err(|_| PatchError::Malformed("Read op count"))?;
+        // Every op takes at least 5 bytes, so a corrupt count cannot make
+        // us reserve more than the input could hold.
+        let remaining = bytes.len() - c.position() as usize;
+        let mut ops = Vec::with_capacity((cnt as usize).min(remaining / 5));
+        for _ in 0..cnt {
+            let op = c.read_u8().map_err(|_| PatchError::Malformed("Read op"))?;
+            let chunk_type = c
+                .read_u8()
+                .map_err(|_| PatchError::Malformed("Read chunk type"))?;
+            let mut id = [0; 3];
+            c.read_exact(&mut id)
+                .map_err(|_| PatchError::Malformed("Read chunk id"))?;
+            ops.push(match op {
+                0 => {
+                    let len = c
+                        .read_u32::<LittleEndian>()
+                        .map_err(|_| PatchError::Malformed("Read chunk length"))?;
+                    if len as u64 > bytes.len() as u64 - c.position() {
+                        return Err(PatchError::Malformed("Chunk length exceeds patch"));
+                    }
+                    let mut data = vec![0; len as usize];
+                    c.read_exact(&mut data)
+                        .map_err(|_| PatchError::Malformed("Read chunk data"))?;
+                    PatchOp::Upsert {
+                        chunk_type,
+                        id,
+                        data,
+                    }
+                }
+                1 => PatchOp::Remove { chunk_type, id },
+                _ => return Err(PatchError::Malformed("Unknown op")),
+            });
+        }
+        Ok(Patch {
+            base_fingerprint,
+            target_fingerprint,
+            ops,
+        })
+    }
+
+    /// Serializes the patch into the on-disk format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(PATCH_MAGIC);
+        // Writing into a Vec cannot fail.
+        out.write_u16::<LittleEndian>(PATCH_VERSION).unwrap();
+        out.write_u64::<LittleEndian>(self.base_fingerprint).unwrap();
+        out.write_u64::<LittleEndian>(self.target_fingerprint).unwrap();
+        out.write_u32::<LittleEndian>(self.ops.len() as u32).unwrap();
+        for op in &self.ops {
+            match op {
+                PatchOp::Upsert {
+                    chunk_type,
+                    id,
+                    data,
+                } => {
+                    out.push(0);
+                    out.push(*chunk_type);
+                    out.extend_from_slice(id);
+                    out.write_u32::<LittleEndian>(data.len() as u32).unwrap();
+                    out.extend_from_slice(data);
+                }
+                PatchOp::Remove { chunk_type, id } => {
+                    out.push(1);
+                    out.push(*chunk_type);
+                    out.extend_from_slice(id);
+                }
+            }
+        }
+        out
+    }
+}
+
+/// 64-bit FNV-1a hash, used for story fingerprints.
+pub fn fnv1a64(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in data {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+/// Every chunk of a complete `.story` file held in memory.
+struct StoryChunks<'a> {
+    index_blob: &'a [u8],
+    entries: Vec<(IndexEntry, &'a [u8])>,
+}
+
+impl<'a> StoryChunks<'a> {
+    /// Splits a complete story file into its index and chunk slices.
+    fn parse(story: &'a [u8]) -> Result<Self, PatchError> {
+        let header = story
+            .get(..crate::decoder::HEADER_LEN)
+            .ok_or(PatchError::Malformed("Story header truncated"))?;
+        let index_offset = CyoaGame::parse_header(header)
+            .map_err(|_| PatchError::Malformed("Story header"))? as usize;
+        let index_blob = story
+            .get(index_offset..)
+            .ok_or(PatchError::Malformed("Story index out of range"))?;
+        let index =
+            CyoaGame::parse_index(index_blob).map_err(|_| PatchError::Malformed("Story index"))?;
+        let mut entries = Vec::with_capacity(index.len());
+        for e in index {
+            let start = e.offset as usize;
+            let raw = story
+                .get(start..start + e.length as usize)
+                .ok_or(PatchError::Malformed("Story chunk out of range"))?;
+            entries.push((e, raw));
+        }
+        Ok(StoryChunks {
+            index_blob,
+            entries,
+        })
+    }
+
+    /// Returns the story's fingerprint (see the module docs).
+    fn fingerprint(&self) -> Result<u64, PatchError> {
+        let meta = self.entries.iter().find(|(e, _)| {
+            e.chunk_type as u8 == crate::decoder::ChunkType::Metadata as u8
+                && e.chunk_id == ID_FINGERPRINT
+        });
+        match meta {
+            Some((_, raw)) => fingerprint_from_chunk(raw),
+            None => Ok(fnv1a64(self.index_blob)),
+        }
+    }
+}
+
+/// Reads the u64 fingerprint stored in a fingerprint metadata chunk.
+pub(crate) fn fingerprint_from_chunk(raw: &[u8]) -> Result<u64, PatchError> {
+    let (_t, _id, flags, comp_len, un_len, hdr_len) =
+        CyoaGame::parse_tlv_header(raw).map_err(|_| PatchError::Malformed("Fingerprint chunk"))?;
+    let body = raw
+        .get(hdr_len..hdr_len + comp_len as usize)
+        .ok_or(PatchError::Malformed("Fingerprint chunk truncated"))?;
+    let payload = CyoaGame::decompress_payload(flags, body, un_len)
+        .map_err(|_| PatchError::Malformed("Fingerprint chunk"))?;
+    Cursor::new(payload)
+        .read_u64::<LittleEndian>()
+        .map_err(|_| PatchError::Malformed("Read fingerprint"))
+}
+
+/// Computes the fingerprint of a complete `.story` file.
+pub fn story_fingerprint(story: &[u8]) -> Result<u64, PatchError> {
+    StoryChunks::parse(story)?.fingerprint()
+}
+
+/// Builds the patch that turns story `old` into story `new`.
+///
+/// Chunks present only in `old` are removed; chunks that are new or whose
+/// bytes differ are added/replaced, in `new`'s index order. Added chunks
+/// are appended to the patched index, so node indices of a patched story
+/// can differ from those of `new` itself.
+pub fn diff(old: &[u8], new: &[u8]) -> Result<Patch, PatchError> {
+    let old = StoryChunks::parse(old)?;
+    let new = StoryChunks::parse(new)?;
+    let key = |e: &IndexEntry| (e.chunk_type as u8, e.chunk_id);
+    let mut ops = Vec::new();
+    for (e, _) in &old.entries {
+        if !new.entries.iter().any(|(n, _)| key(n) == key(e)) {
+            ops.push(PatchOp::Remove {
+                chunk_type: e.chunk_type as u8,
+                id: e.chunk_id,
+            });
+        }
+    }
+    for (e, raw) in &new.entries {
+        let unchanged = old
+            .entries
+            .iter()
+            .any(|(o, old_raw)| key(o) == key(e) && old_raw == raw);
+        if !unchanged {
+            ops.push(PatchOp::Upsert {
+                chunk_type: e.chunk_type as u8,
+                id: e.chunk_id,
+                data: raw.to_vec(),
+            });
+        }
+    }
+    Ok(Patch {
+        base_fingerprint: old.fingerprint()?,
+        target_fingerprint: new.fingerprint()?,
+        ops,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(cnt: u32) -> Vec<u8> {
+        let mut out = PATCH_MAGIC.to_vec();
+        out.write_u16::<LittleEndian>(PATCH_VERSION).unwrap();
+        out.write_u64::<LittleEndian>(1).unwrap();
+        out.write_u64::<LittleEndian>(2).unwrap();
+        out.write_u32::<LittleEndian>(cnt).unwrap();
+        out
+    }
+
+    #[test]
+    fn round_trips() {
+        let patch = Patch {
+            base_fingerprint: 1,
+            target_fingerprint: 2,
+            ops: vec![
+                PatchOp::Upsert {
+                    chunk_type: 3,
+                    id: [0, 0, 1],
+                    data: vec![1, 2, 3],
+                },
+                PatchOp::Remove {
+                    chunk_type: 1,
+                    id: [0, 0, 2],
+                },
+            ],
+        };
+        assert_eq!(Patch::parse(&patch.to_bytes()).unwrap(), patch);
+    }
+
+    #[test]
+    fn rejects_huge_op_count() {
+        assert!(matches!(
+            Patch::parse(&header(u32::MAX)),
+            Err(PatchError::Malformed("Read op"))
+        ));
+    }
+
+    #[test]
+    fn rejects_chunk_length_past_end() {
+        let mut bytes = header(1);
+        bytes.extend_from_slice(&[0, 3, 0, 0, 1]);
+        bytes.write_u32::<LittleEndian>(u32::MAX).unwrap();
+        bytes.extend_from_slice(&[1, 2, 3]);
+        assert!(matches!(
+            Patch::parse(&bytes),
+            Err(PatchError::Malformed("Chunk length exceeds patch"))
+        ));
+    }
+}
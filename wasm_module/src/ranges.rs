@@ -0,0 +1,426 @@
+//! # Range Planning
+//!
+//! Merges the byte spans of the chunks a load needs into as few HTTP
+//! Range requests as possible. Chunks written next to each other in the
+//! file (a node's content segments, its edges, their labels) usually end up
+//! in a single request.
+//!
+//! Also checks what comes back: servers and proxies may ignore `Range`
+//! (200 with the whole file), send a larger span than asked, report an
+//! unknown total (`*`), or answer with `multipart/byteranges`.
+
+/// One planned range request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MergedRange {
+    /// First byte offset of the range.
+    pub start: u64,
+    /// Last byte offset of the range (inclusive, as in a `Range` header).
+    pub end: u64,
+    /// Positions in the input `spans` covered by this range.
+    pub members: Vec<usize>,
+}
+
+/// Plans range requests for `spans`, given as `(offset, length)` pairs.
+///
+/// Spans are merged when the gap between the end of one and the start of
+/// the next is at most `max_gap` bytes; overlapping spans always merge.
+/// A larger gap fetches a few unused bytes in exchange for fewer round
+/// trips. Ranges are returned in file order.
+pub fn plan_ranges(spans: &[(u64, u32)], max_gap: u64) -> Vec<MergedRange> {
+    let mut order: Vec<usize> = (0..spans.len()).collect();
+    order.sort_by_key(|&i| spans[i].0);
+
+    let mut out: Vec<MergedRange> = Vec::new();
+    // Exclusive end of the range currently being built.
+    let mut cur_end = 0u64;
+    for i in order {
+        let (offset, length) = spans[i];
+        let end = offset + length as u64;
+        match out.last_mut() {
+            Some(r) if offset <= cur_end.saturating_add(max_gap) => {
+                cur_end = cur_end.max(end);
+                r.end = cur_end.saturating_sub(1).max(r.start);
+                r.members.push(i);
+            }
+            _ => {
+                cur_end = end;
+                out.push(MergedRange {
+                    start: offset,
+                    end: end.saturating_sub(1).max(offset),
+                    members: vec![i],
+                });
+            }
+        }
+    }
+    out
+}
+
+/// A parsed `Content-Range: bytes <start>-<end>/<total>` header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContentRange {
+    /// First and last byte (inclusive) of the body; `None` for the
+    /// `bytes */<total>` form sent with 416 responses.
+    pub span: Option<(u64, u64)>,
+    /// Size of the whole file; `None` when the server sent `*`.
+    pub total: Option<u64>,
+}
+
+/// Parses a `Content-Range` header value, returning `None` if it is not a
+/// well-formed `bytes` range.
+pub fn parse_content_range(value: &str) -> Option<ContentRange> {
+    let rest = value.trim().strip_prefix("bytes")?.trim_start();
+    let (span, total) = rest.split_once('/')?;
+    let total = match total.trim() {
+        "*" => None,
+        t => Some(t.parse().ok()?),
+    };
+    let span = match span.trim() {
+        "*" => None,
+        s => {
+            let (a, b) = s.split_once('-')?;
+            let (a, b): (u64, u64) = (a.trim().parse().ok()?, b.trim().parse().ok()?);
+            if a > b || total.is_some_and(|t| b >= t) {
+                return None;
+            }
+            Some((a, b))
+        }
+    };
+    if span.is_none() && total.is_none() {
+        return None;
+    }
+    Some(ContentRange { span, total })
+}
+
+/// Why a response could not be turned into the requested bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RangeError {
+    /// The server answered with an error status.
+    Status(u16),
+    /// The response does not hold the requested bytes.
+    Invalid(String),
+}
+
+/// Returns bytes `start..=end` (`start` to the end of the file without
+/// `end`) from a response to a Range request, checking the response
+/// against the request.
+///
+/// - 206 with a `Content-Range` must cover the requested span (servers may
+///   send more) and carry exactly the bytes it announces.
+/// - 206 `multipart/byteranges` is split into its parts, which together
+///   must cover the span.
+/// - 200 means the server ignored `Range` and sent the whole file, which
+///   is sliced locally.
+///
+/// An `end` past the end of the file is clamped to it.
+pub fn extract_range(
+    status: u16,
+    content_range: Option<&str>,
+    content_type: Option<&str>,
+    body: Vec<u8>,
+    start: u64,
+    end: Option<u64>,
+) -> Result<Vec<u8>, RangeError> {
+    let invalid = |msg: &str| RangeError::Invalid(msg.to_string());
+    match status {
+        206 => {
+            let boundary = content_type.and_then(multipart_boundary);
+            let parts = match &boundary {
+                Some(b) => multipart_parts(&body, b)?,
+                None => {
+                    let cr = content_range
+                        .and_then(parse_content_range)
+                        .ok_or_else(|| invalid("missing or malformed Content-Range"))?;
+                    let (s, e) = cr
+                        .span
+                        .ok_or_else(|| invalid("Content-Range has no span"))?;
+                    if e - s >= body.len() as u64 {
+                        return Err(invalid("body shorter than its Content-Range"));
+                    }
+                    vec![Part {
+                        start: s,
+                        end: e,
+                        total: cr.total,
+                        data: &body[..(e - s + 1) as usize],
+                    }]
+                }
+            };
+            assemble(&parts, start, end)
+        }
+        200 => {
+            let len = body.len() as u64;
+            if start >= len {
+                return Err(invalid("range starts past the end of the file"));
+            }
+            let last = end.map_or(len - 1, |e| e.min(len - 1));
+            Ok(body[start as usize..=last as usize].to_vec())
+        }
+        status => Err(RangeError::Status(status)),
+    }
+}
+
+/// One part of a range response: bytes `start..=end` of a file of
+/// `total` bytes, if known.
+struct Part<'a> {
+    start: u64,
+    end: u64,
+    total: Option<u64>,
+    data: &'a [u8],
+}
+
+/// Copies bytes `start..=end` out of `parts`, which must cover them
+/// without gaps.
+fn assemble(parts: &[Part], start: u64, end: Option<u64>) -> Result<Vec<u8>, RangeError> {
+    let invalid = |msg: &str| RangeError::Invalid(msg.to_string());
+    let total = parts.iter().find_map(|p| p.total);
+    let last = match (end, total) {
+        (Some(e), Some(t)) => e.min(t.saturating_sub(1)),
+        (Some(e), None) => e,
+        // Open-ended: up to the end of the file, or of what was sent.
+        (None, Some(t)) => t.saturating_sub(1),
+        (None, None) => parts.iter().map(|p| p.end).max().unwrap_or(start),
+    };
+    if last < start {
+        return Err(invalid("range starts past the end of the file"));
+    }
+    let mut order: Vec<&Part> = parts.iter().collect();
+    order.sort_by_key(|p| p.start);
+    let mut out = Vec::with_capacity((last - start + 1) as usize);
+    // Next byte offset still missing from `out`.
+    let mut next = start;
+    for p in order {
+        if p.end < next {
+            continue;
+        }
+        if p.start > next {
+            break;
+        }
+        let to = p.end.min(last);
+        out.extend_from_slice(&p.data[(next - p.start) as usize..=(to - p.start) as usize]);
+        next = to + 1;
+        if next > last {
+            return Ok(out);
+        }
+    }
+    Err(invalid("response does not cover the requested range"))
+}
+
+/// Returns the boundary of a `multipart/byteranges` content type.
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';');
+    let mime = params.next()?.trim();
+    if !mime.eq_ignore_ascii_case("multipart/byteranges") {
+        return None;
+    }
+    params.find_map(|p| {
+        let (k, v) = p.split_once('=')?;
+        k.trim()
+            .eq_ignore_ascii_case("boundary")
+            .then(|| v.trim().trim_matches('"').to_string())
+    })
+}
+
+/// Splits a `multipart/byteranges` body into its parts. Each part's data
+/// length is taken from its own `Content-Range`, so binary data that
+/// happens to contain the boundary is read correctly.
+fn multipart_parts<'a>(body: &'a [u8], boundary: &str) -> Result<Vec<Part<'a>>, RangeError> {
+    let invalid = |msg: &str| RangeError::Invalid(format!("multipart response: {}", msg));
+    let delim = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut pos = find(body, delim.as_bytes(), 0).ok_or_else(|| invalid("no boundary"))?;
+    loop {
+        pos += delim.len();
+        if body[pos..].starts_with(b"--") {
+            return Ok(parts);
+        }
+        let head_end = find(body, b"\r\n\r\n", pos).ok_or_else(|| invalid("truncated headers"))?;
+        let head = String::from_utf8_lossy(&body[pos..head_end]);
+        let cr = head
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.trim()
+                    .eq_ignore_ascii_case("content-range")
+                    .then(|| parse_content_range(value))
+                    .flatten()
+            })
+            .ok_or_else(|| invalid("part without Content-Range"))?;
+        let (s, e) = cr.span.ok_or_else(|| invalid("part without span"))?;
+        let data_start = head_end + 4;
+        // Compare as u64 first: the span may not fit a wasm32 usize.
+        if e - s >= (body.len() - data_start) as u64 {
+            return Err(invalid("truncated part"));
+        }
+        let data_end = data_start + (e - s + 1) as usize;
+        let data = &body[data_start..data_end];
+        parts.push(Part {
+            start: s,
+            end: e,
+            total: cr.total,
+            data,
+        });
+        pos = find(body, delim.as_bytes(), data_end).ok_or_else(|| invalid("missing boundary"))?;
+    }
+}
+
+/// Returns the first position of `needle` in `haystack` at or after
+/// `from`.
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|i| from + i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u64, end: u64, members: &[usize]) -> MergedRange {
+        MergedRange {
+            start,
+            end,
+            members: members.to_vec(),
+        }
+    }
+
+    #[test]
+    fn merges_spans_within_the_gap() {
+        let spans = [(0, 10), (12, 4), (30, 5)];
+        assert_eq!(plan_ranges(&spans, 2), vec![range(0, 15, &[0, 1]), range(30, 34, &[2])]);
+        assert_eq!(plan_ranges(&spans, 14), vec![range(0, 34, &[0, 1, 2])]);
+        assert_eq!(plan_ranges(&spans, 1).len(), 3);
+    }
+
+    #[test]
+    fn merges_adjacent_and_overlapping_spans() {
+        assert_eq!(plan_ranges(&[(0, 10), (10, 5)], 0), vec![range(0, 14, &[0, 1])]);
+        assert_eq!(plan_ranges(&[(0, 10), (4, 2)], 0), vec![range(0, 9, &[0, 1])]);
+        assert_eq!(plan_ranges(&[(0, 10), (4, 20)], 0), vec![range(0, 23, &[0, 1])]);
+    }
+
+    #[test]
+    fn keeps_zero_length_spans() {
+        assert_eq!(plan_ranges(&[(5, 0)], 0), vec![range(5, 5, &[0])]);
+        assert_eq!(plan_ranges(&[(0, 4), (4, 0)], 0), vec![range(0, 3, &[0, 1])]);
+    }
+
+    #[test]
+    fn orders_ranges_by_offset_and_keeps_input_positions() {
+        let spans = [(100, 4), (0, 4), (50, 4), (4, 4)];
+        assert_eq!(
+            plan_ranges(&spans, 0),
+            vec![range(0, 7, &[1, 3]), range(50, 53, &[2]), range(100, 103, &[0])]
+        );
+    }
+
+    #[test]
+    fn plans_nothing_for_no_spans() {
+        assert!(plan_ranges(&[], 16).is_empty());
+    }
+
+    fn file() -> Vec<u8> {
+        (0..=99).collect()
+    }
+
+    #[test]
+    fn parses_unknown_totals() {
+        assert_eq!(
+            parse_content_range("bytes 10-19/*"),
+            Some(ContentRange {
+                span: Some((10, 19)),
+                total: None
+            })
+        );
+        assert_eq!(
+            parse_content_range("bytes */100"),
+            Some(ContentRange {
+                span: None,
+                total: Some(100)
+            })
+        );
+        assert_eq!(parse_content_range("bytes */*"), None);
+        assert_eq!(parse_content_range("bytes 10-100/100"), None);
+    }
+
+    #[test]
+    fn reads_206_with_unknown_total() {
+        let body = file()[10..20].to_vec();
+        let out = extract_range(206, Some("bytes 10-19/*"), None, body, 12, Some(15));
+        assert_eq!(out, Ok(vec![12, 13, 14, 15]));
+    }
+
+    #[test]
+    fn slices_200_responses() {
+        assert_eq!(extract_range(200, None, None, file(), 10, Some(12)), Ok(vec![10, 11, 12]));
+        assert_eq!(extract_range(200, None, None, file(), 98, None), Ok(vec![98, 99]));
+        assert_eq!(extract_range(200, None, None, file(), 98, Some(500)), Ok(vec![98, 99]));
+        assert!(extract_range(200, None, None, file(), 100, None).is_err());
+    }
+
+    #[test]
+    fn trims_oversized_206() {
+        let body = file()[0..50].to_vec();
+        let out = extract_range(206, Some("bytes 0-49/100"), None, body, 20, Some(22));
+        assert_eq!(out, Ok(vec![20, 21, 22]));
+    }
+
+    #[test]
+    fn rejects_206_not_covering_the_request() {
+        let body = file()[30..40].to_vec();
+        let out = extract_range(206, Some("bytes 30-39/100"), None, body, 20, Some(35));
+        assert!(matches!(out, Err(RangeError::Invalid(_))));
+        let short = file()[30..35].to_vec();
+        let out = extract_range(206, Some("bytes 30-39/100"), None, short, 30, Some(35));
+        assert!(matches!(out, Err(RangeError::Invalid(_))));
+    }
+
+    #[test]
+    fn passes_error_statuses_through() {
+        let out = extract_range(416, Some("bytes */100"), None, Vec::new(), 200, None);
+        assert_eq!(out, Err(RangeError::Status(416)));
+    }
+
+    /// Builds a multipart body with one part per `(start, data)`.
+    fn multipart(boundary: &str, parts: &[(u64, &[u8])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (start, data) in parts {
+            let end = start + data.len() as u64 - 1;
+            let head = format!(
+                "--{}\r\nContent-Type: application/octet-stream\r\n\
+                 Content-Range: bytes {}-{}/100\r\n\r\n",
+                boundary, start, end
+            );
+            out.extend_from_slice(head.as_bytes());
+            out.extend_from_slice(data);
+            out.extend_from_slice(b"\r\n");
+        }
+        out.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+        out
+    }
+
+    #[test]
+    fn joins_multipart_parts() {
+        let body = multipart("XYZ", &[(10, &[10, 11, 12]), (13, &[13, 14])]);
+        let ct = Some("multipart/byteranges; boundary=XYZ");
+        assert_eq!(extract_range(206, None, ct, body, 11, Some(14)), Ok(vec![11, 12, 13, 14]));
+    }
+
+    #[test]
+    fn reads_multipart_data_containing_the_boundary() {
+        let data = b"a--XYZ\r\nb";
+        let body = multipart("XYZ", &[(0, data)]);
+        let ct = Some("multipart/byteranges; boundary=\"XYZ\"");
+        let out = extract_range(206, None, ct, body, 0, Some(data.len() as u64 - 1));
+        assert_eq!(out, Ok(data.to_vec()));
+    }
+
+    #[test]
+    fn rejects_multipart_parts_longer_than_the_body() {
+        let mut body = b"--XYZ\r\nContent-Range: bytes 0-18446744073709551614/*\r\n\r\n".to_vec();
+        body.extend_from_slice(b"abc\r\n--XYZ--");
+        let ct = Some("multipart/byteranges; boundary=XYZ");
+        let out = extract_range(206, None, ct, body, 0, Some(2));
+        assert!(matches!(out, Err(RangeError::Invalid(_))));
+    }
+}
@@ -0,0 +1,277 @@
+//! # Native Story Reader
+//!
+//! A synchronous reader for a complete `.story` file held in memory or
+//! memory-mapped, for hosts that can read the file directly instead of
+//! fetching it over HTTP (the Tauri desktop shell). It uses the same
+//! parsers, decoded records and `engine` rules as `CyoaGame` and returns
+//! the same node shape.
+//!
+//! The reader covers a single file: loading the destination of an edge
+//! that links into another story file fails with
+//! [`ReadError::UnresolvedLink`]. Unlocked achievements are kept by the
+//! caller and passed in on every load.
+
+use serde::Serialize;
+use std::fmt;
+
+use crate::achievements::{Achievement, AchievementOutput, parse_catalog};
+use crate::decoded::{ContentText, EdgeRecord, NodeRecord, Record};
+use crate::decoder::{ChunkType, CyoaGame, FuncCall, GameError, HEADER_LEN, IndexEntry};
+use crate::engine;
+use crate::metadata::ID_ROOT_POINTER;
+
+/// Errors raised while reading a story file.
+#[derive(Debug)]
+pub enum ReadError {
+    /// File magic did not match `CYOA`.
+    InvalidMagic,
+    /// An index entry or pointer lies outside the file.
+    IndexOutOfRange,
+    /// The root pointer metadata chunk is missing.
+    MissingRoot,
+    /// An edge leads into another story file, named by its namespace.
+    UnresolvedLink(String),
+    /// The file ended early or had a malformed field.
+    Malformed(String),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::InvalidMagic => write!(f, "Invalid file magic"),
+            ReadError::IndexOutOfRange => write!(f, "Index out of range"),
+            ReadError::MissingRoot => write!(f, "Root pointer metadata missing"),
+            ReadError::UnresolvedLink(ns) => write!(f, "Edge links into unmounted story {}", ns),
+            ReadError::Malformed(msg) => write!(f, "Malformed data: {}", msg),
+        }
+    }
+}
+
+impl From<GameError> for ReadError {
+    fn from(err: GameError) -> ReadError {
+        match err {
+            GameError::InvalidMagic => ReadError::InvalidMagic,
+            GameError::IndexOutOfRange => ReadError::IndexOutOfRange,
+            GameError::MissingRoot => ReadError::MissingRoot,
+            GameError::Parse(msg) => ReadError::Malformed(msg.to_string()),
+            other => ReadError::Malformed(format!("{:?}", other)),
+        }
+    }
+}
+
+/// Represents one outgoing edge from a node.
+#[derive(Clone, Debug, Serialize)]
+pub struct EdgeOutput {
+    /// Text label shown for this choice.
+    pub label: String,
+    /// Index of the node this edge points to.
+    pub dest_idx: u32,
+}
+
+/// The in‐memory representation of a game node:
+/// its content text plus all outgoing edges.
+#[derive(Serialize)]
+pub struct NodeOutput {
+    /// The narrative or choice text.
+    pub content: String,
+    /// All outgoing edges (choices).
+    pub edges: Vec<EdgeOutput>,
+    /// Achievements newly unlocked by this transition, for toasts.
+    pub unlocked: Vec<AchievementOutput>,
+}
+
+/// A parsed story file over bytes `B` (a `Vec<u8>`, a memory map, ...).
+pub struct StoryReader<B: AsRef<[u8]>> {
+    bytes: B,
+    index: Vec<IndexEntry>,
+    catalog: Vec<Achievement>,
+}
+
+impl<B: AsRef<[u8]>> StoryReader<B> {
+    /// Parses the header, index and achievements catalog of `bytes`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(StoryReader)` on success.
+    /// - `Err(ReadError)` on bad magic, an out-of-range index or a
+    ///   malformed index or catalog.
+    pub fn open(bytes: B) -> Result<Self, ReadError> {
+        let data = bytes.as_ref();
+        let header = data.get(..HEADER_LEN).ok_or(ReadError::InvalidMagic)?;
+        let index_offset = CyoaGame::parse_header(header)? as usize;
+        let idx_blob = data.get(index_offset..).ok_or(ReadError::IndexOutOfRange)?;
+        let index = CyoaGame::parse_index(idx_blob)?;
+        let mut reader = StoryReader {
+            bytes,
+            index,
+            catalog: Vec::new(),
+        };
+        let mut catalog = Vec::new();
+        for e in reader.index.iter().filter(|e| e.chunk_type == ChunkType::Achievements) {
+            catalog.extend(parse_catalog(&reader.payload(e)?)?);
+        }
+        reader.catalog = catalog;
+        Ok(reader)
+    }
+
+    /// Number of index entries; node indices range below this.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns `true` if the index has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Index of the node named by the root pointer metadata chunk.
+    pub fn root_idx(&self) -> Result<usize, ReadError> {
+        let meta = self
+            .find_entry(ChunkType::Metadata, &ID_ROOT_POINTER)
+            .ok_or(ReadError::MissingRoot)?;
+        let raw = self.raw(meta)?;
+        let (_t, _i, _f, _c, _u, h) = CyoaGame::parse_tlv_header(raw)?;
+        let cid: [u8; 3] = raw
+            .get(h..h + 3)
+            .and_then(|b| b.try_into().ok())
+            .ok_or(ReadError::MissingRoot)?;
+        engine::node_position(&self.index, &cid)
+            .ok_or(ReadError::Malformed("root node chunk not found".to_string()))
+    }
+
+    /// Loads node `idx`: runs its entry functions, evaluates content
+    /// guards and resolves every edge's label and destination.
+    ///
+    /// `unlocked` is the player's set of unlocked achievement IDs; newly
+    /// unlocked ones are appended to it and reported in the output.
+    pub fn load_node(
+        &self,
+        idx: usize,
+        unlocked: &mut Vec<[u8; 3]>,
+    ) -> Result<NodeOutput, ReadError> {
+        self.load_node_with_unlocks(idx, unlocked, Vec::new())
+    }
+
+    /// Follows edge `choice` of node `from_idx`, applying the edge's
+    /// effects, and loads the destination node.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok((dest_idx, node))`: The destination's index and its node.
+    /// - `Err(ReadError)`: On an out-of-range choice or malformed chunks.
+    pub fn choose(
+        &self,
+        from_idx: usize,
+        choice: usize,
+        unlocked: &mut Vec<[u8; 3]>,
+    ) -> Result<(usize, NodeOutput), ReadError> {
+        let node = self.node_record(from_idx)?;
+        let edge = self.record::<EdgeRecord>(ChunkType::Edge, &engine::edge_id(&node, choice)?)?;
+        let fresh = self.apply_effects(&edge.effects, unlocked)?;
+        let dest_idx = engine::edge_dest_position(&self.index, &edge.dest)?;
+        let node = self.load_node_with_unlocks(dest_idx, unlocked, fresh)?;
+        Ok((dest_idx, node))
+    }
+
+    /// The full achievements catalog with unlock state from `unlocked`.
+    pub fn achievements(&self, unlocked: &[[u8; 3]]) -> Vec<AchievementOutput> {
+        self.catalog
+            .iter()
+            .map(|a| a.to_output(unlocked.contains(&a.id)))
+            .collect()
+    }
+
+    fn load_node_with_unlocks(
+        &self,
+        idx: usize,
+        unlocked: &mut Vec<[u8; 3]>,
+        mut fresh: Vec<AchievementOutput>,
+    ) -> Result<NodeOutput, ReadError> {
+        let node = self.node_record(idx)?;
+        fresh.extend(self.apply_effects(&node.entry_funcs, unlocked)?);
+
+        let mut content = String::new();
+        for cid in engine::visible_content(&node) {
+            content.push_str(&self.record::<ContentText>(ChunkType::Content, &cid)?.0);
+        }
+
+        let mut edges = Vec::new();
+        for cid in &node.edge_ids {
+            let edge = self.record::<EdgeRecord>(ChunkType::Edge, cid)?;
+            edges.push(EdgeOutput {
+                label: self.record::<ContentText>(ChunkType::Content, &edge.label)?.0,
+                dest_idx: engine::edge_dest_position(&self.index, &edge.dest)? as u32,
+            });
+        }
+
+        Ok(NodeOutput {
+            content,
+            edges,
+            unlocked: fresh,
+        })
+    }
+
+    /// Applies the engine-level effects among `calls`, returning the
+    /// achievements that were newly unlocked.
+    fn apply_effects(
+        &self,
+        calls: &[FuncCall],
+        unlocked: &mut Vec<[u8; 3]>,
+    ) -> Result<Vec<AchievementOutput>, ReadError> {
+        let fresh = engine::apply_effects(calls, &self.catalog, |id| {
+            if unlocked.contains(&id) {
+                return false;
+            }
+            unlocked.push(id);
+            true
+        })?;
+        Ok(fresh)
+    }
+
+    /// Decodes the node at `idx`. A link into another story file, which an
+    /// edge may lead to, fails with `ReadError::UnresolvedLink`.
+    fn node_record(&self, idx: usize) -> Result<NodeRecord, ReadError> {
+        let entry = self
+            .index
+            .get(idx)
+            .ok_or(ReadError::Malformed("not a node chunk".to_string()))?;
+        match entry.chunk_type {
+            ChunkType::Node => Ok(NodeRecord::decode(&self.payload(entry)?)?),
+            ChunkType::Link => {
+                let (namespace, _) = CyoaGame::parse_link(&self.payload(entry)?)?;
+                Err(ReadError::UnresolvedLink(namespace))
+            }
+            _ => Err(ReadError::Malformed("not a node chunk".to_string())),
+        }
+    }
+
+    /// Decodes the chunk `cid` of type `chunk_type` as a `T`.
+    fn record<T: Record>(&self, chunk_type: ChunkType, cid: &[u8; 3]) -> Result<T, ReadError> {
+        Ok(T::decode(&self.payload_of(chunk_type, cid)?)?)
+    }
+
+    fn payload_of(&self, chunk_type: ChunkType, cid: &[u8; 3]) -> Result<Vec<u8>, ReadError> {
+        let entry = self
+            .find_entry(chunk_type, cid)
+            .ok_or(ReadError::Malformed(format!("{:?} chunk not found", chunk_type)))?;
+        self.payload(entry)
+    }
+
+    /// Returns the decompressed payload of `entry`.
+    fn payload(&self, entry: &IndexEntry) -> Result<Vec<u8>, ReadError> {
+        Ok(CyoaGame::chunk_payload(self.raw(entry)?)?)
+    }
+
+    /// Returns the raw TLV bytes of `entry`.
+    fn raw(&self, entry: &IndexEntry) -> Result<&[u8], ReadError> {
+        let start = entry.offset as usize;
+        self.bytes
+            .as_ref()
+            .get(start..start + entry.length as usize)
+            .ok_or(ReadError::IndexOutOfRange)
+    }
+
+    fn find_entry(&self, chunk_type: ChunkType, cid: &[u8; 3]) -> Option<&IndexEntry> {
+        engine::find_entry(&self.index, chunk_type, cid)
+    }
+}
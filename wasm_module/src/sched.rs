@@ -0,0 +1,252 @@
+//! # Fetch Scheduling
+//!
+//! Limits how many chunk requests run at once and decides which queued
+//! request goes next. Every request takes a `Permit` before it touches the
+//! network and gives it back when dropped; while all permits are out,
+//! requests wait in a queue ordered by priority class, oldest first within
+//! a class.
+//!
+//! A waiter's priority is shared with the in-flight fetch it belongs to,
+//! so when a visible load joins a chunk that a prefetch already queued,
+//! raising the priority moves the queued request up.
+
+use futures::channel::oneshot;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::decoder::GameError;
+
+/// Default number of chunk requests allowed in flight at once; browsers
+/// open about six connections per host.
+pub(crate) const DEFAULT_MAX_CONCURRENCY: usize = 6;
+
+/// Priority class of a request; later variants go first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Priority {
+    /// Chunks stored for offline play.
+    Download,
+    /// Chunks of nodes the player might visit next.
+    Prefetch,
+    /// Labels of the choices on screen.
+    Label,
+    /// The node, content and edges being shown.
+    Visible,
+}
+
+/// One request waiting for a permit.
+struct Waiter {
+    priority: Rc<Cell<Priority>>,
+    /// Arrival order, to keep each class first-in first-out.
+    seq: u64,
+    tx: oneshot::Sender<Permit>,
+}
+
+/// Hands out request permits, at most `limit` at a time.
+pub(crate) struct Scheduler {
+    limit: Cell<usize>,
+    active: Cell<usize>,
+    queue: RefCell<Vec<Waiter>>,
+    next_seq: Cell<u64>,
+}
+
+/// Leave to run one request; frees its slot when dropped.
+pub(crate) struct Permit(Rc<Scheduler>);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.0.active.set(self.0.active.get() - 1);
+        self.0.dispatch();
+    }
+}
+
+impl Scheduler {
+    /// Creates a scheduler allowing `limit` concurrent requests.
+    pub(crate) fn new(limit: usize) -> Rc<Self> {
+        Rc::new(Scheduler {
+            limit: Cell::new(limit.max(1)),
+            active: Cell::new(0),
+            queue: RefCell::new(Vec::new()),
+            next_seq: Cell::new(0),
+        })
+    }
+
+    /// Changes the concurrency limit (at least 1), starting queued
+    /// requests if it grew.
+    pub(crate) fn set_limit(self: &Rc<Self>, limit: usize) {
+        self.limit.set(limit.max(1));
+        self.dispatch();
+    }
+
+    /// Waits for a permit at `priority`, which may be raised while
+    /// waiting.
+    ///
+    /// # Errors
+    ///
+    /// - `GameError::Cancelled` if the wait is cancelled by `cancel`.
+    pub(crate) async fn acquire(
+        self: &Rc<Self>,
+        priority: Rc<Cell<Priority>>,
+    ) -> Result<Permit, GameError> {
+        if self.active.get() < self.limit.get() && self.queue.borrow().is_empty() {
+            self.active.set(self.active.get() + 1);
+            return Ok(Permit(self.clone()));
+        }
+        let (tx, rx) = oneshot::channel();
+        let seq = self.next_seq.get();
+        self.next_seq.set(seq + 1);
+        self.queue.borrow_mut().push(Waiter { priority, seq, tx });
+        rx.await.map_err(|_| GameError::Cancelled)
+    }
+
+    /// Drops every queued request of class `priority`; their callers get
+    /// `GameError::Cancelled`. Running requests are not affected.
+    pub(crate) fn cancel(&self, priority: Priority) {
+        self.queue
+            .borrow_mut()
+            .retain(|w| w.priority.get() != priority);
+    }
+
+    /// Hands free slots to the highest-priority waiters.
+    fn dispatch(self: &Rc<Self>) {
+        while self.active.get() < self.limit.get() {
+            let waiter = {
+                let mut queue = self.queue.borrow_mut();
+                queue.retain(|w| !w.tx.is_canceled());
+                let Some(next) = (0..queue.len())
+                    .max_by_key(|&i| (queue[i].priority.get(), std::cmp::Reverse(queue[i].seq)))
+                else {
+                    return;
+                };
+                queue.swap_remove(next)
+            };
+            self.active.set(self.active.get() + 1);
+            // The receiver is alive (checked above, nothing ran since); if
+            // it is dropped before taking the permit, the permit drops with
+            // the channel and frees the slot again.
+            let _ = waiter.tx.send(Permit(self.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+    use futures::executor::block_on;
+    use futures::future::LocalBoxFuture;
+    use futures::task::noop_waker_ref;
+    use std::task::{Context, Poll};
+
+    type Wait = LocalBoxFuture<'static, Result<Permit, GameError>>;
+
+    /// Starts waiting for a permit at `priority`, returning the wait and
+    /// its shared priority.
+    fn wait(sched: &Rc<Scheduler>, priority: Priority) -> (Option<Wait>, Rc<Cell<Priority>>) {
+        let shared = Rc::new(Cell::new(priority));
+        let (sched, queued) = (sched.clone(), shared.clone());
+        let mut wait = async move { sched.acquire(queued).await }.boxed_local();
+        assert!(poll(&mut wait).is_pending());
+        (Some(wait), shared)
+    }
+
+    fn poll(wait: &mut Wait) -> Poll<Result<Permit, GameError>> {
+        wait.as_mut()
+            .poll(&mut Context::from_waker(noop_waker_ref()))
+    }
+
+    /// Polls the waits still pending and returns the positions of those
+    /// that got a permit, keeping the permits in `held`.
+    fn granted(waits: &mut [Option<Wait>], held: &mut Vec<Permit>) -> Vec<usize> {
+        let mut out = Vec::new();
+        for (i, slot) in waits.iter_mut().enumerate() {
+            if let Some(wait) = slot
+                && let Poll::Ready(result) = poll(wait)
+            {
+                held.push(result.unwrap());
+                *slot = None;
+                out.push(i);
+            }
+        }
+        out
+    }
+
+    /// Queues `waits` behind the only permit of a scheduler, then frees
+    /// one permit at a time and returns the order they were served in.
+    fn order(sched: &Rc<Scheduler>, first: Permit, mut waits: Vec<Option<Wait>>) -> Vec<usize> {
+        let mut held = vec![first];
+        let mut out = Vec::new();
+        while let Some(permit) = held.pop() {
+            drop(permit);
+            let next = granted(&mut waits, &mut held);
+            assert!(next.len() <= 1, "{:?} served at once", next);
+            out.extend(next);
+        }
+        assert_eq!(sched.active.get(), 0);
+        out
+    }
+
+    #[test]
+    fn serves_a_class_first_in_first_out() {
+        let sched = Scheduler::new(1);
+        let first = block_on(sched.acquire(Rc::new(Cell::new(Priority::Visible)))).unwrap();
+        let waits = (0..4).map(|_| wait(&sched, Priority::Prefetch).0).collect();
+        assert_eq!(order(&sched, first, waits), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn serves_higher_classes_first() {
+        let sched = Scheduler::new(1);
+        let first = block_on(sched.acquire(Rc::new(Cell::new(Priority::Visible)))).unwrap();
+        let waits = [
+            Priority::Download,
+            Priority::Prefetch,
+            Priority::Visible,
+            Priority::Label,
+            Priority::Visible,
+        ]
+        .into_iter()
+        .map(|p| wait(&sched, p).0)
+        .collect();
+        assert_eq!(order(&sched, first, waits), [2, 4, 3, 1, 0]);
+    }
+
+    #[test]
+    fn raising_a_shared_priority_moves_the_waiter_up() {
+        let sched = Scheduler::new(1);
+        let first = block_on(sched.acquire(Rc::new(Cell::new(Priority::Visible)))).unwrap();
+        let (a, _) = wait(&sched, Priority::Prefetch);
+        let (b, _) = wait(&sched, Priority::Label);
+        let (c, raised) = wait(&sched, Priority::Prefetch);
+        raised.set(Priority::Visible);
+        assert_eq!(order(&sched, first, vec![a, b, c]), [2, 1, 0]);
+    }
+
+    #[test]
+    fn cancelled_waiters_fail() {
+        let sched = Scheduler::new(1);
+        let first = block_on(sched.acquire(Rc::new(Cell::new(Priority::Visible)))).unwrap();
+        let (mut prefetch, _) = wait(&sched, Priority::Prefetch);
+        let (visible, _) = wait(&sched, Priority::Visible);
+        sched.cancel(Priority::Prefetch);
+        let result = poll(prefetch.as_mut().unwrap());
+        assert!(matches!(result, Poll::Ready(Err(GameError::Cancelled))));
+        assert_eq!(order(&sched, first, vec![visible]), [0]);
+    }
+
+    #[test]
+    fn raising_the_limit_starts_queued_requests() {
+        let sched = Scheduler::new(2);
+        let mut held: Vec<Permit> = (0..2)
+            .map(|_| block_on(sched.acquire(Rc::new(Cell::new(Priority::Visible)))).unwrap())
+            .collect();
+        let mut waits: Vec<_> = (0..3).map(|_| wait(&sched, Priority::Label).0).collect();
+        assert!(granted(&mut waits, &mut held).is_empty());
+        sched.set_limit(4);
+        assert_eq!(granted(&mut waits, &mut held), [0, 1]);
+        assert_eq!(sched.active.get(), 4);
+        held.clear();
+        assert_eq!(granted(&mut waits, &mut held), [2]);
+        held.clear();
+        assert_eq!(sched.active.get(), 0);
+    }
+}
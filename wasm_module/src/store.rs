@@ -0,0 +1,229 @@
+//! # Chunk Stores
+//!
+//! Second tier behind each file's in-memory chunk cache: raw chunks kept
+//! across sessions, so a returning player does not download them again.
+//!
+//! Entries are keyed by story URL, story fingerprint and chunk, so chunks
+//! of an older revision of a story are never served for a newer one;
+//! `prune` drops them once the new revision is mounted.
+//!
+//! Stores are best-effort: failures (quota, private browsing, missing
+//! APIs) read as misses and are otherwise ignored.
+
+use futures::FutureExt;
+use futures::future::LocalBoxFuture;
+use js_sys::{Array, Uint8Array, encode_uri_component};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Cache, Request, Response, window};
+
+use crate::cache::ChunkKey;
+
+/// Identifies one revision of one story file in a store.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct StoryKey {
+    pub(crate) url: String,
+    pub(crate) fingerprint: u64,
+}
+
+/// A place raw chunks can be persisted to and read back from.
+pub(crate) trait ChunkStore {
+    /// Returns the stored bytes of chunk `key` of `story`, if any.
+    fn get<'a>(&'a self, story: &'a StoryKey, key: ChunkKey)
+    -> LocalBoxFuture<'a, Option<Vec<u8>>>;
+
+    /// Stores the bytes of chunk `key` of `story`.
+    fn put<'a>(
+        &'a self,
+        story: &'a StoryKey,
+        key: ChunkKey,
+        data: Vec<u8>,
+    ) -> LocalBoxFuture<'a, ()>;
+
+    /// Removes every entry of `story.url` stored under another fingerprint.
+    fn prune<'a>(&'a self, story: &'a StoryKey) -> LocalBoxFuture<'a, ()>;
+}
+
+/// Store that lives only as long as the game; used when the browser has
+/// no Cache API (e.g. insecure origins).
+#[derive(Default)]
+pub(crate) struct MemoryStore {
+    entries: RefCell<HashMap<(StoryKey, ChunkKey), Vec<u8>>>,
+}
+
+impl ChunkStore for MemoryStore {
+    fn get<'a>(
+        &'a self,
+        story: &'a StoryKey,
+        key: ChunkKey,
+    ) -> LocalBoxFuture<'a, Option<Vec<u8>>> {
+        let hit = self.entries.borrow().get(&(story.clone(), key)).cloned();
+        async move { hit }.boxed_local()
+    }
+
+    fn put<'a>(
+        &'a self,
+        story: &'a StoryKey,
+        key: ChunkKey,
+        data: Vec<u8>,
+    ) -> LocalBoxFuture<'a, ()> {
+        self.entries.borrow_mut().insert((story.clone(), key), data);
+        async {}.boxed_local()
+    }
+
+    fn prune<'a>(&'a self, story: &'a StoryKey) -> LocalBoxFuture<'a, ()> {
+        self.entries
+            .borrow_mut()
+            .retain(|(s, _), _| s.url != story.url || s.fingerprint == story.fingerprint);
+        async {}.boxed_local()
+    }
+}
+
+/// Name of the Cache API cache holding persisted chunks.
+const CACHE_NAME: &str = "cyoa-chunks";
+/// Path prefix of the synthetic request URLs entries are stored under.
+const KEY_PREFIX: &str = "/__cyoa_chunks__/";
+
+/// Store backed by the browser's Cache API, which persists across
+/// sessions and is available to pages and service workers alike.
+pub(crate) struct CacheApiStore;
+
+impl CacheApiStore {
+    /// Returns a store if the Cache API is available, else `None`.
+    pub(crate) fn available() -> Option<Self> {
+        window()?.caches().ok().map(|_| CacheApiStore)
+    }
+
+    /// Opens the chunk cache.
+    async fn cache() -> Option<Cache> {
+        let caches = window()?.caches().ok()?;
+        JsFuture::from(caches.open(CACHE_NAME))
+            .await
+            .ok()?
+            .dyn_into()
+            .ok()
+    }
+
+    /// Request path prefix shared by every entry of `url`.
+    fn story_prefix(url: &str) -> String {
+        format!("{}{}/", KEY_PREFIX, String::from(encode_uri_component(url)))
+    }
+
+    /// Request path of chunk `key` of `story`.
+    fn request_path(story: &StoryKey, key: ChunkKey) -> String {
+        format!(
+            "{}{:016x}/{:02x}-{}",
+            Self::story_prefix(&story.url),
+            story.fingerprint,
+            key.0 as u8,
+            crate::utils::hex_id(&key.1)
+        )
+    }
+}
+
+impl ChunkStore for CacheApiStore {
+    fn get<'a>(
+        &'a self,
+        story: &'a StoryKey,
+        key: ChunkKey,
+    ) -> LocalBoxFuture<'a, Option<Vec<u8>>> {
+        async move {
+            let cache = Self::cache().await?;
+            let hit = JsFuture::from(cache.match_with_str(&Self::request_path(story, key)))
+                .await
+                .ok()?;
+            let resp: Response = hit.dyn_into().ok()?;
+            let buf = JsFuture::from(resp.array_buffer().ok()?).await.ok()?;
+            Some(Uint8Array::new(&buf).to_vec())
+        }
+        .boxed_local()
+    }
+
+    fn put<'a>(
+        &'a self,
+        story: &'a StoryKey,
+        key: ChunkKey,
+        mut data: Vec<u8>,
+    ) -> LocalBoxFuture<'a, ()> {
+        async move {
+            let Some(cache) = Self::cache().await else {
+                return;
+            };
+            let Ok(resp) = Response::new_with_opt_u8_array(Some(&mut data)) else {
+                return;
+            };
+            let _ =
+                JsFuture::from(cache.put_with_str(&Self::request_path(story, key), &resp)).await;
+        }
+        .boxed_local()
+    }
+
+    fn prune<'a>(&'a self, story: &'a StoryKey) -> LocalBoxFuture<'a, ()> {
+        async move {
+            let Some(cache) = Self::cache().await else {
+                return;
+            };
+            let Ok(keys) = JsFuture::from(cache.keys()).await else {
+                return;
+            };
+            let prefix = Self::story_prefix(&story.url);
+            let current = format!("{}{:016x}/", prefix, story.fingerprint);
+            for req in Array::from(&keys).iter() {
+                let Ok(req) = req.dyn_into::<Request>() else {
+                    continue;
+                };
+                let url = req.url();
+                let path = url.find(KEY_PREFIX).map(|i| &url[i..]).unwrap_or("");
+                if path.starts_with(&prefix) && !path.starts_with(&current) {
+                    let _ = JsFuture::from(cache.delete_with_str(&url)).await;
+                }
+            }
+        }
+        .boxed_local()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::ChunkType;
+    use futures::executor::block_on;
+
+    fn story(url: &str, fingerprint: u64) -> StoryKey {
+        StoryKey {
+            url: url.to_string(),
+            fingerprint,
+        }
+    }
+
+    fn chunk(id: u8) -> ChunkKey {
+        (ChunkType::Content, [0, 0, id])
+    }
+
+    #[test]
+    fn keys_entries_by_story_revision() {
+        let store = MemoryStore::default();
+        let (old, new) = (story("/a.story", 1), story("/a.story", 2));
+        block_on(store.put(&old, chunk(1), vec![1]));
+        assert_eq!(block_on(store.get(&old, chunk(1))), Some(vec![1]));
+        assert_eq!(block_on(store.get(&new, chunk(1))), None);
+        assert_eq!(block_on(store.get(&old, chunk(2))), None);
+        block_on(store.put(&old, chunk(1), vec![9]));
+        assert_eq!(block_on(store.get(&old, chunk(1))), Some(vec![9]));
+    }
+
+    #[test]
+    fn prune_keeps_the_current_revision_and_other_stories() {
+        let store = MemoryStore::default();
+        let (old, new, other) = (story("/a.story", 1), story("/a.story", 2), story("/b.story", 1));
+        block_on(store.put(&old, chunk(1), vec![1]));
+        block_on(store.put(&new, chunk(1), vec![2]));
+        block_on(store.put(&other, chunk(1), vec![3]));
+        block_on(store.prune(&new));
+        assert_eq!(block_on(store.get(&old, chunk(1))), None);
+        assert_eq!(block_on(store.get(&new, chunk(1))), Some(vec![2]));
+        assert_eq!(block_on(store.get(&other, chunk(1))), Some(vec![3]));
+    }
+}
@@ -0,0 +1,184 @@
+//! # Load Instrumentation
+//!
+//! Counts what node loads cost: requests and bytes fetched, cache hits and
+//! misses per chunk type, and time spent on the network, in zstd and in
+//! parsing. A game keeps running totals (`CyoaGame::stats`) and, while a
+//! node load runs, a trace of that load alone.
+//!
+//! A load's trace collects every event recorded at `Label` or `Visible`
+//! priority while it runs. Prefetches and offline downloads only count
+//! towards the totals. Two loads overlapping in time share their events.
+
+use serde::Serialize;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::decoder::ChunkType;
+use crate::sched::Priority;
+
+/// Cache hits and misses of one chunk type.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct ChunkCounts {
+    pub(crate) chunk_type: &'static str,
+    /// Chunks served from memory or the persistent store.
+    pub(crate) hits: u32,
+    /// Chunks that had to be read from the network or a local file.
+    pub(crate) misses: u32,
+}
+
+/// Counters of one load, or totals since the game started, as passed to
+/// JS.
+#[derive(Clone, Debug, Default, Serialize)]
+pub(crate) struct LoadStats {
+    /// Node loads counted (1 for a single load's trace).
+    pub(crate) loads: u32,
+    /// Wall-clock time of the loads, in milliseconds.
+    pub(crate) elapsed_ms: f64,
+    /// Range requests sent for chunks.
+    pub(crate) requests: u32,
+    /// Bytes those requests returned.
+    pub(crate) bytes: u64,
+    /// Time from sending each request to its last byte, in milliseconds;
+    /// concurrent requests add up.
+    pub(crate) network_ms: f64,
+    /// Time spent decompressing chunk payloads, in milliseconds.
+    pub(crate) zstd_ms: f64,
+    /// Time spent decoding payloads into records, in milliseconds.
+    pub(crate) parse_ms: f64,
+    /// Cache hits and misses by chunk type, in order of first use.
+    pub(crate) chunks: Vec<ChunkCounts>,
+}
+
+impl LoadStats {
+    fn chunk(&mut self, chunk_type: ChunkType, hit: bool) {
+        let name = chunk_type.name();
+        let counts = match self.chunks.iter().position(|c| c.chunk_type == name) {
+            Some(i) => &mut self.chunks[i],
+            None => {
+                self.chunks.push(ChunkCounts {
+                    chunk_type: name,
+                    hits: 0,
+                    misses: 0,
+                });
+                self.chunks.last_mut().expect("just pushed")
+            }
+        };
+        if hit {
+            counts.hits += 1;
+        } else {
+            counts.misses += 1;
+        }
+    }
+}
+
+/// Running totals and the traces of the loads in progress.
+#[derive(Default)]
+pub(crate) struct Stats {
+    totals: RefCell<LoadStats>,
+    active: RefCell<Vec<Rc<RefCell<LoadStats>>>>,
+}
+
+impl Stats {
+    /// Returns the totals since the game started.
+    pub(crate) fn totals(&self) -> LoadStats {
+        self.totals.borrow().clone()
+    }
+
+    /// Starts tracing a node load; see `Trace::finish`.
+    pub(crate) fn begin(self: &Rc<Self>) -> Trace {
+        let stats = Rc::new(RefCell::new(LoadStats::default()));
+        self.active.borrow_mut().push(stats.clone());
+        Trace {
+            owner: self.clone(),
+            stats,
+            started: now(),
+        }
+    }
+
+    /// Counts a chunk served from cache (`hit`) or fetched.
+    pub(crate) fn chunk(&self, priority: Priority, chunk_type: ChunkType, hit: bool) {
+        self.record(priority, |s| s.chunk(chunk_type, hit));
+    }
+
+    /// Counts a request that returned `bytes` after `ms` milliseconds.
+    pub(crate) fn request(&self, priority: Priority, bytes: usize, ms: f64) {
+        self.record(priority, |s| {
+            s.requests += 1;
+            s.bytes += bytes as u64;
+            s.network_ms += ms;
+        });
+    }
+
+    /// Counts `ms` milliseconds of decompression.
+    pub(crate) fn zstd(&self, priority: Priority, ms: f64) {
+        self.record(priority, |s| s.zstd_ms += ms);
+    }
+
+    /// Counts `ms` milliseconds of record decoding.
+    pub(crate) fn parse(&self, priority: Priority, ms: f64) {
+        self.record(priority, |s| s.parse_ms += ms);
+    }
+
+    /// Applies `update` to the totals and, for requests made on behalf of
+    /// the node on screen, to every active trace.
+    fn record(&self, priority: Priority, update: impl Fn(&mut LoadStats)) {
+        update(&mut self.totals.borrow_mut());
+        if priority >= Priority::Label {
+            for trace in self.active.borrow().iter() {
+                update(&mut trace.borrow_mut());
+            }
+        }
+    }
+}
+
+/// The trace of one node load; stops collecting when finished or dropped.
+pub(crate) struct Trace {
+    owner: Rc<Stats>,
+    stats: Rc<RefCell<LoadStats>>,
+    started: f64,
+}
+
+impl Trace {
+    /// Ends the load, adds its duration to the totals and returns its
+    /// counters.
+    pub(crate) fn finish(self) -> LoadStats {
+        let elapsed = now() - self.started;
+        {
+            let mut totals = self.owner.totals.borrow_mut();
+            totals.loads += 1;
+            totals.elapsed_ms += elapsed;
+        }
+        let mut stats = self.stats.borrow().clone();
+        stats.loads = 1;
+        stats.elapsed_ms = elapsed;
+        stats
+    }
+}
+
+impl Drop for Trace {
+    fn drop(&mut self) {
+        self.owner
+            .active
+            .borrow_mut()
+            .retain(|s| !Rc::ptr_eq(s, &self.stats));
+    }
+}
+
+/// Milliseconds from a fixed point, with sub-millisecond precision where
+/// the browser allows.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn now() -> f64 {
+    web_sys::window()
+        .and_then(|w| w.performance())
+        .map(|p| p.now())
+        .unwrap_or_else(js_sys::Date::now)
+}
+
+/// Milliseconds since the Unix epoch; native builds (unit tests) have no
+/// `performance`.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,438 @@
+//! # Transports
+//!
+//! A `Transport` is everything the loader needs from the network: a probe,
+//! a range fetch and a full fetch, each one attempt under an abort signal
+//! where the runtime has them.
+//! Retries, timeouts and the interpretation of status codes stay in the
+//! `net` module, so they apply to every transport alike.
+//!
+//! `BrowserTransport` sends real requests with `fetch`. In unit tests,
+//! `mock::MockTransport` serves one in-memory file and can misbehave on
+//! purpose (ignore `Range`, truncate bodies, add latency, fail every n-th
+//! request, change the file mid-session), which makes flaky networks reproducible
+//! without a server.
+
+use futures::FutureExt;
+use futures::future::LocalBoxFuture;
+use js_sys::{Function, Reflect, Uint8Array};
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    AbortSignal, Headers, ReadableStreamDefaultReader, RequestCredentials, RequestInit,
+    RequestMode, Response, window,
+};
+
+use crate::decoder::GameError;
+use crate::net::{default_mode, fetch_error};
+
+/// What came back for one request: the parts of the response the loader
+/// checks, and its body.
+pub(crate) struct Reply<B = Vec<u8>> {
+    pub(crate) status: u16,
+    /// The `Content-Range` header, if any.
+    pub(crate) content_range: Option<String>,
+    /// The `Content-Length` header, if any.
+    pub(crate) content_length: Option<u64>,
+    /// The `Content-Type` header, if any.
+    pub(crate) content_type: Option<String>,
+    /// The `ETag` header, if any.
+    pub(crate) etag: Option<String>,
+    /// The `Last-Modified` header, if any.
+    pub(crate) last_modified: Option<String>,
+    pub(crate) body: B,
+}
+
+impl<B> Reply<B> {
+    /// Returns the validator to send as `If-Range` for later requests:
+    /// the strong `ETag`, else `Last-Modified`. Weak ETags (`W/"..."`)
+    /// are not allowed in `If-Range`.
+    pub(crate) fn validator(&self) -> Option<&str> {
+        self.etag
+            .as_deref()
+            .filter(|e| !e.starts_with("W/"))
+            .or(self.last_modified.as_deref())
+    }
+}
+
+/// Body of a probe reply. It is only read when the server ignored the
+/// `Range` header, and then holds the whole file.
+pub(crate) enum Body {
+    /// In-memory body; only the test mock serves one.
+    #[cfg_attr(not(test), allow(dead_code))]
+    Bytes(Vec<u8>),
+    Stream(Response),
+}
+
+impl Body {
+    /// Reads the body to the end.
+    ///
+    /// With `on_progress`, a streamed body is read block by block and the
+    /// callback is called as `(loaded, total)` after every block;
+    /// in-memory bodies report once.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Vec<u8>)`: The complete body.
+    /// - `Err(JsValue)`: On stream errors or if the callback throws.
+    pub(crate) async fn read(
+        self,
+        total: u64,
+        on_progress: Option<&Function>,
+    ) -> Result<Vec<u8>, JsValue> {
+        let report = |loaded: usize| -> Result<(), JsValue> {
+            if let Some(cb) = on_progress {
+                cb.call2(
+                    &JsValue::NULL,
+                    &JsValue::from_f64(loaded as f64),
+                    &JsValue::from_f64(total as f64),
+                )?;
+            }
+            Ok(())
+        };
+        let resp = match self {
+            Body::Bytes(bytes) => {
+                report(bytes.len())?;
+                return Ok(bytes);
+            }
+            Body::Stream(resp) => resp,
+        };
+        let (Some(_), Some(body)) = (on_progress, resp.body()) else {
+            let buf = JsFuture::from(resp.array_buffer()?).await?;
+            return Ok(Uint8Array::new(&buf).to_vec());
+        };
+        let reader: ReadableStreamDefaultReader = body.get_reader().dyn_into()?;
+        let mut out = Vec::with_capacity(total as usize);
+        loop {
+            let step = JsFuture::from(reader.read()).await?;
+            if Reflect::get(&step, &JsValue::from_str("done"))?.is_truthy() {
+                break;
+            }
+            let block = Uint8Array::new(&Reflect::get(&step, &JsValue::from_str("value"))?);
+            let start = out.len();
+            out.resize(start + block.length() as usize, 0);
+            block.copy_to(&mut out[start..]);
+            report(out.len())?;
+        }
+        Ok(out)
+    }
+}
+
+/// One way of reaching story files. Each call is a single attempt: it
+/// fails only when no response arrives (`Network`, `Timeout`, `Aborted`);
+/// error statuses come back as replies.
+pub(crate) trait Transport {
+    /// Requests the first byte of `url` (`Range: bytes=0-0`), leaving the
+    /// body unread.
+    fn probe<'a>(
+        &'a self,
+        url: &'a str,
+        signal: Option<AbortSignal>,
+    ) -> LocalBoxFuture<'a, Result<Reply<Body>, GameError>>;
+
+    /// Requests bytes `start..=end` of `url`, or `start` to the end of the
+    /// file without `end`, and reads the body. With `if_range`, the range
+    /// is only honoured while the file still matches that validator.
+    fn fetch_range<'a>(
+        &'a self,
+        url: &'a str,
+        start: u64,
+        end: Option<u64>,
+        if_range: Option<&'a str>,
+        signal: Option<AbortSignal>,
+    ) -> LocalBoxFuture<'a, Result<Reply, GameError>>;
+
+    /// Requests all of `url` and reads the body.
+    fn fetch_full<'a>(
+        &'a self,
+        url: &'a str,
+        signal: Option<AbortSignal>,
+    ) -> LocalBoxFuture<'a, Result<Reply, GameError>>;
+}
+
+/// Transport using the browser's `fetch`, with the request mode,
+/// credentials policy and extra headers from `CyoaGameOptions`.
+#[derive(Default)]
+pub(crate) struct BrowserTransport {
+    /// Request mode; `None` picks one per URL, see `default_mode`.
+    pub(crate) mode: Option<RequestMode>,
+    pub(crate) credentials: Option<RequestCredentials>,
+    pub(crate) headers: Vec<(String, String)>,
+}
+
+impl BrowserTransport {
+    /// Sends a GET for `url` with the configured options, `Range` and
+    /// `If-Range` headers if given, and `signal`.
+    async fn send(
+        &self,
+        url: &str,
+        range: Option<String>,
+        if_range: Option<&str>,
+        signal: Option<&AbortSignal>,
+    ) -> Result<Response, GameError> {
+        let win = window().ok_or(GameError::Other("No window".to_string()))?;
+        let init = RequestInit::new();
+        init.set_method("GET");
+        let page_origin = win.location().origin().ok();
+        init.set_mode(
+            self.mode
+                .unwrap_or_else(|| default_mode(url, page_origin.as_deref())),
+        );
+        if let Some(credentials) = self.credentials {
+            init.set_credentials(credentials);
+        }
+        init.set_signal(signal);
+        let hdrs = Headers::new().map_err(|e| GameError::Other(format!("{:?}", e)))?;
+        for (name, value) in &self.headers {
+            hdrs.set(name, value)
+                .map_err(|e| GameError::Other(format!("Invalid header {}: {:?}", name, e)))?;
+        }
+        if let Some(range) = range {
+            hdrs.set("Range", &range)
+                .map_err(|e| GameError::Other(format!("{:?}", e)))?;
+        }
+        if let Some(validator) = if_range {
+            hdrs.set("If-Range", validator)
+                .map_err(|e| GameError::Other(format!("{:?}", e)))?;
+        }
+        init.set_headers(&hdrs.into());
+        JsFuture::from(win.fetch_with_str_and_init(url, &init))
+            .await
+            .map_err(fetch_error)?
+            .dyn_into::<Response>()
+            .map_err(|_| GameError::Other("Invalid response".to_string()))
+    }
+
+    /// Wraps `resp` and `body` into a reply.
+    fn reply<B>(resp: &Response, body: B) -> Reply<B> {
+        let header = |name: &str| resp.headers().get(name).ok().flatten();
+        Reply {
+            status: resp.status(),
+            content_range: header("content-range"),
+            content_length: header("content-length").and_then(|v| v.parse().ok()),
+            content_type: header("content-type"),
+            etag: header("etag"),
+            last_modified: header("last-modified"),
+            body,
+        }
+    }
+
+    /// Reads the whole body of `resp`.
+    async fn read(resp: &Response) -> Result<Vec<u8>, GameError> {
+        let body = resp.array_buffer().map_err(fetch_error)?;
+        let buf = JsFuture::from(body).await.map_err(fetch_error)?;
+        Ok(Uint8Array::new(&buf).to_vec())
+    }
+}
+
+impl Transport for BrowserTransport {
+    fn probe<'a>(
+        &'a self,
+        url: &'a str,
+        signal: Option<AbortSignal>,
+    ) -> LocalBoxFuture<'a, Result<Reply<Body>, GameError>> {
+        async move {
+            let resp = self
+                .send(url, Some("bytes=0-0".to_string()), None, signal.as_ref())
+                .await?;
+            Ok(Self::reply(&resp, Body::Stream(Clone::clone(&resp))))
+        }
+        .boxed_local()
+    }
+
+    fn fetch_range<'a>(
+        &'a self,
+        url: &'a str,
+        start: u64,
+        end: Option<u64>,
+        if_range: Option<&'a str>,
+        signal: Option<AbortSignal>,
+    ) -> LocalBoxFuture<'a, Result<Reply, GameError>> {
+        async move {
+            let range = match end {
+                Some(e) => format!("bytes={}-{}", start, e),
+                None => format!("bytes={}-", start),
+            };
+            let resp = self
+                .send(url, Some(range), if_range, signal.as_ref())
+                .await?;
+            let body = Self::read(&resp).await?;
+            Ok(Self::reply(&resp, body))
+        }
+        .boxed_local()
+    }
+
+    fn fetch_full<'a>(
+        &'a self,
+        url: &'a str,
+        signal: Option<AbortSignal>,
+    ) -> LocalBoxFuture<'a, Result<Reply, GameError>> {
+        async move {
+            let resp = self.send(url, None, None, signal.as_ref()).await?;
+            let body = Self::read(&resp).await?;
+            Ok(Self::reply(&resp, body))
+        }
+        .boxed_local()
+    }
+}
+
+/// A simulated server for unit tests.
+#[cfg(test)]
+pub(crate) mod mock {
+    use super::*;
+    use crate::net::Runtime;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// How a `MockTransport` misbehaves; the default is a well-behaved
+    /// server.
+    #[derive(Clone, Debug, Default)]
+    pub(crate) struct MockConfig {
+        /// Answer range requests with 200 and the whole file.
+        pub(crate) ignore_range: bool,
+        /// Cut every response body to at most this many bytes.
+        pub(crate) truncate_to: Option<usize>,
+        /// Delay before every response, in milliseconds, waited out
+        /// through the runtime.
+        pub(crate) latency_ms: u32,
+        /// Fail every n-th request (0 never).
+        pub(crate) fail_every: u32,
+        /// Status failing requests answer with; a network error if unset.
+        pub(crate) fail_status: Option<u16>,
+        /// Simulate a redeploy: after this many requests the file is
+        /// served under a new ETag, so range requests made with the old
+        /// one get a 200.
+        pub(crate) change_after: Option<u32>,
+    }
+
+    /// Transport serving one in-memory file, misbehaving as configured.
+    /// Requests for any other URL get a 404.
+    pub(crate) struct MockTransport {
+        url: String,
+        bytes: Vec<u8>,
+        config: MockConfig,
+        /// Where `latency_ms` is waited out.
+        runtime: Rc<dyn Runtime>,
+        /// Requests answered so far, for `fail_every` and `change_after`.
+        pub(crate) requests: Cell<u32>,
+    }
+
+    impl MockTransport {
+        pub(crate) fn new(
+            url: &str,
+            bytes: Vec<u8>,
+            config: MockConfig,
+            runtime: Rc<dyn Runtime>,
+        ) -> Self {
+            MockTransport {
+                url: url.to_string(),
+                bytes,
+                config,
+                runtime,
+                requests: Cell::new(0),
+            }
+        }
+
+        /// Answers a request for `url`: bytes `start..=end` if `range` is
+        /// given and honoured (and `if_range`, if given, matches the
+        /// current ETag), else the whole file.
+        async fn answer(
+            &self,
+            url: &str,
+            range: Option<(u64, Option<u64>)>,
+            if_range: Option<&str>,
+        ) -> Result<Reply, GameError> {
+            let n = self.requests.get() + 1;
+            self.requests.set(n);
+            let version = match self.config.change_after {
+                Some(after) if n > after => 2,
+                _ => 1,
+            };
+            let etag = format!("\"mock-v{}\"", version);
+            let range = range.filter(|_| if_range.is_none_or(|v| v == etag));
+            if self.config.latency_ms > 0 {
+                self.runtime.sleep(self.config.latency_ms).await;
+            }
+            let failing = self.config.fail_every > 0 && n.is_multiple_of(self.config.fail_every);
+            let (status, mut body, content_range) = if failing {
+                match self.config.fail_status {
+                    Some(status) => (status, Vec::new(), None),
+                    None => return Err(GameError::Network("Simulated failure".to_string())),
+                }
+            } else if url != self.url {
+                (404, Vec::new(), None)
+            } else {
+                let total = self.bytes.len() as u64;
+                match range {
+                    Some((start, end)) if !self.config.ignore_range => {
+                        let end = end.unwrap_or(u64::MAX).min(total.saturating_sub(1));
+                        if start >= total || start > end {
+                            (416, Vec::new(), Some(format!("bytes */{}", total)))
+                        } else {
+                            (
+                                206,
+                                self.bytes[start as usize..=end as usize].to_vec(),
+                                Some(format!("bytes {}-{}/{}", start, end, total)),
+                            )
+                        }
+                    }
+                    _ => (200, self.bytes.clone(), None),
+                }
+            };
+            let content_length = Some(body.len() as u64);
+            if let Some(max) = self.config.truncate_to {
+                body.truncate(max);
+            }
+            Ok(Reply {
+                status,
+                content_range,
+                content_length,
+                content_type: None,
+                etag: Some(etag),
+                last_modified: None,
+                body,
+            })
+        }
+    }
+
+    impl Transport for MockTransport {
+        fn probe<'a>(
+            &'a self,
+            url: &'a str,
+            _signal: Option<AbortSignal>,
+        ) -> LocalBoxFuture<'a, Result<Reply<Body>, GameError>> {
+            async move {
+                let r = self.answer(url, Some((0, Some(0))), None).await?;
+                Ok(Reply {
+                    status: r.status,
+                    content_range: r.content_range,
+                    content_length: r.content_length,
+                    content_type: r.content_type,
+                    etag: r.etag,
+                    last_modified: r.last_modified,
+                    body: Body::Bytes(r.body),
+                })
+            }
+            .boxed_local()
+        }
+
+        fn fetch_range<'a>(
+            &'a self,
+            url: &'a str,
+            start: u64,
+            end: Option<u64>,
+            if_range: Option<&'a str>,
+            _signal: Option<AbortSignal>,
+        ) -> LocalBoxFuture<'a, Result<Reply, GameError>> {
+            self.answer(url, Some((start, end)), if_range).boxed_local()
+        }
+
+        fn fetch_full<'a>(
+            &'a self,
+            url: &'a str,
+            _signal: Option<AbortSignal>,
+        ) -> LocalBoxFuture<'a, Result<Reply, GameError>> {
+            self.answer(url, None, None).boxed_local()
+        }
+    }
+}
@@ -8,3 +8,22 @@ pub fn set_panic_hook() {
     #[cfg(feature = "console_error_panic_hook")]
     console_error_panic_hook::set_once();
 }
+
+/// Formats a 3-byte chunk ID as an uppercase hex string, e.g. `"000102"`.
+pub fn hex_id(id: &[u8; 3]) -> String {
+    id.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Parses a 6-digit hex string back into a 3-byte chunk ID.
+///
+/// Returns `None` if the string is not exactly six hex digits.
+pub fn parse_hex_id(s: &str) -> Option<[u8; 3]> {
+    if s.len() != 6 {
+        return None;
+    }
+    let mut id = [0u8; 3];
+    for (i, b) in id.iter_mut().enumerate() {
+        *b = u8::from_str_radix(s.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(id)
+}
@@ -1,3 +1,3 @@
-pub fn run_guard(fid: u32, gb: &Vec<u8>) -> bool{
+pub fn run_guard(_fid: u32, _gb: &[u8]) -> bool {
     true
 }